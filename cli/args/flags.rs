@@ -5,6 +5,7 @@ use clap::builder::FalseyValueParser;
 use clap::value_parser;
 use clap::Arg;
 use clap::ArgAction;
+use clap::ArgGroup;
 use clap::ArgMatches;
 use clap::ColorChoice;
 use clap::Command;
@@ -222,13 +223,123 @@ pub struct InfoFlags {
   pub file: Option<String>,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct InstallFlagsGlobal {
   pub module_url: String,
   pub args: Vec<String>,
   pub name: Option<String>,
   pub root: Option<String>,
   pub force: bool,
+  pub manifest: bool,
+  pub concurrent: Option<NonZeroUsize>,
+  pub aliases: Vec<String>,
+  pub min_deno_version: Option<String>,
+  pub replace_existing_of_same_url: bool,
+  pub pre_run: Option<String>,
+  pub owner_only: bool,
+  pub self_test: bool,
+  pub import_map_overlay: Option<String>,
+  pub list: bool,
+  pub all_roots: bool,
+  pub verify_permissions: bool,
+  pub permission_policy: Option<String>,
+  pub dry_run: bool,
+  pub verbose: bool,
+  pub force_reinstall_deps: Option<String>,
+  pub cmd_crlf: bool,
+  pub only_if_newer: bool,
+  pub env: Vec<String>,
+  pub list_format: InstallListFormat,
+  pub strip_prefix: Option<String>,
+  pub check_updates: bool,
+  pub shebang_env: bool,
+  pub check_name_availability: Vec<String>,
+  pub wrapper_comment: Option<String>,
+  pub base_dir: Option<String>,
+  pub overwrite_companions_only: Option<String>,
+  pub json: bool,
+  pub bat: bool,
+  pub print_path: bool,
+  pub from_lockfile: Option<String>,
+  pub timeout: Option<u64>,
+  pub cwd: Option<String>,
+  pub wrapper_dir_permissions: Option<u32>,
+  pub lock_no_copy: bool,
+  pub export_manifest: bool,
+  pub replace: Option<String>,
+  pub output_format: InstallOutputFormat,
+  pub verify_signature: Option<String>,
+  pub grant_prompt_once: bool,
+  pub auto_import_map: bool,
+  pub which: Option<String>,
+  pub registry_default: Option<String>,
+  pub fail_if_exists: bool,
+  pub verify_run: bool,
+  pub verify_args: Option<Vec<String>>,
+  pub relocatable: bool,
+  pub lowercase_windows_name: bool,
+  pub json_schema: bool,
+  pub reinstall_if_template_outdated: bool,
+  pub stdin_name: bool,
+  pub keep_extension: bool,
+  pub export_archive: Option<String>,
+  pub touch: Option<String>,
+  pub repair: bool,
+  pub name_from_arg: Option<usize>,
+  pub verify_deno_on_path: bool,
+  pub strict: bool,
+  pub merge_args: bool,
+  pub print_env: bool,
+  pub no_pin: bool,
+  pub generic_stems: Vec<String>,
+  pub generic_parents: Vec<String>,
+  pub companions_mode: InstallCompanionsMode,
+  pub desktop_entry: bool,
+  pub desktop_icon: Option<String>,
+  pub verify_name_unique_across_shells: Option<String>,
+  pub overwrite_if_older: Option<u64>,
+  pub strict_name: bool,
+  pub report_file: Option<String>,
+  pub no_shadow: bool,
+  pub allow_shadow: bool,
+  pub template: Option<String>,
+  pub template_vars: Vec<String>,
+  /// The deno flags of an install manifest entry, applied over the flags the
+  /// manifest is installed with.
+  pub deno_flags: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum InstallListFormat {
+  #[default]
+  Plain,
+  Json,
+  Table,
+}
+
+/// Which wrappers `deno install` writes on Windows.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum InstallOutputFormat {
+  /// Both the `.cmd` file and the extensionless shell script.
+  #[default]
+  All,
+  /// Only the shell script, for git bash / MINGW64.
+  Sh,
+  /// Only the `.cmd` file, for cmd and PowerShell.
+  Cmd,
+}
+
+/// What `deno install` does with a config, lockfile or import map copy that
+/// already exists with different content.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum InstallCompanionsMode {
+  /// Replace it with the new content.
+  #[default]
+  Overwrite,
+  /// Keep the existing file.
+  Preserve,
+  /// Fail without writing anything.
+  Error,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -251,10 +362,17 @@ pub struct JupyterFlags {
   pub conn_file: Option<String>,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct UninstallFlagsGlobal {
-  pub name: String,
+  pub name: Option<String>,
   pub root: Option<String>,
+  pub prune: bool,
+  pub dry_run: bool,
+  pub check_remote: bool,
+  pub all: bool,
+  pub force: bool,
+  pub report_file: Option<String>,
+  pub timeout: Option<u64>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -616,11 +734,13 @@ pub struct PermissionFlags {
   pub deny_sys: Option<Vec<String>>,
   pub allow_write: Option<Vec<String>>,
   pub deny_write: Option<Vec<String>>,
+  pub allow_import: Option<Vec<String>>,
   pub no_prompt: bool,
 }
 
 impl PermissionFlags {
   pub fn has_permission(&self) -> bool {
+    // --allow-import is left out, as it isn't enforced
     self.allow_all
       || self.allow_env.is_some()
       || self.deny_env.is_some()
@@ -786,6 +906,17 @@ impl Flags {
       _ => {}
     }
 
+    match &self.permissions.allow_import {
+      Some(import_allowlist) if import_allowlist.is_empty() => {
+        args.push("--allow-import".to_string());
+      }
+      Some(import_allowlist) => {
+        let s = format!("--allow-import={}", import_allowlist.join(","));
+        args.push(s);
+      }
+      _ => {}
+    }
+
     match &self.unsafely_ignore_certificate_errors {
       Some(ic_allowlist) if ic_allowlist.is_empty() => {
         args.push("--unsafely-ignore-certificate-errors".to_string());
@@ -1092,6 +1223,9 @@ static ENV_VARIABLES_HELP: &str = color_print::cstr!(
     <g>DENO_INSTALL_ROOT</>    Set deno install's output directory
                          (defaults to $HOME/.deno/bin)
 
+    <g>DENO_INSTALL_BIN</>     Set the name of the directory deno install writes
+                         executables to within its root (defaults to bin)
+
     <g>DENO_JOBS</>            Number of parallel workers used for the --parallel
                          flag with the test subcommand. Defaults to number
                          of available CPUs.
@@ -2155,6 +2289,35 @@ TypeScript compiler cache: Subdirectory containing TS compiler output.",
       ))
 }
 
+fn directory_mode_validator(mode: &str) -> Result<u32, String> {
+  let digits = mode.strip_prefix("0o").unwrap_or(mode);
+  match u32::from_str_radix(digits, 8) {
+    Ok(mode) if mode <= 0o777 => Ok(mode),
+    _ => Err(format!("Invalid octal permissions: {mode}")),
+  }
+}
+
+/// Parses an age like `30m`, `12h`, `7d` or `2w` into seconds.
+fn age_validator(age: &str) -> Result<u64, String> {
+  let invalid = || {
+    format!("Invalid age: {age}. Expected a number followed by s, m, h, d or w, like 7d")
+  };
+  let unit = age.chars().last().ok_or_else(invalid)?;
+  let seconds_per_unit = match unit {
+    's' => 1,
+    'm' => 60,
+    'h' => 60 * 60,
+    'd' => 24 * 60 * 60,
+    'w' => 7 * 24 * 60 * 60,
+    _ => return Err(invalid()),
+  };
+  age[..age.len() - 1]
+    .parse::<u64>()
+    .ok()
+    .and_then(|count| count.checked_mul(seconds_per_unit))
+    .ok_or_else(invalid)
+}
+
 fn install_args(cmd: Command, deno_future: bool) -> Command {
   let cmd = if deno_future {
     cmd.arg(
@@ -2166,7 +2329,22 @@ fn install_args(cmd: Command, deno_future: bool) -> Command {
   } else {
     cmd.arg(
       Arg::new("cmd")
-        .required(true)
+        .required_unless_present_any([
+          "self-test",
+          "list",
+          "force-reinstall-deps",
+          "check-updates",
+          "check-name-availability",
+          "overwrite-companions-only",
+          "export-manifest",
+          "which",
+          "json-schema",
+          "reinstall-if-template-outdated",
+          "touch",
+          "repair",
+          "print-env",
+          "verify-name-unique-across-shells",
+        ])
         .num_args(1..)
         .value_hint(ValueHint::FilePath),
     )
@@ -2179,6 +2357,66 @@ fn install_args(cmd: Command, deno_future: bool) -> Command {
         .help("Executable file name")
         .required(false),
     )
+    .arg(
+      Arg::new("stdin-name")
+        .long("stdin-name")
+        .help("Ask for the executable name when it can't be inferred and the terminal is interactive")
+        .conflicts_with("name")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("name-from-arg")
+        .long("name-from-arg")
+        .help("Use the path segment of the module URL at this index (starting at 0) as the executable name")
+        .value_name("INDEX")
+        .value_parser(value_parser!(usize))
+        .conflicts_with_all(["name", "manifest"]),
+    )
+    .arg(
+      Arg::new("no-shadow")
+        .long("no-shadow")
+        .help("Fail if the executable name already resolves to an executable elsewhere on the PATH")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("allow-shadow")
+        .long("allow-shadow")
+        .help("Allow shadowing executables on the PATH, even if install.json sets noShadow")
+        .conflicts_with("no-shadow")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("report-file")
+        .long("report-file")
+        .help("Append a JSON line describing the installation and its outcome to this file")
+        .value_hint(ValueHint::FilePath),
+    )
+    .arg(
+      Arg::new("strict-name")
+        .long("strict-name")
+        .help("Only allow executable and alias names made of lowercase ASCII letters, digits and dashes, starting with a letter, for maximal portability")
+        .conflicts_with("keep-extension")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("keep-extension")
+        .long("keep-extension")
+        .help("Keep the extension of the module's file name in the inferred executable name, e.g. server.ts (no effect on Windows)")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("strip-prefix")
+        .long("strip-prefix")
+        .help("Prefix removed from the executable name inferred from the module URL")
+        .value_name("PREFIX")
+        .conflicts_with("name"),
+    )
+    .arg(
+      Arg::new("base-dir")
+        .long("base-dir")
+        .help("Directory relative module, config, lockfile and import map paths are resolved against. Defaults to the current working directory")
+        .value_hint(ValueHint::DirPath),
+    )
     .arg(
       Arg::new("root")
         .long("root")
@@ -2192,6 +2430,77 @@ fn install_args(cmd: Command, deno_future: bool) -> Command {
         .help("Forcefully overwrite existing installation")
         .action(ArgAction::SetTrue),
     )
+    .arg(
+      Arg::new("lowercase-windows-name")
+        .long("lowercase-windows-name")
+        .help("Lowercase the file names of the executables on Windows, so that they match on every platform. Case-sensitive shells like git bash then need the lowercase name")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("relocatable")
+        .long("relocatable")
+        .help("Reference the config, lockfile and import map copies relative to the executable, so that the installation directory can be moved")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("verify-deno-on-path")
+        .long("verify-deno-on-path")
+        .help("Warn when deno can't be found on the PATH the installed executable is going to look it up on")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("strict")
+        .long("strict")
+        .help("Fail instead of warning when --verify-deno-on-path can't find deno")
+        .requires("verify-deno-on-path")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("no-pin")
+        .long("no-pin")
+        .help("Don't pin jsr: modules to the newest matching version at install time, resolving the version on every run instead")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("merge-args")
+        .long("merge-args")
+        .help("When reinstalling, append the script arguments to the ones already installed instead of replacing them")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("verify-run")
+        .long("verify-run")
+        .help("Run the installed executable once and uninstall it again if it fails")
+        .conflicts_with("dry-run")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("verify-args")
+        .long("verify-args")
+        .help("Whitespace separated arguments the executable is run with by --verify-run. Defaults to '--version'")
+        .value_name("ARGS")
+        .allow_hyphen_values(true)
+        .requires("verify-run"),
+    )
+    .arg(
+      Arg::new("fail-if-exists")
+        .long("fail-if-exists")
+        .help("Fail with exit code 17 if the executable is already installed, even when it was installed from the same module")
+        .conflicts_with_all(["force", "replace-existing-of-same-url", "only-if-newer"])
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("only-if-newer")
+        .long("only-if-newer")
+        .help("Skip reinstalling a remote module the server reports as unchanged since it was last installed")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("replace-existing-of-same-url")
+        .long("replace-existing-of-same-url")
+        .help("Overwrite an existing installation without --force if it was installed from the same module")
+        .action(ArgAction::SetTrue),
+    )
     .arg(
       Arg::new("global")
         .long("global")
@@ -2199,9 +2508,426 @@ fn install_args(cmd: Command, deno_future: bool) -> Command {
         .help("Install a package or script as a globally available executable")
         .action(ArgAction::SetTrue),
     )
+    .arg(
+      Arg::new("alias")
+        .long("alias")
+        .help("Additional executable name for the installed script (can be repeated)")
+        .value_name("NAME")
+        .action(ArgAction::Append),
+    )
+    .arg(
+      Arg::new("generic-stem")
+        .long("generic-stem")
+        .help("File name that is too generic to name the executable after, in addition to main, mod, index and cli (can be repeated)")
+        .value_name("STEM")
+        .conflicts_with("name")
+        .action(ArgAction::Append),
+    )
+    .arg(
+      Arg::new("generic-parent")
+        .long("generic-parent")
+        .help("Directory skipped when naming the executable after the directory of a generic file, like src in tool/src/main.ts (can be repeated)")
+        .value_name("DIR")
+        .conflicts_with("name")
+        .action(ArgAction::Append),
+    )
+    .arg(
+      Arg::new("min-deno-version")
+        .long("min-deno-version")
+        .help("Minimum deno version required to run the installed script")
+        .value_name("VERSION"),
+    )
+    .arg(
+      Arg::new("pre-run")
+        .long("pre-run")
+        .help("Shell command the installed executable runs before invoking deno")
+        .value_name("CMD"),
+    )
+    .arg(
+      Arg::new("set-env")
+        .long("set-env")
+        .help("Environment variable the installed executable sets before invoking deno")
+        .value_name("KEY=VALUE")
+        .action(ArgAction::Append),
+    )
+    .arg(
+      Arg::new("template")
+        .long("template")
+        .help("Render the shell script of the executable from this file. It can use the {{NAME}}, {{MODULE_URL}}, {{DENO_ARGS}}, {{PREAMBLE}}, {{ENV}} and {{MARKER}} placeholders and any --template-var. {{PREAMBLE}} holds the lines added by --min-deno-version, --cwd, --pre-run and --grant-prompt-once, {{ENV}} the --set-env assignments; the template must include them when those flags are used. On Windows, it requires --output-format sh")
+        .value_hint(ValueHint::FilePath),
+    )
+    .arg(
+      Arg::new("template-var")
+        .long("template-var")
+        .help("Value substituted for the {{KEY}} placeholders of the --template")
+        .value_name("KEY=VALUE")
+        .requires("template")
+        .action(ArgAction::Append),
+    )
+    .arg(
+      Arg::new("wrapper-comment")
+        .long("wrapper-comment")
+        .help("Comment added to the generated executable, e.g. to note who installed it")
+        .value_name("TEXT"),
+    )
+    .arg(
+      Arg::new("desktop-entry")
+        .long("desktop-entry")
+        .help("Also write a .desktop file launching the executable to the applications directory, so that it shows up in the application menu (Linux only)")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("desktop-icon")
+        .long("desktop-icon")
+        .help("Icon of the desktop entry: a file path or the name of an icon theme icon")
+        .value_name("ICON")
+        .requires("desktop-entry"),
+    )
+    .arg(
+      Arg::new("shebang-env")
+        .long("shebang-env")
+        .help("Write the executable as a script run by deno through a '#!/usr/bin/env -S deno run' shebang when possible (Unix only). The script imports the module, so a shell script is written instead for npm: packages and for modules that check import.meta.main or Deno.mainModule or whose source can't be checked")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("cmd-crlf")
+        .long("cmd-crlf")
+        .help("Write the .cmd executable with CRLF line endings (Windows only)")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("lock-no-copy")
+        .long("lock-no-copy")
+        .help("Make the installed executable use the lockfile passed to --lock in place instead of a copy")
+        .requires("lock")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("wrapper-dir-permissions")
+        .long("wrapper-dir-permissions")
+        .help("Octal permissions of the installation directories created by this install, such as 700 (Unix only)")
+        .value_name("MODE")
+        .value_parser(directory_mode_validator),
+    )
+    .arg(
+      Arg::new("cwd")
+        .long("cwd")
+        .help("Directory the installed executable changes to before running the script")
+        .value_name("DIR")
+        .value_hint(ValueHint::DirPath),
+    )
+    .arg(
+      Arg::new("timeout")
+        .long("timeout")
+        .help("Maximum number of seconds each network request made by the installer may take. Defaults to 30")
+        .value_name("SECONDS")
+        .value_parser(value_parser!(u64)),
+    )
+    .arg(
+      Arg::new("overwrite-if-older")
+        .long("overwrite-if-older")
+        .help("Only overwrite an existing installation when it was installed longer ago than the given age, like 7d, and skip it otherwise")
+        .value_name("AGE")
+        .value_parser(age_validator)
+        .conflicts_with("force"),
+    )
+    .arg(
+      Arg::new("export-archive")
+        .long("export-archive")
+        .help("Write the executables of the manifest to a .tar or .tar.gz archive instead of installing them")
+        .value_name("FILE")
+        .value_hint(ValueHint::FilePath)
+        .requires("manifest"),
+    )
+    .arg(
+      Arg::new("from-lockfile")
+        .long("from-lockfile")
+        .help("Pin the jsr and npm modules of the manifest to the versions in this lockfile, and check remote modules against it")
+        .value_name("FILE")
+        .value_hint(ValueHint::FilePath)
+        .requires("manifest")
+        .conflicts_with("lock"),
+    )
+    .arg(
+      Arg::new("print-path")
+        .long("print-path")
+        .help("Only print the path of the installed executable")
+        .action(ArgAction::SetTrue)
+        .conflicts_with_all(["json", "dry-run"]),
+    )
+    .arg(
+      Arg::new("bat")
+        .long("bat")
+        .help("Also write a .bat copy of the .cmd executable for legacy toolchains (Windows only)")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("owner-only")
+        .long("owner-only")
+        .help("Only allow the current user to read and run the installed executable (Unix only)")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("import-map-overlay")
+        .long("import-map-overlay")
+        .help("JSON merged over the import map before it is copied next to the installed executable")
+        .value_name("JSON")
+        .requires("import-map"),
+    )
+    .arg(
+      Arg::new("registry-default")
+        .long("registry-default")
+        .help("Base URL bare names like 'cowsay' are installed from, e.g. https://deno.land/x/ or npm:. Defaults to $DENO_INSTALL_REGISTRY")
+        .value_name("BASE"),
+    )
+    .arg(
+      Arg::new("reinstall-if-template-outdated")
+        .long("reinstall-if-template-outdated")
+        .help("Regenerate the installed executables whose wrapper script was written by an older version of deno")
+        .conflicts_with_all(["cmd", "manifest", "list", "self-test"])
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("json-schema")
+        .long("json-schema")
+        .help("Print the JSON Schema of the metadata stored next to installed executables")
+        .conflicts_with_all(["cmd", "manifest", "list", "self-test"])
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("print-env")
+        .long("print-env")
+        .help("Print the installation root and bin directory resolved from the environment, and where they came from, without installing")
+        .conflicts_with_all(["cmd", "manifest", "list", "self-test"])
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("repair")
+        .long("repair")
+        .help("Point the installed executables at their config, lockfile and import map copies again after the installation root was moved")
+        .conflicts_with_all(["cmd", "manifest", "list", "self-test"])
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("touch")
+        .long("touch")
+        .help("Record that an installed executable was verified now, without changing anything else")
+        .value_name("NAME")
+        .conflicts_with_all(["cmd", "manifest", "list", "self-test"]),
+    )
+    .arg(
+      Arg::new("which")
+        .long("which")
+        .help("Print the path of an installed executable")
+        .value_name("NAME")
+        .conflicts_with_all(["cmd", "manifest", "list", "self-test"]),
+    )
+    .arg(
+      Arg::new("verify-name-unique-across-shells")
+        .long("verify-name-unique-across-shells")
+        .help("Check that the .cmd, .bat and shell script variants of an installed executable are all present and forward the same arguments, e.g. after an interrupted install")
+        .value_name("NAME")
+        .conflicts_with_all(["cmd", "manifest", "list", "self-test"]),
+    )
+    .arg(
+      Arg::new("auto-import-map")
+        .long("auto-import-map")
+        .help("Copy the import map declared by the deno.json next to a local module (its importMap, or its imports and scopes) next to the installed executable")
+        .conflicts_with("import-map")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("json")
+        .long("json")
+        .help("Print the installed files as JSON, and errors as JSON objects on stderr")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("overwrite-companions-only")
+        .long("overwrite-companions-only")
+        .help("Replace the config, lockfile or import map of an installed executable with the ones provided, leaving the executable untouched")
+        .value_name("NAME")
+        .conflicts_with_all(["cmd", "manifest", "list", "self-test", "force-reinstall-deps"]),
+    )
+    .arg(
+      Arg::new("force-reinstall-deps")
+        .long("force-reinstall-deps")
+        .help("Copy the config, lockfile and import map of an installed executable again from their original locations")
+        .value_name("NAME")
+        .conflicts_with_all(["cmd", "manifest", "list", "self-test"]),
+    )
+    .arg(
+      Arg::new("dry-run")
+        .long("dry-run")
+        .help("Print the deno invocation and the files that would be written without writing them. The module isn't downloaded or type checked, but resolving its name or version may still query the network")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("verbose")
+        .long("verbose")
+        .help("With --dry-run, also print the full contents of every file")
+        .requires("dry-run")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("verify-permissions")
+        .long("verify-permissions")
+        .help("Reject the installation if it grants permissions forbidden by the permission policy (by default --allow-all and --allow-run)")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("permission-policy")
+        .long("permission-policy")
+        .help("JSON file listing the forbidden permissions, e.g. { \"forbid\": [\"allow-run\"] }. Implies --verify-permissions")
+        .value_name("FILE")
+        .value_hint(ValueHint::FilePath),
+    )
+    .arg(
+      Arg::new("check-name-availability")
+        .long("check-name-availability")
+        .help("Report which of the given executable names are already installed")
+        .value_name("NAMES")
+        .value_delimiter(',')
+        .num_args(1..)
+        .conflicts_with_all(["cmd", "manifest", "list", "self-test"]),
+    )
+    .arg(
+      Arg::new("check-updates")
+        .long("check-updates")
+        .help("Report installed executables with a newer version in their registry (deno.land/x, npm or jsr)")
+        .conflicts_with_all(["cmd", "manifest", "list", "self-test"])
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("grant-prompt-once")
+        .long("grant-prompt-once")
+        .help("Remember permissions granted at a prompt so that later runs of the executable don't prompt for them again")
+        .long_help(
+          "Remember permissions granted at a prompt so that later runs of the
+executable don't prompt for them again. When the program exits, the
+permissions granted to all of their kind (answering 'A' at a prompt) are
+recorded in a state file next to the executable and passed as --allow-*
+flags on every later run.
+
+Recorded permissions are granted without asking, including to any newer
+version of a remote module, so this can't be combined with
+--verify-permissions or --permission-policy. The executable is allowed to
+write its state file; only the --allow-* flags it records are read from it.
+The module is run through a generated entrypoint that imports it, so
+Deno.mainModule points to that entrypoint and import.meta.main is false in
+the module, and npm: packages can't be installed this way. Delete the state
+file to be prompted again.",
+        )
+        .conflicts_with_all(["verify-permissions", "permission-policy"])
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("verify-signature")
+        .long("verify-signature")
+        .help("Verify the detached Ed25519 signature at <module url>.sig against the base64 encoded public key in the given file before installing")
+        .value_name("PUBLIC_KEY_FILE")
+        .value_hint(ValueHint::FilePath)
+        .conflicts_with("manifest"),
+    )
+    .arg(
+      Arg::new("output-format")
+        .long("output-format")
+        .help("Which executables to write on Windows: the .cmd file, the shell script for git bash, or both. Defaults to 'all'.")
+        .value_parser(["all", "sh", "cmd"]),
+    )
+    .arg(
+      Arg::new("companions")
+        .long("companions")
+        .help("What to do with config, lockfile and import map copies that already exist with different content, e.g. after editing them. Defaults to 'overwrite'.")
+        .value_name("MODE")
+        .value_parser(["overwrite", "preserve", "error"]),
+    )
+    .arg(
+      Arg::new("replace")
+        .long("replace")
+        .help("Swap an installed executable for the given script, keeping a backup of its previous files")
+        .value_name("NAME")
+        .conflicts_with_all(["name", "manifest", "alias", "dry-run"]),
+    )
+    .arg(
+      Arg::new("export-manifest")
+        .long("export-manifest")
+        .help("Print a manifest of the installed executables that can be installed elsewhere with --manifest")
+        .conflicts_with_all(["cmd", "manifest", "self-test", "list"])
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("list")
+        .long("list")
+        .help("List the installed executables")
+        .conflicts_with_all(["cmd", "manifest", "self-test"])
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("format")
+        .long("format")
+        .help("Output format of --list. Defaults to 'plain'.")
+        .value_parser(["plain", "json", "table"])
+        .requires("list"),
+    )
+    .arg(
+      Arg::new("all-roots")
+        .long("all-roots")
+        .help("List the executables of every known installation root")
+        .requires("list")
+        .conflicts_with("root")
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("self-test")
+        .long("self-test")
+        .help("Install, run and uninstall a small echo script to check that installing works on this machine")
+        .conflicts_with_all(["cmd", "manifest"])
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("manifest")
+        .long("manifest")
+        .help("Treat the argument as a JSON manifest listing the scripts to install. The other install flags apply to every entry, except where an entry sets its own preRun, minDenoVersion, env, cwd or denoFlags")
+        .conflicts_with_all(["name", "alias"])
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("concurrent")
+        .long("concurrent")
+        .help("Maximum number of manifest entries to install in parallel")
+        .value_name("N")
+        .value_parser(value_parser!(NonZeroUsize))
+        .requires("manifest"),
+    )
     .arg(env_file_arg())
+    .group(
+      ArgGroup::new("install-mode")
+        .args(INSTALL_MODE_ARGS)
+        .multiple(false),
+    )
 }
 
+/// The flags of `deno install` that do something other than installing a
+/// script. Only one of them can be used at a time.
+const INSTALL_MODE_ARGS: [&str; 15] = [
+  "force-reinstall-deps",
+  "overwrite-companions-only",
+  "check-name-availability",
+  "check-updates",
+  "repair",
+  "reinstall-if-template-outdated",
+  "list",
+  "print-env",
+  "json-schema",
+  "touch",
+  "which",
+  "verify-name-unique-across-shells",
+  "export-manifest",
+  "self-test",
+  "manifest",
+];
+
 fn future_install_subcommand() -> Command {
   Command::new("install")
     .visible_alias("i")
@@ -2236,18 +2962,31 @@ The executable name is inferred by default:
   - If the file stem is something generic like 'main', 'mod', 'index' or 'cli',
     and the path has no parent, take the file name of the parent path. Otherwise
     settle with the generic name.
-  - If the resulting name has an '@...' suffix, strip it.
+  - If the resulting name has an '@<version>' suffix, strip it.
 
 To change the installation root, use --root:
 
   deno install -g --allow-net --allow-read --root /usr/local jsr:@std/http/file-server
 
+To install several scripts at once, list them in a JSON manifest and use --manifest:
+
+  deno install -g --manifest --concurrent 4 tools.json
+
 The installation root is determined, in order of precedence:
   - --root option
   - DENO_INSTALL_ROOT environment variable
+  - $XDG_DATA_HOME/deno (Linux only, unless $HOME/.deno exists)
   - $HOME/.deno
 
-These must be added to the path manually if required.")
+Executables are installed to its 'bin' directory, or to the directory named by
+the DENO_INSTALL_BIN environment variable, e.g. DENO_INSTALL_BIN=scripts.
+
+These must be added to the path manually if required.
+
+Defaults for the --root, --output-format and --wrapper-dir-permissions options
+can be set in an 'install.json' file in the installation root determined above,
+eg. {\"root\": \"/usr/local\", \"shell\": \"sh\", \"mode\": \"700\"}.
+Options passed on the command line take precedence over these defaults.")
     .defer(|cmd| {
       let cmd = runtime_args(cmd, true, true).arg(check_arg(true)).arg(allow_scripts_arg());
       install_args(cmd, true)
@@ -2273,20 +3012,33 @@ The executable name is inferred by default:
   - If the file stem is something generic like 'main', 'mod', 'index' or 'cli',
     and the path has no parent, take the file name of the parent path. Otherwise
     settle with the generic name.
-  - If the resulting name has an '@...' suffix, strip it.
+  - If the resulting name has an '@<version>' suffix, strip it.
 
 To change the installation root, use --root:
 
   deno install -g --allow-net --allow-read --root /usr/local jsr:@std/http/file-server
 
+To install several scripts at once, list them in a JSON manifest and use --manifest:
+
+  deno install -g --manifest --concurrent 4 tools.json
+
 The installation root is determined, in order of precedence:
   - --root option
   - DENO_INSTALL_ROOT environment variable
+  - $XDG_DATA_HOME/deno (Linux only, unless $HOME/.deno exists)
   - $HOME/.deno
 
-These must be added to the path manually if required.")
+Executables are installed to its 'bin' directory, or to the directory named by
+the DENO_INSTALL_BIN environment variable, e.g. DENO_INSTALL_BIN=scripts.
+
+These must be added to the path manually if required.
+
+Defaults for the --root, --output-format and --wrapper-dir-permissions options
+can be set in an 'install.json' file in the installation root determined above,
+eg. {\"root\": \"/usr/local\", \"shell\": \"sh\", \"mode\": \"700\"}.
+Options passed on the command line take precedence over these defaults.")
     .defer(|cmd| {
-      let cmd = runtime_args(cmd, true, true).arg(check_arg(true));
+      let cmd = runtime_args(cmd, true, true).arg(check_arg(true)).arg(allow_scripts_arg());
       install_args(cmd, false)
     })
 }
@@ -2330,15 +3082,75 @@ To change the installation root, use --root:
 
   deno uninstall --root /usr/local serve
 
+To remove every installation whose local module no longer exists, use --prune:
+
+  deno uninstall --prune
+  deno uninstall --prune --dry-run
+
+To remove every installation, use --all together with --force:
+
+  deno uninstall --all --force
+
 The installation root is determined, in order of precedence:
   - --root option
   - DENO_INSTALL_ROOT environment variable
+  - $XDG_DATA_HOME/deno (Linux only, unless $HOME/.deno exists)
   - $HOME/.deno")
-    .defer(|cmd| cmd.arg(Arg::new("name").required(true))
+    .defer(|cmd| cmd.arg(Arg::new("name").required_unless_present_any(["prune", "all"]))
       .arg(
-        Arg::new("root")
-          .long("root")
-          .help("Installation root")
+        Arg::new("prune")
+          .long("prune")
+          .help("Remove installations whose local module no longer exists")
+          .conflicts_with("name")
+          .action(ArgAction::SetTrue)
+      )
+      .arg(
+        Arg::new("all")
+          .long("all")
+          .help("Remove every installation in the installation root")
+          .conflicts_with_all(["name", "prune"])
+          .requires("force")
+          .action(ArgAction::SetTrue)
+      )
+      .arg(
+        Arg::new("force")
+          .long("force")
+          .short('f')
+          .help("Confirm the removal of every installation with --all")
+          .action(ArgAction::SetTrue)
+      )
+      .arg(
+        Arg::new("dry-run")
+          .long("dry-run")
+          .help("Print the installations that would be pruned without removing them")
+          .requires("prune")
+          .action(ArgAction::SetTrue)
+      )
+      .arg(
+        Arg::new("check-remote")
+          .long("check-remote")
+          .help("Also prune remote installations whose module can no longer be fetched")
+          .requires("prune")
+          .action(ArgAction::SetTrue)
+      )
+      .arg(
+        Arg::new("timeout")
+          .long("timeout")
+          .help("Maximum number of seconds each request made by --check-remote may take. Defaults to 30")
+          .value_name("SECONDS")
+          .requires("check-remote")
+          .value_parser(value_parser!(u64))
+      )
+      .arg(
+        Arg::new("report-file")
+          .long("report-file")
+          .help("Append a JSON line describing each removal and its outcome to this file")
+          .value_hint(ValueHint::FilePath)
+      )
+      .arg(
+        Arg::new("root")
+          .long("root")
+          .help("Installation root")
           .value_hint(ValueHint::DirPath)
       )
       .arg(
@@ -2520,6 +3332,7 @@ fn run_subcommand() -> Command {
     )
     .arg(env_file_arg())
     .arg(no_code_cache_arg())
+    .arg(allow_scripts_arg())
     .about("Run a JavaScript or TypeScript program")
     .long_about(
       "Run a JavaScript or TypeScript program
@@ -3014,6 +3827,15 @@ static ALLOW_NET_HELP: &str = concat!(
   "  --allow-net=\"localhost:8080,deno.land\""
 );
 
+// Accepted so that wrappers written for newer runtimes keep parsing; this
+// version does not gate remote imports, so the list is not enforced.
+static ALLOW_IMPORT_HELP: &str = concat!(
+  "Allow importing remote modules from these hosts. Accepted for compatibility; this version does not restrict imports\n",
+  "Examples:\n",
+  "  --allow-import\n",
+  "  --allow-import=\"deno.land,jsr.io:443\""
+);
+
 static DENY_NET_HELP: &str = concat!(
   "Deny network access. Optionally specify denied IP addresses and host names, with ports as necessary.\n",
   "Docs: https://deno.land/manual@v",
@@ -3181,6 +4003,16 @@ fn permission_args(app: Command) -> Command {
         .help(ALLOW_NET_HELP)
         .value_parser(flags_net::validator),
     )
+    .arg(
+      Arg::new("allow-import")
+        .long("allow-import")
+        .num_args(0..)
+        .use_value_delimiter(true)
+        .require_equals(true)
+        .value_name("IP_OR_HOSTNAME")
+        .help(ALLOW_IMPORT_HELP)
+        .value_parser(flags_net::validator),
+    )
     .arg(
       Arg::new("deny-net")
         .long("deny-net")
@@ -4138,16 +4970,131 @@ fn info_parse(flags: &mut Flags, matches: &mut ArgMatches) {
 
 fn install_parse(flags: &mut Flags, matches: &mut ArgMatches) {
   runtime_args_parse(flags, matches, true, true);
+  allow_scripts_arg_parse(flags, matches);
 
   let global = matches.get_flag("global");
   if global || !*DENO_FUTURE {
     let root = matches.remove_one::<String>("root");
     let force = matches.get_flag("force");
     let name = matches.remove_one::<String>("name");
+    let strip_prefix = matches.remove_one::<String>("strip-prefix");
+    let check_updates = matches.get_flag("check-updates");
+    let check_name_availability = matches
+      .remove_many::<String>("check-name-availability")
+      .map(|names| names.collect())
+      .unwrap_or_default();
+    let manifest = matches.get_flag("manifest");
+    let concurrent = matches.remove_one::<NonZeroUsize>("concurrent");
+    let aliases = matches
+      .remove_many::<String>("alias")
+      .map(|aliases| aliases.collect())
+      .unwrap_or_default();
+    let min_deno_version = matches.remove_one::<String>("min-deno-version");
+    let replace_existing_of_same_url =
+      matches.get_flag("replace-existing-of-same-url");
+    let pre_run = matches.remove_one::<String>("pre-run");
+    let owner_only = matches.get_flag("owner-only");
+    let cmd_crlf = matches.get_flag("cmd-crlf");
+    let shebang_env = matches.get_flag("shebang-env");
+    let wrapper_comment = matches.remove_one::<String>("wrapper-comment");
+    let base_dir = matches.remove_one::<String>("base-dir");
+    let overwrite_companions_only =
+      matches.remove_one::<String>("overwrite-companions-only");
+    let only_if_newer = matches.get_flag("only-if-newer");
+    let json = matches.get_flag("json");
+    let bat = matches.get_flag("bat");
+    let print_path = matches.get_flag("print-path");
+    let from_lockfile = matches.remove_one::<String>("from-lockfile");
+    let timeout = matches.remove_one::<u64>("timeout");
+    let cwd = matches.remove_one::<String>("cwd");
+    let wrapper_dir_permissions =
+      matches.remove_one::<u32>("wrapper-dir-permissions");
+    let lock_no_copy = matches.get_flag("lock-no-copy");
+    let export_manifest = matches.get_flag("export-manifest");
+    let replace = matches.remove_one::<String>("replace");
+    let output_format =
+      match matches.remove_one::<String>("output-format").as_deref() {
+        Some("sh") => InstallOutputFormat::Sh,
+        Some("cmd") => InstallOutputFormat::Cmd,
+        _ => InstallOutputFormat::All,
+      };
+    let companions_mode =
+      match matches.remove_one::<String>("companions").as_deref() {
+        Some("preserve") => InstallCompanionsMode::Preserve,
+        Some("error") => InstallCompanionsMode::Error,
+        _ => InstallCompanionsMode::Overwrite,
+      };
+    let desktop_entry = matches.get_flag("desktop-entry");
+    let desktop_icon = matches.remove_one::<String>("desktop-icon");
+    let verify_signature = matches.remove_one::<String>("verify-signature");
+    let grant_prompt_once = matches.get_flag("grant-prompt-once");
+    let auto_import_map = matches.get_flag("auto-import-map");
+    let which = matches.remove_one::<String>("which");
+    let verify_name_unique_across_shells =
+      matches.remove_one::<String>("verify-name-unique-across-shells");
+    let overwrite_if_older = matches.remove_one::<u64>("overwrite-if-older");
+    let strict_name = matches.get_flag("strict-name");
+    let report_file = matches.remove_one::<String>("report-file");
+    let no_shadow = matches.get_flag("no-shadow");
+    let allow_shadow = matches.get_flag("allow-shadow");
+    let template = matches.remove_one::<String>("template");
+    let template_vars = matches
+      .remove_many::<String>("template-var")
+      .map(|template_vars| template_vars.collect())
+      .unwrap_or_default();
+    let registry_default = matches.remove_one::<String>("registry-default");
+    let fail_if_exists = matches.get_flag("fail-if-exists");
+    let verify_run = matches.get_flag("verify-run");
+    let relocatable = matches.get_flag("relocatable");
+    let lowercase_windows_name = matches.get_flag("lowercase-windows-name");
+    let json_schema = matches.get_flag("json-schema");
+    let reinstall_if_template_outdated =
+      matches.get_flag("reinstall-if-template-outdated");
+    let stdin_name = matches.get_flag("stdin-name");
+    let keep_extension = matches.get_flag("keep-extension");
+    let export_archive = matches.remove_one::<String>("export-archive");
+    let touch = matches.remove_one::<String>("touch");
+    let repair = matches.get_flag("repair");
+    let name_from_arg = matches.remove_one::<usize>("name-from-arg");
+    let verify_deno_on_path = matches.get_flag("verify-deno-on-path");
+    let strict = matches.get_flag("strict");
+    let merge_args = matches.get_flag("merge-args");
+    let print_env = matches.get_flag("print-env");
+    let no_pin = matches.get_flag("no-pin");
+    let generic_stems = matches
+      .remove_many::<String>("generic-stem")
+      .map(|stems| stems.collect())
+      .unwrap_or_default();
+    let generic_parents = matches
+      .remove_many::<String>("generic-parent")
+      .map(|parents| parents.collect())
+      .unwrap_or_default();
+    let verify_args = matches
+      .remove_one::<String>("verify-args")
+      .map(|args| args.split_whitespace().map(String::from).collect());
+    let env = matches
+      .remove_many::<String>("set-env")
+      .map(|env| env.collect())
+      .unwrap_or_default();
+    let list = matches.get_flag("list");
+    let all_roots = matches.get_flag("all-roots");
+    let verify_permissions = matches.get_flag("verify-permissions");
+    let permission_policy = matches.remove_one::<String>("permission-policy");
+    let dry_run = matches.get_flag("dry-run");
+    let verbose = matches.get_flag("verbose");
+    let force_reinstall_deps =
+      matches.remove_one::<String>("force-reinstall-deps");
+    let list_format = match matches.remove_one::<String>("format").as_deref() {
+      Some("json") => InstallListFormat::Json,
+      Some("table") => InstallListFormat::Table,
+      _ => InstallListFormat::Plain,
+    };
+    let self_test = matches.get_flag("self-test");
+    let import_map_overlay = matches.remove_one::<String>("import-map-overlay");
     let mut cmd_values =
       matches.remove_many::<String>("cmd").unwrap_or_default();
 
-    let module_url = cmd_values.next().unwrap();
+    let module_url = cmd_values.next().unwrap_or_default();
     let args = cmd_values.collect();
 
     flags.subcommand = DenoSubcommand::Install(InstallFlags {
@@ -4159,13 +5106,87 @@ fn install_parse(flags: &mut Flags, matches: &mut ArgMatches) {
         args,
         root,
         force,
+        manifest,
+        concurrent,
+        aliases,
+        min_deno_version,
+        replace_existing_of_same_url,
+        pre_run,
+        owner_only,
+        self_test,
+        import_map_overlay,
+        list,
+        all_roots,
+        verify_permissions,
+        permission_policy,
+        dry_run,
+        verbose,
+        force_reinstall_deps,
+        cmd_crlf,
+        only_if_newer,
+        env,
+        list_format,
+        strip_prefix,
+        check_updates,
+        shebang_env,
+        check_name_availability,
+        wrapper_comment,
+        base_dir,
+        overwrite_companions_only,
+        json,
+        bat,
+        print_path,
+        from_lockfile,
+        timeout,
+        cwd,
+        wrapper_dir_permissions,
+        lock_no_copy,
+        export_manifest,
+        replace,
+        output_format,
+        verify_signature,
+        grant_prompt_once,
+        auto_import_map,
+        which,
+        registry_default,
+        fail_if_exists,
+        verify_run,
+        verify_args,
+        relocatable,
+        lowercase_windows_name,
+        json_schema,
+        reinstall_if_template_outdated,
+        stdin_name,
+        keep_extension,
+        export_archive,
+        touch,
+        repair,
+        name_from_arg,
+        verify_deno_on_path,
+        strict,
+        merge_args,
+        print_env,
+        no_pin,
+        generic_stems,
+        generic_parents,
+        companions_mode,
+        desktop_entry,
+        desktop_icon,
+        verify_name_unique_across_shells,
+        overwrite_if_older,
+        strict_name,
+        report_file,
+        no_shadow,
+        allow_shadow,
+        template,
+        template_vars,
+        deno_flags: vec![],
       }),
     });
   } else {
     let local_flags = matches
       .remove_many("cmd")
       .map(|packages| add_parse_inner(matches, Some(packages)));
-    allow_scripts_arg_parse(flags, matches);
     flags.subcommand = DenoSubcommand::Install(InstallFlags {
       global,
       kind: InstallKind::Local(local_flags),
@@ -4188,12 +5209,29 @@ fn jupyter_parse(flags: &mut Flags, matches: &mut ArgMatches) {
 fn uninstall_parse(flags: &mut Flags, matches: &mut ArgMatches) {
   let root = matches.remove_one::<String>("root");
   let global = matches.get_flag("global");
-  let name = matches.remove_one::<String>("name").unwrap();
+  let name = matches.remove_one::<String>("name");
+  let prune = matches.get_flag("prune");
+  let dry_run = matches.get_flag("dry-run");
+  let check_remote = matches.get_flag("check-remote");
+  let all = matches.get_flag("all");
+  let force = matches.get_flag("force");
+  let report_file = matches.remove_one::<String>("report-file");
+  let timeout = matches.remove_one::<u64>("timeout");
   flags.subcommand = DenoSubcommand::Uninstall(UninstallFlags {
     // TODO(bartlomieju): remove once `deno uninstall` supports both local and
     // global installs
     global,
-    kind: UninstallKind::Global(UninstallFlagsGlobal { name, root }),
+    kind: UninstallKind::Global(UninstallFlagsGlobal {
+      name,
+      root,
+      prune,
+      dry_run,
+      check_remote,
+      all,
+      force,
+      report_file,
+      timeout,
+    }),
   });
 }
 
@@ -4331,6 +5369,7 @@ fn run_parse(
   flags.argv.extend(script_arg);
 
   ext_arg_parse(flags, matches);
+  allow_scripts_arg_parse(flags, matches);
   temp_netlify_deno_1_hack(flags, &script);
 
   flags.subcommand = DenoSubcommand::Run(RunFlags {
@@ -4659,6 +5698,18 @@ fn permission_args_parse(flags: &mut Flags, matches: &mut ArgMatches) {
     flags.permissions.allow_net = Some(net_allowlist);
   }
 
+  if let Some(import_wl) = matches.remove_many::<String>("allow-import") {
+    let import_allowlist = flags_net::parse(import_wl.collect()).unwrap();
+    #[allow(clippy::print_stderr)]
+    {
+      eprintln!(
+        "{} --allow-import is accepted for compatibility, but this version of Deno doesn't restrict remote imports, so it isn't enforced.",
+        crate::colors::yellow("Warning"),
+      );
+    }
+    flags.permissions.allow_import = Some(import_allowlist);
+  }
+
   if let Some(net_wl) = matches.remove_many::<String>("deny-net") {
     let net_denylist = flags_net::parse(net_wl.collect()).unwrap();
     flags.permissions.deny_net = Some(net_denylist);
@@ -5598,6 +6649,9 @@ mod tests {
 
     let r = flags_from_vec(svec!["deno", "run", "x.ts"]);
     assert_eq!(r.unwrap().has_permission(), false);
+
+    let r = flags_from_vec(svec!["deno", "run", "--allow-import", "x.ts"]);
+    assert_eq!(r.unwrap().has_permission(), false);
   }
 
   #[test]
@@ -6933,6 +7987,30 @@ mod tests {
     );
   }
 
+  #[test]
+  fn allow_import_allowlist() {
+    let r = flags_from_vec(svec![
+      "deno",
+      "run",
+      "--allow-import=deno.land,jsr.io:443",
+      "script.ts"
+    ]);
+    assert_eq!(
+      r.unwrap(),
+      Flags {
+        subcommand: DenoSubcommand::Run(RunFlags::new_default(
+          "script.ts".to_string(),
+        )),
+        permissions: PermissionFlags {
+          allow_import: Some(svec!["deno.land", "jsr.io:443"]),
+          ..Default::default()
+        },
+        code_cache_enabled: true,
+        ..Flags::default()
+      }
+    );
+  }
+
   #[test]
   fn deny_net_denylist() {
     let r =
@@ -7687,6 +8765,7 @@ mod tests {
             args: vec![],
             root: None,
             force: false,
+            ..Default::default()
           }),
           global: false,
         }),
@@ -7710,9 +8789,51 @@ mod tests {
             args: vec![],
             root: None,
             force: false,
+            ..Default::default()
+          }),
+          global: true,
+        }),
+        ..Flags::default()
+      }
+    );
+  }
+
+  #[test]
+  fn install_with_allow_scripts() {
+    let r = flags_from_vec(svec![
+      "deno",
+      "install",
+      "-g",
+      "--node-modules-dir",
+      "--allow-scripts=npm:cowsay",
+      "npm:cowsay"
+    ]);
+    assert_eq!(
+      r.unwrap(),
+      Flags {
+        subcommand: DenoSubcommand::Install(InstallFlags {
+          kind: InstallKind::Global(InstallFlagsGlobal {
+            module_url: "npm:cowsay".to_string(),
+            ..Default::default()
           }),
           global: true,
         }),
+        node_modules_dir: Some(true),
+        allow_scripts: PackagesAllowedScripts::Some(svec!["npm:cowsay"]),
+        ..Flags::default()
+      }
+    );
+
+    let r =
+      flags_from_vec(svec!["deno", "run", "--allow-scripts", "npm:cowsay"]);
+    assert_eq!(
+      r.unwrap(),
+      Flags {
+        subcommand: DenoSubcommand::Run(RunFlags::new_default(
+          "npm:cowsay".to_string(),
+        )),
+        allow_scripts: PackagesAllowedScripts::All,
+        code_cache_enabled: true,
         ..Flags::default()
       }
     );
@@ -7732,6 +8853,7 @@ mod tests {
             args: svec!["foo", "bar"],
             root: Some("/foo".to_string()),
             force: true,
+            ..Default::default()
           }),
           global: false,
         }),
@@ -7759,6 +8881,305 @@ mod tests {
     );
   }
 
+  #[test]
+  fn install_alias() {
+    let r = flags_from_vec(svec![
+      "deno",
+      "install",
+      "-g",
+      "--alias",
+      "g",
+      "--alias",
+      "gh",
+      "jsr:@std/http/file-server"
+    ]);
+    assert_eq!(
+      r.unwrap(),
+      Flags {
+        subcommand: DenoSubcommand::Install(InstallFlags {
+          kind: InstallKind::Global(InstallFlagsGlobal {
+            module_url: "jsr:@std/http/file-server".to_string(),
+            aliases: svec!["g", "gh"],
+            ..Default::default()
+          }),
+          global: true,
+        }),
+        ..Flags::default()
+      }
+    );
+  }
+
+  #[test]
+  fn install_list() {
+    let r = flags_from_vec(svec!["deno", "install", "--list", "--all-roots"]);
+    assert_eq!(
+      r.unwrap(),
+      Flags {
+        subcommand: DenoSubcommand::Install(InstallFlags {
+          kind: InstallKind::Global(InstallFlagsGlobal {
+            list: true,
+            all_roots: true,
+            ..Default::default()
+          }),
+          global: false,
+        }),
+        ..Flags::default()
+      }
+    );
+
+    let r =
+      flags_from_vec(svec!["deno", "install", "--list", "--format", "table"]);
+    assert_eq!(
+      r.unwrap(),
+      Flags {
+        subcommand: DenoSubcommand::Install(InstallFlags {
+          kind: InstallKind::Global(InstallFlagsGlobal {
+            list: true,
+            list_format: InstallListFormat::Table,
+            ..Default::default()
+          }),
+          global: false,
+        }),
+        ..Flags::default()
+      }
+    );
+
+    let r =
+      flags_from_vec(svec!["deno", "install", "--list", "--format", "yaml"]);
+    assert!(r.is_err());
+
+    let r = flags_from_vec(svec!["deno", "install", "--all-roots"]);
+    assert!(r.is_err());
+  }
+
+  #[test]
+  fn install_grant_prompt_once_conflicts() {
+    for flag in ["--verify-permissions", "--permission-policy=policy.json"] {
+      let r = flags_from_vec(svec![
+        "deno",
+        "install",
+        "--grant-prompt-once",
+        flag,
+        "https://example.com/script.ts"
+      ]);
+      assert!(r.is_err(), "{flag}");
+    }
+  }
+
+  #[test]
+  fn install_wrapper_dir_permissions() {
+    for mode in ["700", "0o700"] {
+      let r = flags_from_vec(svec![
+        "deno",
+        "install",
+        "--wrapper-dir-permissions",
+        mode,
+        "https://example.com/script.ts"
+      ]);
+      assert_eq!(
+        r.unwrap(),
+        Flags {
+          subcommand: DenoSubcommand::Install(InstallFlags {
+            kind: InstallKind::Global(InstallFlagsGlobal {
+              module_url: "https://example.com/script.ts".to_string(),
+              wrapper_dir_permissions: Some(0o700),
+              ..Default::default()
+            }),
+            global: false,
+          }),
+          ..Flags::default()
+        }
+      );
+    }
+
+    for mode in ["800", "1777", "rwx"] {
+      let r = flags_from_vec(svec![
+        "deno",
+        "install",
+        "--wrapper-dir-permissions",
+        mode,
+        "https://example.com/script.ts"
+      ]);
+      assert!(r.is_err(), "{mode}");
+    }
+  }
+
+  #[test]
+  fn install_overwrite_if_older() {
+    for (age, seconds) in [("45s", 45), ("30m", 1800), ("7d", 604800)] {
+      let r = flags_from_vec(svec![
+        "deno",
+        "install",
+        "--overwrite-if-older",
+        age,
+        "https://example.com/script.ts"
+      ]);
+      assert_eq!(
+        r.unwrap(),
+        Flags {
+          subcommand: DenoSubcommand::Install(InstallFlags {
+            kind: InstallKind::Global(InstallFlagsGlobal {
+              module_url: "https://example.com/script.ts".to_string(),
+              overwrite_if_older: Some(seconds),
+              ..Default::default()
+            }),
+            global: false,
+          }),
+          ..Flags::default()
+        }
+      );
+    }
+
+    for age in ["7", "d", "7y", "-1d", ""] {
+      let r = flags_from_vec(svec![
+        "deno",
+        "install",
+        "--overwrite-if-older",
+        age,
+        "https://example.com/script.ts"
+      ]);
+      assert!(r.is_err(), "{age}");
+    }
+  }
+
+  #[test]
+  fn install_allow_import() {
+    let r = flags_from_vec(svec![
+      "deno",
+      "install",
+      "--allow-import=deno.land,jsr.io:443",
+      "https://example.com/script.ts"
+    ]);
+    assert_eq!(
+      r.unwrap(),
+      Flags {
+        subcommand: DenoSubcommand::Install(InstallFlags {
+          kind: InstallKind::Global(InstallFlagsGlobal {
+            module_url: "https://example.com/script.ts".to_string(),
+            ..Default::default()
+          }),
+          global: false,
+        }),
+        permissions: PermissionFlags {
+          allow_import: Some(svec!["deno.land", "jsr.io:443"]),
+          ..Default::default()
+        },
+        ..Flags::default()
+      }
+    );
+  }
+
+  #[test]
+  fn install_check_name_availability() {
+    let r = flags_from_vec(svec![
+      "deno",
+      "install",
+      "--check-name-availability",
+      "a,b",
+      "c"
+    ]);
+    assert_eq!(
+      r.unwrap(),
+      Flags {
+        subcommand: DenoSubcommand::Install(InstallFlags {
+          kind: InstallKind::Global(InstallFlagsGlobal {
+            check_name_availability: svec!["a", "b", "c"],
+            ..Default::default()
+          }),
+          global: false,
+        }),
+        ..Flags::default()
+      }
+    );
+  }
+
+  #[test]
+  fn install_self_test() {
+    let r = flags_from_vec(svec!["deno", "install", "--self-test"]);
+    assert_eq!(
+      r.unwrap(),
+      Flags {
+        subcommand: DenoSubcommand::Install(InstallFlags {
+          kind: InstallKind::Global(InstallFlagsGlobal {
+            self_test: true,
+            ..Default::default()
+          }),
+          global: false,
+        }),
+        ..Flags::default()
+      }
+    );
+
+    let r = flags_from_vec(svec![
+      "deno",
+      "install",
+      "--self-test",
+      "https://example.com/script.ts"
+    ]);
+    assert!(r.is_err());
+  }
+
+  #[test]
+  fn install_modes_conflict() {
+    let r = flags_from_vec(svec!["deno", "install", "--repair", "--list"]);
+    assert_eq!(
+      r.unwrap_err().kind(),
+      clap::error::ErrorKind::ArgumentConflict
+    );
+    let r = flags_from_vec(svec![
+      "deno",
+      "install",
+      "--check-updates",
+      "--export-manifest"
+    ]);
+    assert_eq!(
+      r.unwrap_err().kind(),
+      clap::error::ErrorKind::ArgumentConflict
+    );
+  }
+
+  #[test]
+  fn install_manifest() {
+    let r = flags_from_vec(svec![
+      "deno",
+      "install",
+      "-g",
+      "--manifest",
+      "--concurrent",
+      "4",
+      "tools.json"
+    ]);
+    assert_eq!(
+      r.unwrap(),
+      Flags {
+        subcommand: DenoSubcommand::Install(InstallFlags {
+          kind: InstallKind::Global(InstallFlagsGlobal {
+            name: None,
+            module_url: "tools.json".to_string(),
+            args: vec![],
+            root: None,
+            force: false,
+            manifest: true,
+            concurrent: Some(NonZeroUsize::new(4).unwrap()),
+            ..Default::default()
+          }),
+          global: true,
+        }),
+        ..Flags::default()
+      }
+    );
+
+    let r = flags_from_vec(svec![
+      "deno",
+      "install",
+      "-g",
+      "--concurrent",
+      "4",
+      "tools.json"
+    ]);
+    assert!(r.is_err());
+  }
+
   #[test]
   fn uninstall() {
     let r = flags_from_vec(svec!["deno", "uninstall", "file_server"]);
@@ -7767,8 +9188,9 @@ mod tests {
       Flags {
         subcommand: DenoSubcommand::Uninstall(UninstallFlags {
           kind: UninstallKind::Global(UninstallFlagsGlobal {
-            name: "file_server".to_string(),
+            name: Some("file_server".to_string()),
             root: None,
+            ..Default::default()
           }),
           global: false,
         }),
@@ -7782,8 +9204,9 @@ mod tests {
       Flags {
         subcommand: DenoSubcommand::Uninstall(UninstallFlags {
           kind: UninstallKind::Global(UninstallFlagsGlobal {
-            name: "file_server".to_string(),
+            name: Some("file_server".to_string()),
             root: None,
+            ..Default::default()
           }),
           global: true,
         }),
@@ -7792,6 +9215,115 @@ mod tests {
     );
   }
 
+  #[test]
+  fn uninstall_prune() {
+    let r = flags_from_vec(svec![
+      "deno",
+      "uninstall",
+      "--prune",
+      "--dry-run",
+      "--check-remote"
+    ]);
+    assert_eq!(
+      r.unwrap(),
+      Flags {
+        subcommand: DenoSubcommand::Uninstall(UninstallFlags {
+          kind: UninstallKind::Global(UninstallFlagsGlobal {
+            name: None,
+            root: None,
+            prune: true,
+            dry_run: true,
+            check_remote: true,
+            all: false,
+            force: false,
+            report_file: None,
+            timeout: None,
+          }),
+          global: false,
+        }),
+        ..Flags::default()
+      }
+    );
+
+    let r = flags_from_vec(svec![
+      "deno",
+      "uninstall",
+      "--prune",
+      "--check-remote",
+      "--timeout",
+      "5"
+    ]);
+    assert_eq!(
+      r.unwrap(),
+      Flags {
+        subcommand: DenoSubcommand::Uninstall(UninstallFlags {
+          kind: UninstallKind::Global(UninstallFlagsGlobal {
+            prune: true,
+            check_remote: true,
+            timeout: Some(5),
+            ..Default::default()
+          }),
+          global: false,
+        }),
+        ..Flags::default()
+      }
+    );
+
+    let r = flags_from_vec(svec!["deno", "uninstall", "--dry-run"]);
+    assert!(r.is_err());
+    let r =
+      flags_from_vec(svec!["deno", "uninstall", "--timeout", "5", "serve"]);
+    assert!(r.is_err());
+  }
+  #[test]
+  fn uninstall_all() {
+    let r = flags_from_vec(svec!["deno", "uninstall", "--all", "--force"]);
+    assert_eq!(
+      r.unwrap(),
+      Flags {
+        subcommand: DenoSubcommand::Uninstall(UninstallFlags {
+          kind: UninstallKind::Global(UninstallFlagsGlobal {
+            all: true,
+            force: true,
+            ..Default::default()
+          }),
+          global: false,
+        }),
+        ..Flags::default()
+      }
+    );
+
+    let r = flags_from_vec(svec!["deno", "uninstall", "--all"]);
+    assert!(r.is_err());
+    let r = flags_from_vec(svec!["deno", "uninstall", "--all", "-f", "serve"]);
+    assert!(r.is_err());
+  }
+
+  #[test]
+  fn uninstall_report_file() {
+    let r = flags_from_vec(svec![
+      "deno",
+      "uninstall",
+      "--report-file",
+      "audit.jsonl",
+      "serve"
+    ]);
+    assert_eq!(
+      r.unwrap(),
+      Flags {
+        subcommand: DenoSubcommand::Uninstall(UninstallFlags {
+          kind: UninstallKind::Global(UninstallFlagsGlobal {
+            name: Some("serve".to_string()),
+            report_file: Some("audit.jsonl".to_string()),
+            ..Default::default()
+          }),
+          global: false,
+        }),
+        ..Flags::default()
+      }
+    );
+  }
+
   #[test]
   fn uninstall_with_help_flag() {
     let r = flags_from_vec(svec!["deno", "uninstall", "--help"]);
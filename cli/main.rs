@@ -161,7 +161,7 @@ async fn run_subcommand(flags: Arc<Flags>) -> Result<i32, AnyError> {
       tools::jupyter::kernel(flags, jupyter_flags).await
     }),
     DenoSubcommand::Uninstall(uninstall_flags) => spawn_subcommand(async {
-      tools::installer::uninstall(uninstall_flags)
+      tools::installer::uninstall(flags, uninstall_flags).await
     }),
     DenoSubcommand::Lsp => spawn_subcommand(async { lsp::start().await }),
     DenoSubcommand::Lint(lint_flags) => spawn_subcommand(async {
@@ -301,11 +301,19 @@ fn exit_with_message(message: &str, code: i32) -> ! {
   std::process::exit(code);
 }
 
+#[allow(clippy::print_stderr)]
 fn exit_for_error(error: AnyError) -> ! {
+  if let Some(e) = error.downcast_ref::<tools::installer::JsonInstallError>() {
+    eprintln!("{e}");
+    std::process::exit(e.1);
+  }
+
   let mut error_string = format!("{error:?}");
   let mut error_code = 1;
 
-  if let Some(e) = error.downcast_ref::<JsError>() {
+  if let Some(e) = error.downcast_ref::<tools::installer::InstallError>() {
+    error_code = e.exit_code;
+  } else if let Some(e) = error.downcast_ref::<JsError>() {
     error_string = format_js_error(e);
   } else if let Some(SnapshotFromLockfileError::IntegrityCheckFailed(e)) =
     error.downcast_ref::<SnapshotFromLockfileError>()
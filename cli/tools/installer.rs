@@ -1,41 +1,142 @@
 // Copyright 2018-2024 the Deno authors. All rights reserved. MIT license.
 
+use crate::args::flags_from_vec;
 use crate::args::resolve_no_prompt;
 use crate::args::AddFlags;
 use crate::args::CaData;
 use crate::args::ConfigFlag;
 use crate::args::Flags;
+use crate::args::InstallCompanionsMode;
 use crate::args::InstallFlags;
 use crate::args::InstallFlagsGlobal;
 use crate::args::InstallKind;
+use crate::args::InstallListFormat;
+use crate::args::InstallOutputFormat;
+use crate::args::PackagesAllowedScripts;
+use crate::args::PermissionFlags;
 use crate::args::TypeCheckMode;
 use crate::args::UninstallFlags;
 use crate::args::UninstallKind;
+use crate::cache::DenoDir;
+use crate::cache::GlobalHttpCache;
+use crate::cache::HttpCache;
+use crate::cache::RealDenoCacheEnv;
+use crate::colors;
 use crate::factory::CliFactory;
 use crate::http_util::HttpClientProvider;
+use crate::util::diff;
+use crate::util::display;
 use crate::util::fs::canonicalize_path_maybe_not_exists;
+use crate::util::fs::LaxSingleProcessFsFlag;
 
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
 use deno_core::anyhow::Context;
 use deno_core::error::generic_error;
 use deno_core::error::AnyError;
+use deno_core::futures::stream;
+use deno_core::futures::StreamExt;
 use deno_core::resolve_url_or_path;
+use deno_core::serde_json;
+use deno_core::specifier_has_uri_scheme;
 use deno_core::url::Url;
+use deno_graph::packages::JsrPackageInfo;
+use deno_semver::jsr::JsrPackageReqReference;
 use deno_semver::npm::NpmPackageReqReference;
+use deno_semver::Version;
+use http::header::HeaderValue;
+use http::header::ETAG;
+use http::header::IF_MODIFIED_SINCE;
+use http::header::IF_NONE_MATCH;
+use http::header::LAST_MODIFIED;
+use http::StatusCode;
 use log::Level;
 use once_cell::sync::Lazy;
 use regex::Regex;
 use regex::RegexBuilder;
+use ring::signature::UnparsedPublicKey;
+use ring::signature::ED25519;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::env;
+use std::ffi::OsStr;
 use std::fs;
 use std::fs::File;
 use std::io;
+use std::io::BufRead;
+use std::io::IsTerminal;
 use std::io::Write;
+use std::num::NonZeroUsize;
 use std::path::Path;
 use std::path::PathBuf;
 
 #[cfg(not(windows))]
 use std::os::unix::fs::PermissionsExt;
 use std::sync::Arc;
+use std::time::Duration;
+
+/// The category of an installation failure, reported as `kind` in the JSON
+/// errors printed by `deno install --json`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum InstallErrorKind {
+  AlreadyInstalled,
+  InvalidName,
+  InvalidModuleUrl,
+  NotFound,
+  Other,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct InstallError {
+  pub kind: InstallErrorKind,
+  message: String,
+  /// The exit code deno exits with on this error.
+  pub exit_code: i32,
+}
+
+/// The exit code of `deno install --fail-if-exists` when the executable is
+/// already installed (`EEXIST`).
+pub const ALREADY_INSTALLED_EXIT_CODE: i32 = 17;
+
+fn install_error(
+  kind: InstallErrorKind,
+  message: impl Into<String>,
+) -> AnyError {
+  InstallError {
+    kind,
+    message: message.into(),
+    exit_code: 1,
+  }
+  .into()
+}
+
+/// An installation error already rendered as a JSON object, and the exit
+/// code to exit with. It is printed as is on stderr, without the usual
+/// `error:` prefix.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct JsonInstallError(pub String, pub i32);
+
+/// The output of `--print-path`: the path of the executable on a single line.
+fn print_path_output(installed: &InstalledScript) -> String {
+  format!("{}\n", installed.file_path.display())
+}
+
+fn render_json_error(err: &AnyError) -> String {
+  let kind = err
+    .downcast_ref::<InstallError>()
+    .map(|err| err.kind)
+    .unwrap_or(InstallErrorKind::Other);
+  serde_json::json!({
+    "error": err.to_string(),
+    "kind": kind,
+  })
+  .to_string()
+}
 
 static EXEC_NAME_RE: Lazy<Regex> = Lazy::new(|| {
   RegexBuilder::new(r"^[a-z0-9][\w-]*$")
@@ -44,13 +145,139 @@ static EXEC_NAME_RE: Lazy<Regex> = Lazy::new(|| {
     .expect("invalid regex")
 });
 
+/// The portable executable names allowed by `--strict-name`.
+static STRICT_EXEC_NAME_RE: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"^[a-z][a-z0-9-]*$").expect("invalid regex"));
+
+/// A GitHub shorthand like `gh:owner/repo/path/to/cli.ts@ref`, capturing the
+/// owner, the repository and the path with the optional ref.
+static GITHUB_SHORTHAND_RE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(r"^gh:([A-Za-z0-9_.-]+)/([A-Za-z0-9_.-]+)/(.+)$")
+    .expect("invalid regex")
+});
+
+/// A bare package name like `cowsay` or `cowsay@1.2.0`, without a scheme or
+/// a path, resolved against the default registry.
+static BARE_NAME_RE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(r"^[A-Za-z0-9][\w-]*(?:@[\w.+-]+)?$").expect("invalid regex")
+});
+
+static VERSION_SUFFIX_RE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(r"^(.+?)@v?\d+(?:\.\d+)*(?:[-+][0-9A-Za-z.+-]*)?$")
+    .expect("invalid regex")
+});
+
+/// Removes `prefix` from an inferred executable name, unless nothing would
+/// be left of the name.
+fn strip_name_prefix(name: String, prefix: &str) -> String {
+  match name.strip_prefix(prefix) {
+    Some(stripped) if !stripped.is_empty() => stripped.to_string(),
+    _ => name,
+  }
+}
+
+/// Whether `name` can be joined to the installation directory without
+/// escaping it or creating a hidden file. This is checked independently of
+/// `EXEC_NAME_RE` before any path is built from a name.
+fn is_safe_name(name: &str) -> bool {
+  !name.is_empty()
+    && !name.starts_with('.')
+    && !name.contains("..")
+    && !name.contains(['/', '\\'])
+    && !Path::new(name).is_absolute()
+}
+
+fn ensure_safe_name(name: &str) -> Result<(), AnyError> {
+  if is_safe_name(name) {
+    Ok(())
+  } else {
+    Err(install_error(
+      InstallErrorKind::InvalidName,
+      format!("Invalid executable name: {name}"),
+    ))
+  }
+}
+
+/// Validates a name kept with `--keep-extension`: an executable name
+/// followed by an alphanumeric extension, like `server.ts`.
+fn validate_name_with_extension(exec_name: &str) -> Result<(), AnyError> {
+  match exec_name.rsplit_once('.') {
+    Some((stem, ext))
+      if !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric()) =>
+    {
+      validate_name(stem).map_err(|_| {
+        install_error(
+          InstallErrorKind::InvalidName,
+          format!("Invalid executable name: {exec_name}"),
+        )
+      })
+    }
+    _ => validate_name(exec_name),
+  }
+}
+
+/// Returns the path segment of the module URL at `index`, for
+/// `--name-from-arg`.
+fn name_from_url_segment(
+  module_url: &Url,
+  index: usize,
+) -> Result<String, AnyError> {
+  let segment = module_url
+    .path_segments()
+    .and_then(|mut segments| segments.nth(index))
+    .filter(|segment| !segment.is_empty())
+    .ok_or_else(|| {
+      install_error(
+        InstallErrorKind::InvalidName,
+        format!("{module_url} has no path segment at index {index}"),
+      )
+    })?;
+  Ok(
+    percent_encoding::percent_decode_str(segment)
+      .decode_utf8_lossy()
+      .to_string(),
+  )
+}
+
+/// Appends the extension of the module's file name to a name inferred from
+/// that file name, for `--keep-extension`.
+fn keep_module_extension(name: String, module_url: &Url) -> String {
+  let path =
+    percent_encoding::percent_decode_str(module_url.path()).decode_utf8_lossy();
+  let path = Path::new(path.as_ref());
+  match (path.file_stem(), path.extension()) {
+    (Some(stem), Some(ext)) if stem.to_string_lossy() == name => {
+      format!("{name}.{}", ext.to_string_lossy())
+    }
+    _ => name,
+  }
+}
+
+/// Validates `exec_name` against the portable names allowed by
+/// `--strict-name`, on top of [`validate_name`].
+fn validate_strict_name(exec_name: &str) -> Result<(), AnyError> {
+  validate_name(exec_name)?;
+  if STRICT_EXEC_NAME_RE.is_match(exec_name) {
+    Ok(())
+  } else {
+    Err(install_error(
+      InstallErrorKind::InvalidName,
+      format!(
+        "Invalid executable name: {exec_name}. With --strict-name, names may only contain lowercase ASCII letters, digits and dashes, and must start with a letter."
+      ),
+    ))
+  }
+}
+
 fn validate_name(exec_name: &str) -> Result<(), AnyError> {
+  ensure_safe_name(exec_name)?;
   if EXEC_NAME_RE.is_match(exec_name) {
     Ok(())
   } else {
-    Err(generic_error(format!(
-      "Invalid executable name: {exec_name}"
-    )))
+    Err(install_error(
+      InstallErrorKind::InvalidName,
+      format!("Invalid executable name: {exec_name}"),
+    ))
   }
 }
 
@@ -59,1458 +286,12467 @@ fn validate_name(exec_name: &str) -> Result<(), AnyError> {
 /// One compatible with cmd & powershell with a .cmd extension
 /// A second compatible with git bash / MINGW64
 /// Generate batch script to satisfy that.
-fn generate_executable_file(shim_data: &ShimData) -> Result<(), AnyError> {
-  let args: Vec<String> =
-    shim_data.args.iter().map(|c| format!("\"{c}\"")).collect();
-  let template = format!(
-    "% generated by deno install %\n@deno {} %*\n",
-    args
-      .iter()
-      .map(|arg| arg.replace('%', "%%"))
-      .collect::<Vec<_>>()
-      .join(" ")
+fn render_executable_files(
+  shim_data: &ShimData,
+  file_path: &Path,
+) -> Vec<(PathBuf, String)> {
+  let mut args: Vec<String> = shim_data
+    .args
+    .iter()
+    .map(|c| match relocatable_arg(shim_data, c) {
+      Some((prefix, rest)) => {
+        format!("\"{prefix}$(dirname \"$0\")/{}\"", rest.replace('\\', "/"))
+      }
+      None => format!("\"{c}\""),
+    })
+    .collect();
+  if shim_data.grant_state_file.is_some() {
+    args.insert(grant_flags_index(shim_data), "$grants".to_string());
+  }
+  let invocation = cmd_invocation(shim_data);
+  let mut cmd_template = format!(
+    "@echo off\n% {GENERATED_MARKER} %\n{}{}{}\n",
+    shim_data
+      .wrapper_comment
+      .as_ref()
+      .map(|comment| format!("% {} %\n", comment.replace('%', "")))
+      .unwrap_or_default(),
+    cmd_preamble(shim_data),
+    invocation,
   );
-  let mut file = File::create(&shim_data.file_path)?;
-  file.write_all(template.as_bytes())?;
-
-  // write file for bash
-  // create filepath without extensions
-  let template = format!(
-    r#"#!/bin/sh
-# generated by deno install
-deno {} "$@"
+  if shim_data.cmd_crlf {
+    cmd_template = cmd_template.replace('\n', "\r\n");
+  }
+
+  // file for bash, without extensions
+  let sh_template = match &shim_data.custom_template {
+    Some(template) => render_sh_template(template, shim_data, &args),
+    None => format!(
+      r#"#!/bin/sh
+# {GENERATED_MARKER}
+{}{}{}deno {} "$@"
 "#,
-    args.join(" "),
-  );
-  let mut file = File::create(shim_data.file_path.with_extension(""))?;
-  file.write_all(template.as_bytes())?;
-  Ok(())
+      wrapper_comment_line(shim_data, "# "),
+      sh_preamble(shim_data),
+      sh_env_assignments(&shim_data.env),
+      args.join(" "),
+    ),
+  };
+  let shell_file_path = file_path.with_extension("");
+  let mut files = vec![];
+  if shim_data.output_format != InstallOutputFormat::Sh {
+    if shim_data.bat {
+      // some legacy toolchains only look for .bat files
+      files.push((file_path.with_extension("bat"), cmd_template.clone()));
+    }
+    files.push((file_path.to_path_buf(), cmd_template));
+  }
+  if shim_data.output_format != InstallOutputFormat::Cmd {
+    files.push((shell_file_path, sh_template));
+  }
+  files
 }
 
 #[cfg(not(windows))]
-fn generate_executable_file(shim_data: &ShimData) -> Result<(), AnyError> {
+/// Renders the executable shell script.
+fn render_executable_files(
+  shim_data: &ShimData,
+  file_path: &Path,
+) -> Vec<(PathBuf, String)> {
   use shell_escape::escape;
-  let args: Vec<String> = shim_data
+  if shim_data.shebang_env && shim_data.custom_template.is_none() {
+    if let Some(script) = render_shebang_script(shim_data) {
+      return vec![(file_path.to_path_buf(), script)];
+    }
+    log::debug!(
+      "Falling back to a shell script for {} as its invocation can't be expressed as a shebang",
+      shim_data.name
+    );
+  }
+  let mut args: Vec<String> = shim_data
     .args
     .iter()
-    .map(|c| escape(c.into()).into_owned())
+    .map(|c| match relocatable_arg(shim_data, c) {
+      Some((prefix, rest)) => format!(
+        "{}\"$(dirname \"$0\")\"/{}",
+        escape(prefix.into()),
+        escape(rest.into())
+      ),
+      None => escape(c.into()).into_owned(),
+    })
     .collect();
-  let template = format!(
-    r#"#!/bin/sh
-# generated by deno install
-exec deno {} "$@"
-"#,
-    args.join(" "),
-  );
-  let mut file = File::create(&shim_data.file_path)?;
-  file.write_all(template.as_bytes())?;
-  let _metadata = fs::metadata(&shim_data.file_path)?;
-  let mut permissions = _metadata.permissions();
-  permissions.set_mode(0o755);
-  fs::set_permissions(&shim_data.file_path, permissions)?;
-  Ok(())
-}
-
-fn get_installer_root() -> Result<PathBuf, io::Error> {
-  if let Ok(env_dir) = env::var("DENO_INSTALL_ROOT") {
-    if !env_dir.is_empty() {
-      return canonicalize_path_maybe_not_exists(&PathBuf::from(env_dir));
-    }
+  if shim_data.grant_state_file.is_some() {
+    args.insert(grant_flags_index(shim_data), "$grants".to_string());
   }
-  // Note: on Windows, the $HOME environment variable may be set by users or by
-  // third party software, but it is non-standard and should not be relied upon.
-  let home_env_var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
-  let mut home_path =
-    env::var_os(home_env_var)
-      .map(PathBuf::from)
-      .ok_or_else(|| {
-        io::Error::new(
-          io::ErrorKind::NotFound,
-          format!("${home_env_var} is not defined"),
-        )
-      })?;
-  home_path.push(".deno");
-  Ok(home_path)
+  let template = match &shim_data.custom_template {
+    Some(template) => render_sh_template(template, shim_data, &args),
+    None => format!(
+      r#"#!/bin/sh
+# {GENERATED_MARKER}
+{}{}{}exec deno {} "$@"
+"#,
+      wrapper_comment_line(shim_data, "# "),
+      sh_preamble(shim_data),
+      sh_env_assignments(&shim_data.env),
+      args.join(" "),
+    ),
+  };
+  vec![(file_path.to_path_buf(), template)]
 }
 
-pub async fn infer_name_from_url(
-  http_client_provider: &HttpClientProvider,
-  url: &Url,
-) -> Option<String> {
-  // If there's an absolute url with no path, eg. https://my-cli.com
-  // perform a request, and see if it redirects another file instead.
-  let mut url = url.clone();
-
-  if url.path() == "/" {
-    if let Ok(client) = http_client_provider.get_or_create() {
-      if let Ok(redirected_url) =
-        client.get_redirected_url(url.clone(), None).await
-      {
-        url = redirected_url;
-      }
-    }
+/// The longest shebang line Linux reads in full.
+#[cfg(not(windows))]
+const SHEBANG_LINE_LIMIT: usize = 255;
+
+/// Renders a script that deno runs directly through its
+/// `#!/usr/bin/env -S deno run` shebang, importing the installed module.
+/// As the module is imported rather than run, `import.meta.main` is false in
+/// it, so `--shebang-env` is only honored for modules that don't check it.
+/// Returns `None` when the invocation needs a shell: for baked-in script
+/// arguments, arguments containing newlines or shell preambles.
+#[cfg(not(windows))]
+fn render_shebang_script(shim_data: &ShimData) -> Option<String> {
+  if !shim_data.metadata.args.is_empty()
+    || !shim_data.env.is_empty()
+    || shim_data.pre_run.is_some()
+    || shim_data.working_dir.is_some()
+    || shim_data.min_deno_version.is_some()
+    || shim_data.grant_state_file.is_some()
+    || shim_data.relocatable
+  {
+    return None;
   }
-
-  if let Ok(npm_ref) = NpmPackageReqReference::from_specifier(&url) {
-    let npm_ref = npm_ref.into_inner();
-    if let Some(sub_path) = npm_ref.sub_path {
-      if !sub_path.contains('/') {
-        return Some(sub_path);
-      }
-    }
-    if !npm_ref.req.name.contains('/') {
-      return Some(npm_ref.req.name);
-    }
+  let (module_url, run_args) = shim_data.args.get(1..)?.split_last()?;
+  if run_args.iter().any(|arg| arg.contains(['\n', '\r']))
+    || module_url.contains(['"', '\\', '\n'])
+  {
     return None;
   }
-
-  let percent_decode = percent_encoding::percent_decode(url.path().as_bytes());
-  #[cfg(unix)]
-  let path = {
-    use std::os::unix::prelude::OsStringExt;
-    PathBuf::from(std::ffi::OsString::from_vec(
-      percent_decode.collect::<Vec<u8>>(),
-    ))
-  };
-  #[cfg(windows)]
-  let path = PathBuf::from(percent_decode.decode_utf8_lossy().as_ref());
-
-  let mut stem = path.file_stem()?.to_string_lossy();
-  if matches!(stem.as_ref(), "main" | "mod" | "index" | "cli") {
-    if let Some(parent_name) = path.parent().and_then(|p| p.file_name()) {
-      stem = parent_name.to_string_lossy();
-    }
+  let mut shebang = "#!/usr/bin/env -S deno run --ext=js".to_string();
+  for arg in run_args {
+    shebang.push(' ');
+    shebang.push_str(&env_split_escape(arg));
   }
-
-  // if atmark symbol appears in the index other than 0 (e.g. `foo@bar`) we use
-  // the former part as the inferred name because the latter part is most likely
-  // a version number.
-  match stem.find('@') {
-    Some(at_index) if at_index > 0 => {
-      stem = stem.split_at(at_index).0.to_string().into();
-    }
-    _ => {}
+  if shebang.len() > SHEBANG_LINE_LIMIT {
+    return None;
   }
-
-  Some(stem.to_string())
+  Some(format!(
+    "{shebang}\n// {GENERATED_MARKER}\n{}import \"{module_url}\";\n",
+    wrapper_comment_line(shim_data, "// "),
+  ))
 }
 
-pub fn uninstall(uninstall_flags: UninstallFlags) -> Result<(), AnyError> {
-  if !uninstall_flags.global {
-    log::warn!("⚠️ `deno install` behavior will change in Deno 2. To preserve the current behavior use the `-g` or `--global` flag.");
+/// Quotes an argument for the `env -S` of a shebang, which splits on
+/// whitespace and interprets `$`, quotes, `\` and `#`. Within single quotes,
+/// only `\\` and `\'` are escapes.
+fn env_split_escape(arg: &str) -> String {
+  if !arg.contains(|c: char| {
+    c.is_whitespace() || matches!(c, '$' | '\'' | '"' | '\\' | '#')
+  }) {
+    return arg.to_string();
   }
+  format!("'{}'", arg.replace('\\', r"\\").replace('\'', r"\'"))
+}
 
-  let uninstall_flags = match uninstall_flags.kind {
-    UninstallKind::Global(flags) => flags,
-    UninstallKind::Local => unreachable!(),
-  };
+/// The file system operations used to write and remove installations, so
+/// that tests can check the written files without touching the disk.
+trait InstallerFs {
+  fn read_to_string(&self, path: &Path) -> io::Result<String>;
+  fn write(&self, path: &Path, contents: &str) -> io::Result<()>;
+  #[cfg_attr(windows, allow(dead_code))]
+  fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()>;
+  fn remove_file(&self, path: &Path) -> io::Result<()>;
+  fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+  fn exists(&self, path: &Path) -> bool;
+}
 
-  let cwd = std::env::current_dir().context("Unable to get CWD")?;
-  let root = if let Some(root) = uninstall_flags.root {
-    canonicalize_path_maybe_not_exists(&cwd.join(root))?
-  } else {
-    get_installer_root()?
-  };
-  let installation_dir = root.join("bin");
+/// [`InstallerFs`] backed by [`std::fs`].
+struct RealInstallerFs;
 
-  // ensure directory exists
-  if let Ok(metadata) = fs::metadata(&installation_dir) {
-    if !metadata.is_dir() {
-      return Err(generic_error("Installation path is not a directory"));
-    }
+impl InstallerFs for RealInstallerFs {
+  fn read_to_string(&self, path: &Path) -> io::Result<String> {
+    fs::read_to_string(path)
   }
 
-  let file_path = installation_dir.join(&uninstall_flags.name);
+  fn write(&self, path: &Path, contents: &str) -> io::Result<()> {
+    File::create(path).and_then(|mut file| file.write_all(contents.as_bytes()))
+  }
 
-  let mut removed = false;
+  #[cfg(not(windows))]
+  fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+  }
 
-  if file_path.exists() {
-    fs::remove_file(&file_path)?;
-    log::info!("deleted {}", file_path.to_string_lossy());
-    removed = true
-  };
+  #[cfg(windows)]
+  fn set_mode(&self, _path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+  }
 
-  if cfg!(windows) {
-    let file_path = file_path.with_extension("cmd");
-    if file_path.exists() {
-      fs::remove_file(&file_path)?;
-      log::info!("deleted {}", file_path.to_string_lossy());
-      removed = true
-    }
+  fn remove_file(&self, path: &Path) -> io::Result<()> {
+    fs::remove_file(path)
   }
 
-  if !removed {
-    return Err(generic_error(format!(
-      "No installation found for {}",
-      uninstall_flags.name
-    )));
+  fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+    fs::rename(from, to)
   }
 
-  // There might be some extra files to delete
-  // Note: tsconfig.json is legacy. We renamed it to deno.json.
-  // Remove cleaning it up after January 2024
-  for ext in ["tsconfig.json", "deno.json", "lock.json"] {
-    let file_path = file_path.with_extension(ext);
-    if file_path.exists() {
-      fs::remove_file(&file_path)?;
-      log::info!("deleted {}", file_path.to_string_lossy());
-    }
+  fn exists(&self, path: &Path) -> bool {
+    path.exists()
   }
+}
 
-  log::info!("✅ Successfully uninstalled {}", uninstall_flags.name);
-  Ok(())
+/// Writes the executable files for `file_path`, returning their paths.
+fn generate_executable_file(
+  fs: &dyn InstallerFs,
+  shim_data: &ShimData,
+  file_path: &Path,
+) -> Result<Vec<PathBuf>, AnyError> {
+  let mut created_files = Vec::new();
+  for (path, contents) in render_executable_files(shim_data, file_path) {
+    fs.write(&path, &contents).with_context(|| {
+      format!("Failed to write executable at {}", path.display())
+    })?;
+    set_executable_permissions(fs, shim_data, &path)?;
+    created_files.push(path);
+  }
+  Ok(created_files)
 }
 
-async fn install_local(
-  flags: Arc<Flags>,
-  maybe_add_flags: Option<AddFlags>,
+#[cfg_attr(windows, allow(unused_variables))]
+fn set_executable_permissions(
+  fs: &dyn InstallerFs,
+  shim_data: &ShimData,
+  path: &Path,
 ) -> Result<(), AnyError> {
-  if let Some(add_flags) = maybe_add_flags {
-    return super::registry::add(flags, add_flags).await;
+  #[cfg(not(windows))]
+  {
+    let mode = if shim_data.owner_only { 0o700 } else { 0o755 };
+    fs.set_mode(path, mode).with_context(|| {
+      format!("Failed to set the permissions of {}", path.display())
+    })?;
   }
+  Ok(())
+}
 
-  let factory = CliFactory::from_flags(flags);
-  crate::module_loader::load_top_level_deps(&factory).await?;
+/// Renders every file of an installation, split into the executables and the
+/// other files (companions and metadata).
+#[allow(clippy::type_complexity)]
+fn render_installation_files(
+  shim_data: &ShimData,
+) -> Result<(Vec<(PathBuf, String)>, Vec<(PathBuf, String)>), AnyError> {
+  let mut executables =
+    render_executable_files(shim_data, &shim_data.file_path);
+  for alias_path in &shim_data.alias_paths {
+    executables.extend(render_executable_files(shim_data, alias_path));
+  }
+  let mut others = shim_data.extra_files.clone();
+  others.push((
+    get_metadata_path(&shim_data.file_path),
+    serde_json::to_string_pretty(&shim_data.metadata)?,
+  ));
+  Ok((executables, others))
+}
 
-  if let Some(lockfile) = factory.cli_options()?.maybe_lockfile() {
-    lockfile.write_if_changed()?;
+/// Describes the files an installation would write without writing them.
+/// With `verbose`, the full contents of every file are included.
+fn render_dry_run(
+  shim_data: &ShimData,
+  verbose: bool,
+) -> Result<String, AnyError> {
+  let (executables, others) = render_installation_files(shim_data)?;
+  let files = executables.into_iter().chain(others);
+
+  let mut output = format!("deno {}\n", shim_data.args.join(" "));
+  for (path, contents) in files {
+    if verbose {
+      output.push_str(&format!("\n{}:\n{}", path.display(), contents));
+      if !contents.ends_with('\n') {
+        output.push('\n');
+      }
+    } else {
+      output.push_str(&format!("{}\n", path.display()));
+    }
   }
+  Ok(output)
+}
 
-  Ok(())
+/// The plan printed by `--dry-run --json`: the executable an installation
+/// would write, its arguments, whether it would overwrite an existing
+/// installation and the companion files written next to it.
+fn render_dry_run_plan(shim_data: &ShimData) -> serde_json::Value {
+  let would_overwrite = std::iter::once(&shim_data.file_path)
+    .chain(&shim_data.alias_paths)
+    .any(|path| path.exists());
+  let companions = shim_data
+    .extra_files
+    .iter()
+    .map(|(path, _)| path.to_string_lossy())
+    .collect::<Vec<_>>();
+  serde_json::json!({
+    "name": shim_data.name,
+    "path": shim_data.file_path.to_string_lossy(),
+    "args": shim_data.args,
+    "would_overwrite": would_overwrite,
+    "companions": companions,
+  })
 }
 
-pub async fn install_command(
-  flags: Arc<Flags>,
-  install_flags: InstallFlags,
-) -> Result<(), AnyError> {
-  match install_flags.kind {
-    InstallKind::Global(global_flags) => {
-      if !install_flags.global {
-        log::warn!("⚠️ `deno install` behavior will change in Deno 2. To preserve the current behavior use the `-g` or `--global` flag.");
+/// Describes how `--force` would change the executables of an existing
+/// installation, with a line diff for each executable whose content differs.
+fn render_dry_run_diff(shim_data: &ShimData) -> Result<String, AnyError> {
+  let (executables, _) = render_installation_files(shim_data)?;
+  let mut output = String::new();
+  for (path, contents) in executables {
+    match fs::read_to_string(&path) {
+      Ok(existing) if existing == contents => {
+        output.push_str(&format!("{} is unchanged\n", path.display()));
+      }
+      Ok(existing) => {
+        output.push_str(&format!(
+          "\n{}:\n{}",
+          path.display(),
+          diff::diff(&existing, &contents)
+        ));
+      }
+      Err(err) if err.kind() == io::ErrorKind::NotFound => {
+        output.push_str(&format!("{} is a new file\n", path.display()));
+      }
+      Err(err) => {
+        return Err(err).with_context(|| {
+          format!("Failed to read executable at {}", path.display())
+        })
       }
-
-      install_global(flags, global_flags).await
-    }
-    InstallKind::Local(maybe_add_flags) => {
-      install_local(flags, maybe_add_flags).await
     }
   }
+  Ok(output)
 }
 
-async fn install_global(
-  flags: Arc<Flags>,
-  install_flags_global: InstallFlagsGlobal,
-) -> Result<(), AnyError> {
-  // ensure the module is cached
-  let factory = CliFactory::from_flags(flags.clone());
-  factory
-    .main_module_graph_container()
-    .await?
-    .load_and_type_check_files(&[install_flags_global.module_url.clone()])
-    .await?;
-  let http_client = factory.http_client_provider();
-
-  // create the install shim
-  create_install_shim(http_client, &flags, install_flags_global).await
+/// The `--wrapper-comment` line following the generated marker of a script
+/// whose line comments start with `comment_start`.
+fn wrapper_comment_line(shim_data: &ShimData, comment_start: &str) -> String {
+  match &shim_data.wrapper_comment {
+    Some(comment) => format!("{comment_start}{comment}\n"),
+    None => String::new(),
+  }
 }
 
-async fn create_install_shim(
-  http_client_provider: &HttpClientProvider,
-  flags: &Flags,
-  install_flags_global: InstallFlagsGlobal,
-) -> Result<(), AnyError> {
-  let shim_data =
-    resolve_shim_data(http_client_provider, flags, &install_flags_global)
-      .await?;
+/// The placeholders every `--template` can use, besides `--template-var`s.
+const BUILTIN_TEMPLATE_VARS: [&str; 6] = [
+  "NAME",
+  "MODULE_URL",
+  "DENO_ARGS",
+  "PREAMBLE",
+  "ENV",
+  "MARKER",
+];
+
+/// Placeholders that depend on the executable being rendered, so they are
+/// substituted by [`render_sh_template`] rather than at install time.
+const SHIM_TEMPLATE_VARS: [&str; 3] = ["DENO_ARGS", "PREAMBLE", "ENV"];
+
+static TEMPLATE_PLACEHOLDER_RE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(r"\{\{([A-Za-z_][A-Za-z0-9_]*)\}\}").expect("invalid regex")
+});
 
-  // ensure directory exists
-  if let Ok(metadata) = fs::metadata(&shim_data.installation_dir) {
-    if !metadata.is_dir() {
-      return Err(generic_error("Installation path is not a directory"));
+/// Substitutes the placeholders of a `--template`. `{{DENO_ARGS}}`,
+/// `{{PREAMBLE}}` and `{{ENV}}` are left in place, as they are rendered for
+/// each executable. Every placeholder has to be built in or provided with
+/// `--template-var`.
+fn render_custom_template(
+  template: &str,
+  name: &str,
+  module_url: &str,
+  template_vars: &[String],
+) -> Result<String, AnyError> {
+  let mut vars = HashMap::new();
+  for template_var in template_vars {
+    let Some((key, value)) = template_var.split_once('=') else {
+      return Err(generic_error(format!(
+        "Invalid template variable, expected KEY=VALUE: {template_var}"
+      )));
+    };
+    if !ENV_KEY_RE.is_match(key) {
+      return Err(generic_error(format!(
+        "Invalid template variable name: {key}"
+      )));
     }
-  } else {
-    fs::create_dir_all(&shim_data.installation_dir)?;
-  };
+    if BUILTIN_TEMPLATE_VARS.contains(&key) {
+      return Err(generic_error(format!(
+        "Template variable {key} is built in and can't be overridden"
+      )));
+    }
+    vars.insert(key, value);
+  }
+  vars.insert("NAME", name);
+  vars.insert("MODULE_URL", module_url);
+  vars.insert("MARKER", GENERATED_MARKER);
+
+  let mut unresolved = Vec::new();
+  for captures in TEMPLATE_PLACEHOLDER_RE.captures_iter(template) {
+    let key = captures.get(1).unwrap().as_str();
+    if !SHIM_TEMPLATE_VARS.contains(&key)
+      && !vars.contains_key(key)
+      && !unresolved.contains(&key)
+    {
+      unresolved.push(key);
+    }
+  }
+  if !unresolved.is_empty() {
+    return Err(generic_error(format!(
+      "Unresolved placeholders in the template: {}. Provide them with --template-var KEY=VALUE.",
+      unresolved
+        .iter()
+        .map(|key| format!("{{{{{key}}}}}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+    )));
+  }
 
-  if shim_data.file_path.exists() && !install_flags_global.force {
+  let rendered = TEMPLATE_PLACEHOLDER_RE
+    .replace_all(template, |captures: &regex::Captures| {
+      match vars.get(&captures[1]) {
+        Some(value) => value.to_string(),
+        None => captures[0].to_string(),
+      }
+    })
+    .into_owned();
+  if !has_generated_marker(&rendered) {
     return Err(generic_error(
-      "Existing installation found. Aborting (Use -f to overwrite).",
+      "The template must contain the {{MARKER}} placeholder, which marks the executable as generated by deno install",
     ));
-  };
-
-  generate_executable_file(&shim_data)?;
-  for (path, contents) in shim_data.extra_files {
-    fs::write(path, contents)?;
   }
+  Ok(rendered)
+}
 
-  log::info!("✅ Successfully installed {}", shim_data.name);
-  log::info!("{}", shim_data.file_path.display());
-  if cfg!(windows) {
-    let display_path = shim_data.file_path.with_extension("");
-    log::info!("{} (shell)", display_path.display());
-  }
-  let installation_dir_str = shim_data.installation_dir.to_string_lossy();
+/// Fills in the placeholders of a rendered `--template` that depend on the
+/// executable: the deno arguments, the shell preamble and the `--set-env`
+/// assignments.
+fn render_sh_template(
+  template: &str,
+  shim_data: &ShimData,
+  args: &[String],
+) -> String {
+  TEMPLATE_PLACEHOLDER_RE
+    .replace_all(template, |captures: &regex::Captures| match &captures[1] {
+      "DENO_ARGS" => args.join(" "),
+      "PREAMBLE" => sh_preamble(shim_data),
+      "ENV" => sh_env_assignments(&shim_data.env),
+      _ => captures[0].to_string(),
+    })
+    .into_owned()
+}
 
-  if !is_in_path(&shim_data.installation_dir) {
-    log::info!("ℹ️  Add {} to PATH", installation_dir_str);
-    if cfg!(windows) {
-      log::info!("    set PATH=%PATH%;{}", installation_dir_str);
-    } else {
-      log::info!("    export PATH=\"{}:$PATH\"", installation_dir_str);
-    }
+/// Checks that a `--template` has a place for everything other flags add to
+/// the executable, so that none of it is dropped silently.
+fn check_custom_template(shim_data: &ShimData) -> Result<(), AnyError> {
+  let Some(template) = &shim_data.custom_template else {
+    return Ok(());
+  };
+  if !template.contains("{{PREAMBLE}}") && !sh_preamble(shim_data).is_empty() {
+    return Err(generic_error(
+      "The template has no {{PREAMBLE}} placeholder, which --min-deno-version, --cwd, --pre-run and --grant-prompt-once need",
+    ));
+  }
+  if !template.contains("{{ENV}}") && !shim_data.env.is_empty() {
+    return Err(generic_error(
+      "The template has no {{ENV}} placeholder, which --set-env needs",
+    ));
+  }
+  if cfg!(windows) && shim_data.output_format != InstallOutputFormat::Sh {
+    return Err(generic_error(
+      "The template only renders the shell script, not the .cmd file. Pass --output-format sh to install with a template on Windows.",
+    ));
   }
-
   Ok(())
 }
 
-struct ShimData {
-  name: String,
-  installation_dir: PathBuf,
-  file_path: PathBuf,
-  args: Vec<String>,
-  extra_files: Vec<(PathBuf, String)>,
+/// Variable assignments prefixed to the deno invocation of shell scripts.
+fn sh_env_assignments(env: &[(String, String)]) -> String {
+  env
+    .iter()
+    .map(|(key, value)| format!("{}='{}' ", key, value.replace('\'', r"'\''")))
+    .collect()
 }
 
-async fn resolve_shim_data(
-  http_client_provider: &HttpClientProvider,
-  flags: &Flags,
+/// The registry bare names are installed from: `--registry-default`, or
+/// `$DENO_INSTALL_REGISTRY`.
+fn registry_default(
   install_flags_global: &InstallFlagsGlobal,
-) -> Result<ShimData, AnyError> {
-  let cwd = std::env::current_dir().context("Unable to get CWD")?;
-  let root = if let Some(root) = &install_flags_global.root {
-    canonicalize_path_maybe_not_exists(&cwd.join(root))?
-  } else {
-    get_installer_root()?
-  };
-  let installation_dir = root.join("bin");
-
-  // Check if module_url is remote
-  let module_url = resolve_url_or_path(&install_flags_global.module_url, &cwd)?;
+) -> Option<String> {
+  install_flags_global
+    .registry_default
+    .clone()
+    .or_else(|| env::var("DENO_INSTALL_REGISTRY").ok())
+    .filter(|registry| !registry.is_empty())
+}
 
-  let name = if install_flags_global.name.is_some() {
-    install_flags_global.name.clone()
-  } else {
-    infer_name_from_url(http_client_provider, &module_url).await
+/// Expands a GitHub shorthand like `gh:owner/repo/path/to/cli.ts@ref` to the
+/// URL of the raw file. Without a ref, the default branch of the repository
+/// is used. Returns `None` for other specifiers.
+fn expand_github_shorthand(
+  specifier: &str,
+) -> Result<Option<String>, AnyError> {
+  if !specifier.starts_with("gh:") {
+    return Ok(None);
+  }
+  let invalid = || {
+    install_error(
+      InstallErrorKind::InvalidModuleUrl,
+      format!(
+        "Invalid GitHub shorthand: {specifier}. Expected gh:owner/repo/path/to/file.ts, optionally followed by @ref."
+      ),
+    )
   };
-
-  let name = match name {
-    Some(name) => name,
-    None => return Err(generic_error(
-      "An executable name was not provided. One could not be inferred from the URL. Aborting.",
-    )),
+  let captures = GITHUB_SHORTHAND_RE
+    .captures(specifier)
+    .ok_or_else(invalid)?;
+  let (path, git_ref) = match captures[3].rsplit_once('@') {
+    Some((path, git_ref)) => (path, git_ref),
+    // `HEAD` resolves to the default branch
+    None => (&captures[3], "HEAD"),
   };
+  if path.is_empty() || path.ends_with('/') || git_ref.is_empty() {
+    return Err(invalid());
+  }
+  Ok(Some(format!(
+    "https://raw.githubusercontent.com/{}/{}/{git_ref}/{path}",
+    &captures[1], &captures[2]
+  )))
+}
 
-  validate_name(name.as_str())?;
-  let mut file_path = installation_dir.join(&name);
+/// Resolves a bare package name like `cowsay` against `registry_default` and
+/// expands GitHub shorthands. Other specifiers, and bare names of existing
+/// files or directories in `base_dir`, are returned as is. Without a default
+/// registry, other bare names are rejected.
+fn resolve_bare_name(
+  specifier: &str,
+  registry_default: Option<&str>,
+  base_dir: &Path,
+) -> Result<String, AnyError> {
+  if let Some(url) = expand_github_shorthand(specifier)? {
+    return Ok(url);
+  }
+  // a local path takes precedence over a package of the same name
+  if !BARE_NAME_RE.is_match(specifier) || base_dir.join(specifier).exists() {
+    return Ok(specifier.to_string());
+  }
+  match registry_default {
+    Some(base) if base.ends_with(['/', ':']) => Ok(format!("{base}{specifier}")),
+    Some(base) => Ok(format!("{base}/{specifier}")),
+    None => Err(install_error(
+      InstallErrorKind::InvalidModuleUrl,
+      format!(
+        "{specifier} is a bare name and no default registry is configured. Use --registry-default or DENO_INSTALL_REGISTRY to install it from a registry, or ./{specifier} for a local file."
+      ),
+    )),
+  }
+}
 
-  if cfg!(windows) {
-    file_path = file_path.with_extension("cmd");
+/// Turns an `--allow-net` entry given as a URL into the `host[:port]` form
+/// the allowlist expects, returning a warning when the entry was changed or
+/// still looks malformed.
+fn normalize_net_host(host: &str) -> (String, Option<String>) {
+  if host.contains("://") {
+    if let Some(normalized) = Url::parse(host).ok().and_then(|url| {
+      let host = url.host_str()?.to_string();
+      Some(match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host,
+      })
+    }) {
+      let warning = format!(
+        "--allow-net expects host[:port] entries, using {normalized} for {host}"
+      );
+      return (normalized, Some(warning));
+    }
   }
+  let is_host_and_port = Url::parse(&format!("internal://{host}"))
+    .map(|url| url.path().is_empty())
+    .unwrap_or(false)
+    || host.parse::<std::net::IpAddr>().is_ok();
+  if !is_host_and_port {
+    let warning =
+      format!("--allow-net entry {host} doesn't look like host[:port]");
+    return (host.to_string(), Some(warning));
+  }
+  (host.to_string(), None)
+}
 
-  let mut extra_files: Vec<(PathBuf, String)> = vec![];
+/// Whether `value` contains a NUL byte or a control character other than a
+/// tab, which could corrupt the generated scripts or how they are parsed.
+fn has_control_chars(value: &str) -> bool {
+  value.chars().any(|c| c.is_control() && c != '\t')
+}
 
-  let mut executable_args = vec!["run".to_string()];
-  executable_args.extend_from_slice(&flags.to_permission_args());
-  if let Some(url) = flags.location.as_ref() {
-    executable_args.push("--location".to_string());
-    executable_args.push(url.to_string());
+/// Rejects user input that ends up in the generated scripts when it
+/// contains NUL bytes or control characters, before anything is written.
+fn validate_script_input(
+  install_flags_global: &InstallFlagsGlobal,
+) -> Result<(), AnyError> {
+  if has_control_chars(&install_flags_global.module_url) {
+    return Err(install_error(
+      InstallErrorKind::InvalidModuleUrl,
+      format!(
+        "The module URL must not contain NUL bytes or control characters: {:?}",
+        install_flags_global.module_url
+      ),
+    ));
   }
-  if let Some(CaData::File(ca_file)) = &flags.ca_data {
-    executable_args.push("--cert".to_string());
-    executable_args.push(ca_file.to_owned())
+  for name in install_flags_global
+    .name
+    .iter()
+    .chain(&install_flags_global.aliases)
+  {
+    if has_control_chars(name) {
+      return Err(install_error(
+        InstallErrorKind::InvalidName,
+        format!("Invalid executable name: {name:?}"),
+      ));
+    }
   }
-  if let Some(log_level) = flags.log_level {
-    if log_level == Level::Error {
-      executable_args.push("--quiet".to_string());
-    } else {
-      executable_args.push("--log-level".to_string());
-      let log_level = match log_level {
-        Level::Debug => "debug",
-        Level::Info => "info",
-        _ => {
-          return Err(generic_error(format!("invalid log level {log_level}")))
-        }
-      };
-      executable_args.push(log_level.to_string());
+  let values = [
+    ("argument", &install_flags_global.args),
+    ("environment variable", &install_flags_global.env),
+  ];
+  for (kind, values) in values {
+    if let Some(value) = values.iter().find(|value| has_control_chars(value)) {
+      return Err(generic_error(format!(
+        "The {kind} {value:?} must not contain NUL bytes or control characters"
+      )));
     }
   }
+  Ok(())
+}
 
-  // we should avoid a default branch here to ensure we continue to cover any
-  // changes to this flag.
-  match flags.type_check_mode {
-    TypeCheckMode::All => executable_args.push("--check=all".to_string()),
-    TypeCheckMode::None => {}
-    TypeCheckMode::Local => executable_args.push("--check".to_string()),
-  }
+static ENV_KEY_RE: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").expect("invalid regex"));
 
-  if flags.unstable_config.legacy_flag_enabled {
-    executable_args.push("--unstable".to_string());
+/// Parses a `KEY=VALUE` environment variable provided to `--set-env`.
+fn parse_env_var(env_var: &str) -> Result<(String, String), AnyError> {
+  let Some((key, value)) = env_var.split_once('=') else {
+    return Err(generic_error(format!(
+      "Invalid environment variable, expected KEY=VALUE: {env_var}"
+    )));
+  };
+  if !ENV_KEY_RE.is_match(key) {
+    return Err(generic_error(format!(
+      "Invalid environment variable name: {key}"
+    )));
   }
-
-  for feature in &flags.unstable_config.features {
-    executable_args.push(format!("--unstable-{}", feature));
+  if value.contains(['\n', '\r']) {
+    return Err(generic_error(format!(
+      "The value of environment variable {key} must be a single line"
+    )));
   }
+  Ok((key.to_string(), value.to_string()))
+}
 
-  if flags.no_remote {
-    executable_args.push("--no-remote".to_string());
+/// The maximum length of a command line accepted by cmd.exe.
+const CMD_LINE_LIMIT: usize = 8191;
+
+/// Room left for the arguments passed to a `.cmd` wrapper at runtime. The
+/// baked-in invocation using more than the rest of the limit is warned about.
+const CMD_LINE_RUNTIME_ARGS_ROOM: usize = 1024;
+
+/// Renders the line of the `.cmd` wrapper that invokes deno.
+fn cmd_invocation(shim_data: &ShimData) -> String {
+  let mut args: Vec<String> = shim_data
+    .args
+    .iter()
+    .map(|c| match relocatable_arg(shim_data, c) {
+      Some((prefix, rest)) => format!(
+        "\"{}%~dp0{}\"",
+        prefix.replace('%', "%%"),
+        rest.replace('%', "%%")
+      ),
+      None => format!("\"{}\"", c.replace('%', "%%")),
+    })
+    .collect();
+  if shim_data.grant_state_file.is_some() {
+    args.insert(grant_flags_index(shim_data), "%grants%".to_string());
   }
+  format!("@deno {} %*", args.join(" "))
+}
 
-  if flags.no_npm {
-    executable_args.push("--no-npm".to_string());
+/// With `--relocatable`, splits an argument referencing a file in the
+/// installation directory into the part before the path (e.g.
+/// `--allow-write=`) and the path relative to the installation directory,
+/// so that the executables can reference it relative to their own location.
+fn relocatable_arg(
+  shim_data: &ShimData,
+  arg: &str,
+) -> Option<(String, String)> {
+  if !shim_data.relocatable {
+    return None;
   }
+  let (prefix, path) = match arg.split_once('=') {
+    Some((flag, value)) if flag.starts_with("--") => {
+      (format!("{flag}="), value)
+    }
+    _ => (String::new(), arg),
+  };
+  let rest = Path::new(path)
+    .strip_prefix(&shim_data.installation_dir)
+    .ok()?;
+  Some((prefix, rest.to_string_lossy().to_string()))
+}
 
-  if flags.lock_write {
-    executable_args.push("--lock-write".to_string());
+/// Returns a warning when the `.cmd` wrapper's invocation of deno gets close
+/// to the command line length limit of cmd.exe, which silently truncates
+/// longer command lines at runtime. The length is measured with `%~dp0`
+/// expanded to the installation directory, as cmd.exe does before running
+/// the line.
+fn cmd_line_length_warning(shim_data: &ShimData) -> Option<String> {
+  let invocation = cmd_invocation(shim_data);
+  // `%~dp0` expands to the directory of the wrapper and a trailing separator
+  let dp0_len = shim_data.installation_dir.to_string_lossy().len() + 1;
+  let len = invocation.len()
+    + invocation.matches("%~dp0").count() * dp0_len.saturating_sub(5);
+  if len + CMD_LINE_RUNTIME_ARGS_ROOM <= CMD_LINE_LIMIT {
+    return None;
   }
+  Some(format!(
+    "The command line generated for {} is {len} characters long, close to the {CMD_LINE_LIMIT} character limit of cmd.exe. It may get truncated when run; consider moving some of the arguments into a config file (--config).",
+    shim_data.name
+  ))
+}
 
-  if flags.cached_only {
-    executable_args.push("--cached-only".to_string());
+/// Shell lines that run before deno is invoked.
+fn sh_preamble(shim_data: &ShimData) -> String {
+  let mut preamble = String::new();
+  if let Some(version) = &shim_data.min_deno_version {
+    preamble.push_str(&sh_version_check(&shim_data.name, version));
+  }
+  if let Some(working_dir) = &shim_data.working_dir {
+    preamble.push_str(&format!(
+      "cd {} || exit 1\n",
+      shell_escape::unix::escape(working_dir.to_string_lossy())
+    ));
   }
+  if let Some(pre_run) = &shim_data.pre_run {
+    preamble.push_str(pre_run);
+    preamble.push('\n');
+  }
+  if let Some(state_file) = &shim_data.grant_state_file {
+    let state_file = state_file.to_string_lossy();
+    let state_file = match relocatable_arg(shim_data, &state_file) {
+      Some((_, rest)) => format!(
+        "\"$(dirname \"$0\")\"/{}",
+        shell_escape::unix::escape(rest.replace('\\', "/").into())
+      ),
+      None => shell_escape::unix::escape(state_file).into_owned(),
+    };
+    // the program can write the state file, so only the exact flags it
+    // records are read from it
+    let recorded = RECORDED_PERMISSIONS
+      .iter()
+      .map(|name| format!("--allow-{name}"))
+      .collect::<Vec<_>>()
+      .join("|");
+    preamble.push_str(&format!(
+      r#"grants=
+if [ -f {state_file} ]; then
+  while IFS= read -r grant || [ -n "$grant" ]; do
+    case "$grant" in
+      {recorded}) grants="$grants $grant" ;;
+    esac
+  done < {state_file}
+fi
+"#
+    ));
+  }
+  preamble
+}
 
-  if flags.frozen_lockfile {
-    executable_args.push("--frozen".to_string());
+/// Batch lines that run before deno is invoked.
+#[cfg(windows)]
+fn cmd_preamble(shim_data: &ShimData) -> String {
+  let mut preamble = String::new();
+  if let Some(version) = &shim_data.min_deno_version {
+    preamble.push_str(&cmd_version_check(&shim_data.name, version));
+  }
+  for (key, value) in &shim_data.env {
+    preamble.push_str(&format!(
+      "@set \"{}={}\"\n",
+      key,
+      value.replace('%', "%%")
+    ));
+  }
+  if let Some(working_dir) = &shim_data.working_dir {
+    preamble.push_str(&format!(
+      "@cd /d \"{}\" || exit /b 1\n",
+      working_dir.to_string_lossy().replace('%', "%%")
+    ));
+  }
+  if let Some(pre_run) = &shim_data.pre_run {
+    preamble.push('@');
+    preamble.push_str(pre_run);
+    preamble.push('\n');
+  }
+  if let Some(state_file) = &shim_data.grant_state_file {
+    let state_file = state_file.to_string_lossy();
+    let state_file = match relocatable_arg(shim_data, &state_file) {
+      Some((_, rest)) => format!("%~dp0{}", rest.replace('%', "%%")),
+      None => state_file.replace('%', "%%"),
+    };
+    // the program can write the state file, so only the exact flags it
+    // records are read from it, never its text
+    preamble.push_str(&format!(
+      "@set \"grants=\"\n@for %%p in ({}) do @findstr /l /x /c:\"--allow-%%p\" \"{state_file}\" >nul 2>&1 && call set \"grants=%%grants%% --allow-%%p\"\n",
+      RECORDED_PERMISSIONS.join(" ")
+    ));
   }
+  preamble
+}
 
-  if resolve_no_prompt(&flags.permissions) {
-    executable_args.push("--no-prompt".to_string());
+/// Encodes a version as a single number so that the generated scripts can
+/// compare versions with plain integer comparisons.
+fn version_check_number(version: &Version) -> u64 {
+  version.major * 1_000_000 + version.minor * 1_000 + version.patch
+}
+
+/// Shell snippet that exits early when the running deno is older than
+/// `min_version`.
+fn sh_version_check(name: &str, min_version: &Version) -> String {
+  format!(
+    r#"deno_version=$(deno --version | head -n 1 | cut -d ' ' -f 2)
+if [ "$(echo "$deno_version" | awk -F '[.+-]' '{{ print $1 * 1000000 + $2 * 1000 + $3 }}')" -lt {} ]; then
+  echo "{} requires deno {} or newer (found $deno_version)" >&2
+  exit 1
+fi
+"#,
+    version_check_number(min_version),
+    name,
+    min_version,
+  )
+}
+
+/// Batch snippet that exits early when the running deno is older than
+/// `min_version`.
+#[cfg(windows)]
+fn cmd_version_check(name: &str, min_version: &Version) -> String {
+  format!(
+    r#"@for /f "tokens=2" %%v in ('deno --version ^| findstr /b deno') do @set "DENO_VERSION=%%v"
+@for /f "tokens=1-3 delims=.+-" %%a in ("%DENO_VERSION%") do @set /a "DENO_VERSION_NUM=%%a*1000000+%%b*1000+%%c" >nul
+@if %DENO_VERSION_NUM% LSS {} (
+  echo {} requires deno {} or newer ^(found %DENO_VERSION%^) 1>&2
+  exit /b 1
+)
+"#,
+    version_check_number(min_version),
+    name,
+    min_version,
+  )
+}
+
+/// Resolves the root directory executables are installed into (in its
+/// `bin` subdirectory) when no `--root` is provided. In order of precedence:
+///
+/// 1. `$DENO_INSTALL_ROOT`
+/// 2. `$HOME/.deno`, when it exists (Linux only)
+/// 3. `$XDG_DATA_HOME/deno` (Linux only)
+/// 4. `$HOME/.deno` (`%USERPROFILE%\.deno` on Windows)
+pub fn get_installer_root() -> Result<PathBuf, AnyError> {
+  get_installer_root_with_source().map(|(root, _)| root)
+}
+
+/// Resolves the installation root like [`get_installer_root`], along with
+/// the environment variable it was derived from.
+fn get_installer_root_with_source() -> Result<(PathBuf, &'static str), AnyError>
+{
+  match get_env_installer_root()? {
+    Some(root) => Ok((root, "DENO_INSTALL_ROOT")),
+    None => get_default_installer_root_with_source(),
   }
+}
 
-  if !flags.v8_flags.is_empty() {
-    executable_args.push(format!("--v8-flags={}", flags.v8_flags.join(",")));
+/// Resolves a `--root` relative to the current directory, or the default
+/// installation root when it isn't provided.
+fn resolve_root(root: Option<impl AsRef<Path>>) -> Result<PathBuf, AnyError> {
+  match root {
+    Some(root) => {
+      let cwd = std::env::current_dir().context("Unable to get CWD")?;
+      canonicalize_path_maybe_not_exists(&cwd.join(root))
+    }
+    None => get_installer_root(),
   }
+}
 
-  if let Some(seed) = flags.seed {
-    executable_args.push("--seed".to_string());
-    executable_args.push(seed.to_string());
+/// Returns the directory of `root` executables are installed to: `bin`,
+/// unless overridden by `DENO_INSTALL_BIN`.
+fn get_installation_dir(root: &Path) -> Result<PathBuf, AnyError> {
+  installation_dir_with_bin(root, env::var("DENO_INSTALL_BIN").ok().as_deref())
+}
+
+fn installation_dir_with_bin(
+  root: &Path,
+  bin: Option<&str>,
+) -> Result<PathBuf, AnyError> {
+  let Some(bin) = bin.filter(|bin| !bin.is_empty()) else {
+    return Ok(root.join("bin"));
+  };
+  let mut components = Path::new(bin).components();
+  match (components.next(), components.next()) {
+    (Some(std::path::Component::Normal(_)), None) => Ok(root.join(bin)),
+    _ => Err(generic_error(format!(
+      "Invalid bin directory in DENO_INSTALL_BIN: {bin}. Expected a single directory name."
+    ))),
   }
+}
 
-  if let Some(inspect) = flags.inspect {
-    executable_args.push(format!("--inspect={inspect}"));
+/// Returns the installation root set by `DENO_INSTALL_ROOT`, if any.
+fn get_env_installer_root() -> Result<Option<PathBuf>, AnyError> {
+  if let Ok(env_dir) = env::var("DENO_INSTALL_ROOT") {
+    if !env_dir.is_empty() {
+      let root = canonicalize_path_maybe_not_exists(&PathBuf::from(&env_dir))
+        .with_context(|| {
+        format!("Invalid installation root in DENO_INSTALL_ROOT: {env_dir}")
+      })?;
+      return Ok(Some(root));
+    }
   }
+  Ok(None)
+}
 
-  if let Some(inspect_brk) = flags.inspect_brk {
-    executable_args.push(format!("--inspect-brk={inspect_brk}"));
+/// Returns the installation root used when neither `--root` nor
+/// `DENO_INSTALL_ROOT` are provided.
+fn get_default_installer_root() -> Result<PathBuf, AnyError> {
+  get_default_installer_root_with_source().map(|(root, _)| root)
+}
+
+fn get_default_installer_root_with_source(
+) -> Result<(PathBuf, &'static str), AnyError> {
+  // Note: on Windows, the $HOME environment variable may be set by users or by
+  // third party software, but it is non-standard and should not be relied upon.
+  let home_env_var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+  let home_root = env::var_os(home_env_var)
+    .filter(|home| !home.is_empty())
+    .map(|home| PathBuf::from(home).join(".deno"));
+
+  if cfg!(target_os = "linux") {
+    // existing installations in ~/.deno stay where they are
+    if let Some(home_root) = home_root.as_ref().filter(|root| root.is_dir()) {
+      return Ok((home_root.clone(), home_env_var));
+    }
+    // relative paths are invalid according to the XDG base directory spec
+    if let Some(data_home) = env::var_os("XDG_DATA_HOME")
+      .map(PathBuf::from)
+      .filter(|path| path.is_absolute())
+    {
+      return Ok((data_home.join("deno"), "XDG_DATA_HOME"));
+    }
   }
 
-  if let Some(import_map_path) = &flags.import_map_path {
-    let import_map_url = resolve_url_or_path(import_map_path, &cwd)?;
-    executable_args.push("--import-map".to_string());
-    executable_args.push(import_map_url.to_string());
+  match home_root {
+    Some(home_root) => Ok((home_root, home_env_var)),
+    None => Err(generic_error(format!(
+      "Unable to determine the installation root: ${home_env_var} is not defined. Set DENO_INSTALL_ROOT or use --root."
+    ))),
   }
+}
 
-  if let ConfigFlag::Path(config_path) = &flags.config_flag {
-    let copy_path = get_hidden_file_with_ext(&file_path, "deno.json");
-    executable_args.push("--config".to_string());
-    executable_args.push(copy_path.to_str().unwrap().to_string());
-    extra_files.push((
-      copy_path,
-      fs::read_to_string(config_path)
-        .with_context(|| format!("error reading {config_path}"))?,
+/// Describes how the installation root, bin directory and shell were resolved
+/// from the environment, for `--print-env`.
+fn render_install_env(root: Option<&str>) -> Result<String, AnyError> {
+  let (root, source) = match root {
+    Some(root) => (resolve_root(Some(root))?, "--root"),
+    None => get_installer_root_with_source()?,
+  };
+  let installation_dir = get_installation_dir(&root)?;
+  let bin_source = match env::var("DENO_INSTALL_BIN") {
+    Ok(bin) if !bin.is_empty() => "DENO_INSTALL_BIN",
+    _ => "default",
+  };
+  let shell = env::var("SHELL").ok().filter(|shell| !shell.is_empty());
+  Ok(format!(
+    "root: {}\nroot source: {}\nbin dir: {}\nbin dir source: {}\nshell: {}\nbin dir on PATH: {}\n",
+    root.display(),
+    source,
+    installation_dir.display(),
+    bin_source,
+    shell.as_deref().unwrap_or("unknown"),
+    if is_in_path(&installation_dir) { "yes" } else { "no" },
+  ))
+}
+
+/// Returns whether an executable named `name` is installed in the `bin`
+/// directory of `root`, or of the default installation root when `root` is
+/// not provided.
+pub fn is_installed(
+  name: &str,
+  root: Option<PathBuf>,
+) -> Result<bool, AnyError> {
+  Ok(wrapper_path(name, root)?.is_file())
+}
+
+/// Returns the absolute path of the installed executable named `name`, like
+/// `which` does for executables on the PATH. Errors if it isn't installed.
+pub fn which(name: &str, root: Option<PathBuf>) -> Result<PathBuf, AnyError> {
+  let file_path = wrapper_path(name, root)?;
+  if !file_path.is_file() {
+    return Err(install_error(
+      InstallErrorKind::NotFound,
+      format!("{name} is not installed"),
     ));
-  } else {
-    executable_args.push("--no-config".to_string());
   }
+  Ok(file_path)
+}
 
-  if flags.no_lock {
-    executable_args.push("--no-lock".to_string());
-  } else if flags.lock.is_some()
-    // always use a lockfile for an npm entrypoint unless --no-lock
-    || NpmPackageReqReference::from_specifier(&module_url).is_ok()
-  {
-    let copy_path = get_hidden_file_with_ext(&file_path, "lock.json");
-    executable_args.push("--lock".to_string());
-    executable_args.push(copy_path.to_str().unwrap().to_string());
-
-    if let Some(lock_path) = &flags.lock {
-      extra_files.push((
-        copy_path,
-        fs::read_to_string(lock_path)
-          .with_context(|| format!("error reading {}", lock_path))?,
+/// Records in the metadata of the installation of `name` that it was
+/// verified now, after checking that its executables still exist. Nothing
+/// else is regenerated.
+pub fn touch(name: &str, root: Option<PathBuf>) -> Result<(), AnyError> {
+  let file_path = wrapper_path(name, root)?;
+  let Some(mut metadata) = read_metadata(&file_path) else {
+    return Err(install_error(
+      InstallErrorKind::NotFound,
+      format!("{name} is not installed"),
+    ));
+  };
+  let installation_dir = file_path.parent().unwrap();
+  for name in std::iter::once(&metadata.name).chain(&metadata.aliases) {
+    let path = executable_path(installation_dir, name);
+    if !path.is_file() {
+      return Err(install_error(
+        InstallErrorKind::NotFound,
+        format!("{} is missing, reinstall {}", path.display(), metadata.name),
       ));
-    } else {
-      // Provide an empty lockfile so that this overwrites any existing lockfile
-      // from a previous installation. This will get populated on first run.
-      extra_files.push((copy_path, "{}".to_string()));
     }
   }
+  metadata.last_verified = Some(
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+  );
+  let metadata_path = get_metadata_path(&file_path);
+  let fs = &RealInstallerFs;
+  fs.write(&metadata_path, &serde_json::to_string_pretty(&metadata)?)
+    .with_context(|| format!("Failed to write {}", metadata_path.display()))?;
+  update_install_index(fs, installation_dir);
+  Ok(())
+}
 
-  executable_args.push(module_url.to_string());
-  executable_args.extend_from_slice(&install_flags_global.args);
+/// The path of the executable named `name`: on Windows, the `.cmd` file.
+fn executable_path(installation_dir: &Path, name: &str) -> PathBuf {
+  let file_path = installation_dir.join(name);
+  if cfg!(windows) {
+    file_path.with_extension("cmd")
+  } else {
+    file_path
+  }
+}
 
-  Ok(ShimData {
-    name,
-    installation_dir,
-    file_path,
-    args: executable_args,
-    extra_files,
-  })
+/// The path the executable named `name` is installed at, whether or not it
+/// exists.
+fn wrapper_path(
+  name: &str,
+  root: Option<PathBuf>,
+) -> Result<PathBuf, AnyError> {
+  let root = resolve_root(root)?;
+  ensure_safe_name(name)?;
+  Ok(executable_path(&get_installation_dir(&root)?, name))
 }
 
-fn get_hidden_file_with_ext(file_path: &Path, ext: &str) -> PathBuf {
-  // use a dot file to prevent the file from showing up in some
-  // users shell auto-complete since this directory is on the PATH
-  file_path
-    .with_file_name(format!(
-      ".{}",
-      file_path.file_name().unwrap().to_string_lossy()
-    ))
-    .with_extension(ext)
+/// Returns which of the candidate `names` are already installed in `root`,
+/// in the same order as `names`.
+pub fn check_name_availability(
+  names: &[String],
+  root: Option<PathBuf>,
+) -> Result<Vec<(String, bool)>, AnyError> {
+  names
+    .iter()
+    .map(|name| {
+      let installed = is_installed(name, root.clone())?;
+      Ok((name.clone(), !installed))
+    })
+    .collect()
 }
 
-fn is_in_path(dir: &Path) -> bool {
-  if let Some(paths) = env::var_os("PATH") {
-    for p in env::split_paths(&paths) {
-      if *dir == p {
-        return true;
-      }
-    }
-  }
-  false
+const INSTALL_DEFAULTS_FILE_NAME: &str = "install.json";
+
+/// Defaults for `deno install` options, read from an `install.json` file in
+/// the default installation root. Options provided on the command line
+/// always take precedence.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+struct InstallDefaults {
+  root: Option<String>,
+  /// Which executables to write on Windows, like `--output-format`.
+  shell: Option<String>,
+  /// Octal permissions of created directories, like
+  /// `--wrapper-dir-permissions`.
+  mode: Option<String>,
+  no_shadow: Option<bool>,
 }
 
-#[cfg(test)]
+fn apply_install_defaults(
+  mut install_flags_global: InstallFlagsGlobal,
+  defaults_path: &Path,
+) -> Result<InstallFlagsGlobal, AnyError> {
+  let text = match fs::read_to_string(defaults_path) {
+    Ok(text) => text,
+    Err(err) if err.kind() == io::ErrorKind::NotFound => {
+      return Ok(install_flags_global)
+    }
+    Err(err) => {
+      return Err(err)
+        .with_context(|| format!("error reading {}", defaults_path.display()))
+    }
+  };
+  let defaults: InstallDefaults = serde_json::from_str(&text)
+    .with_context(|| format!("error parsing {}", defaults_path.display()))?;
+
+  if install_flags_global.root.is_none() {
+    // relative roots are resolved against the defaults file's directory
+    install_flags_global.root =
+      defaults.root.map(|root| match defaults_path.parent() {
+        Some(dir) => dir.join(root).to_string_lossy().to_string(),
+        None => root,
+      });
+  }
+  if install_flags_global.output_format == InstallOutputFormat::All {
+    install_flags_global.output_format = match defaults.shell.as_deref() {
+      None | Some("all") => InstallOutputFormat::All,
+      Some("sh") => InstallOutputFormat::Sh,
+      Some("cmd") => InstallOutputFormat::Cmd,
+      Some(shell) => {
+        return Err(generic_error(format!(
+          "error parsing {}: invalid shell {shell:?}, expected \"all\", \"sh\" or \"cmd\"",
+          defaults_path.display()
+        )))
+      }
+    };
+  }
+  if install_flags_global.wrapper_dir_permissions.is_none() {
+    if let Some(mode) = defaults.mode {
+      let digits = mode.strip_prefix("0o").unwrap_or(&mode);
+      match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o777 => {
+          install_flags_global.wrapper_dir_permissions = Some(mode);
+        }
+        _ => {
+          return Err(generic_error(format!(
+            "error parsing {}: invalid octal mode {mode:?}",
+            defaults_path.display()
+          )))
+        }
+      }
+    }
+  }
+  if !install_flags_global.no_shadow && !install_flags_global.allow_shadow {
+    install_flags_global.no_shadow = defaults.no_shadow.unwrap_or(false);
+  }
+  Ok(install_flags_global)
+}
+
+/// Returns the `name` declared in a `deno.json` or `package.json` next to a
+/// local module (or inside it, for a directory). The scope of a scoped name
+/// like `@scope/tool` is dropped.
+fn infer_name_from_local_manifest(module_path: &Path) -> Option<String> {
+  #[derive(Deserialize)]
+  struct NamedManifest {
+    name: Option<String>,
+  }
+
+  let dir = if module_path.is_dir() {
+    module_path
+  } else {
+    module_path.parent()?
+  };
+  ["deno.json", "package.json"].iter().find_map(|file_name| {
+    let text = fs::read_to_string(dir.join(file_name)).ok()?;
+    let name = serde_json::from_str::<NamedManifest>(&text).ok()?.name?;
+    let name = match name.split_once('/') {
+      Some((scope, name)) if scope.starts_with('@') => name.to_string(),
+      _ => name,
+    };
+    Some(name).filter(|name| !name.is_empty())
+  })
+}
+
+/// File stems too generic to name an executable after, like `main.ts`. The
+/// name of their directory is used instead.
+const GENERIC_STEMS: [&str; 4] = ["main", "mod", "index", "cli"];
+
+pub async fn infer_name_from_url(
+  http_client_provider: &HttpClientProvider,
+  url: &Url,
+) -> Option<String> {
+  infer_name_from_url_with_generic_names(
+    http_client_provider,
+    url,
+    &[],
+    &[],
+    DEFAULT_NETWORK_TIMEOUT,
+  )
+  .await
+}
+
+/// Like [`infer_name_from_url`], additionally treating `generic_stems` as too
+/// generic to name the executable after, and skipping the `generic_parents`
+/// directories, like `src` in `tool/src/main.ts`, when naming it after the
+/// directory of a generic file. The redirect lookup of URLs without a path
+/// gives up after `timeout`.
+async fn infer_name_from_url_with_generic_names(
+  http_client_provider: &HttpClientProvider,
+  url: &Url,
+  generic_stems: &[String],
+  generic_parents: &[String],
+  timeout: Duration,
+) -> Option<String> {
+  // If there's an absolute url with no path, eg. https://my-cli.com
+  // perform a request, and see if it redirects another file instead.
+  let mut url = url.clone();
+
+  if url.path() == "/" {
+    if let Ok(client) = http_client_provider.get_or_create() {
+      if let Ok(redirected_url) = with_timeout(
+        timeout,
+        "resolving the redirect of",
+        &url,
+        client.get_redirected_url(url.clone(), None),
+      )
+      .await
+      {
+        url = redirected_url;
+      }
+    }
+  }
+
+  if let Ok(npm_ref) = NpmPackageReqReference::from_specifier(&url) {
+    let npm_ref = npm_ref.into_inner();
+    if let Some(sub_path) = npm_ref.sub_path {
+      if !sub_path.contains('/') {
+        return Some(sub_path);
+      }
+    }
+    if !npm_ref.req.name.contains('/') {
+      return Some(npm_ref.req.name);
+    }
+    return None;
+  }
+
+  if let Ok(path) = url.to_file_path() {
+    if let Some(name) = infer_name_from_local_manifest(&path) {
+      return Some(name);
+    }
+  }
+
+  let percent_decode = percent_encoding::percent_decode(url.path().as_bytes());
+  #[cfg(unix)]
+  let path = {
+    use std::os::unix::prelude::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(
+      percent_decode.collect::<Vec<u8>>(),
+    ))
+  };
+  #[cfg(windows)]
+  let path = PathBuf::from(percent_decode.decode_utf8_lossy().as_ref());
+
+  let mut stem = path.file_stem()?.to_string_lossy();
+  let is_generic_stem = GENERIC_STEMS.contains(&&*stem)
+    || generic_stems.iter().any(|generic| *generic == stem);
+  if is_generic_stem {
+    let parent_names = path
+      .ancestors()
+      .skip(1)
+      .filter_map(|dir| dir.file_name())
+      .map(|name| name.to_string_lossy())
+      .collect::<Vec<_>>();
+    // fall back to the closest directory when all of them are generic
+    if let Some(parent_name) = parent_names
+      .iter()
+      .find(|name| !generic_parents.iter().any(|generic| generic == *name))
+      .or(parent_names.first())
+    {
+      stem = parent_name.clone();
+    }
+  }
+
+  // if the name ends with a version (e.g. `foo@1.0.0`) we strip it and use the
+  // former part as the inferred name. Only a trailing suffix that looks like a
+  // version is stripped, so an `@` elsewhere in the name is left untouched.
+  if let Some(captures) = VERSION_SUFFIX_RE.captures(&stem) {
+    stem = captures[1].to_string().into();
+  }
+
+  Some(stem.to_string())
+}
+
+pub async fn uninstall(
+  flags: Arc<Flags>,
+  uninstall_flags: UninstallFlags,
+) -> Result<(), AnyError> {
+  if !uninstall_flags.global {
+    log::warn!("⚠️ `deno install` behavior will change in Deno 2. To preserve the current behavior use the `-g` or `--global` flag.");
+  }
+
+  let uninstall_flags = match uninstall_flags.kind {
+    UninstallKind::Global(flags) => flags,
+    UninstallKind::Local => unreachable!(),
+  };
+
+  let root = resolve_root(uninstall_flags.root.as_ref())?;
+  let installation_dir = get_installation_dir(&root)?;
+
+  // ensure directory exists
+  if let Ok(metadata) = fs::metadata(&installation_dir) {
+    if !metadata.is_dir() {
+      return Err(generic_error("Installation path is not a directory"));
+    }
+  }
+
+  if uninstall_flags.prune {
+    let factory = CliFactory::from_flags(flags);
+    let _install_lock = lock_installation_dir(&installation_dir).await;
+    prune(
+      factory.http_client_provider(),
+      &installation_dir,
+      PruneOptions {
+        dry_run: uninstall_flags.dry_run,
+        check_remote: uninstall_flags.check_remote,
+        timeout: uninstall_flags
+          .timeout
+          .map(Duration::from_secs)
+          .unwrap_or(DEFAULT_NETWORK_TIMEOUT),
+      },
+    )
+    .await?;
+    return Ok(());
+  }
+
+  let cwd = std::env::current_dir().context("Unable to get CWD")?;
+  let report_file = uninstall_flags
+    .report_file
+    .as_ref()
+    .map(|report_file| cwd.join(report_file));
+
+  if uninstall_flags.all {
+    let installed = match &report_file {
+      Some(_) => read_installed_metadata(&installation_dir)?,
+      None => Vec::new(),
+    };
+    let removed = {
+      let _install_lock = lock_installation_dir(&installation_dir).await;
+      uninstall_all(&installation_dir)?
+    };
+    if let Some(report_file) = &report_file {
+      for metadata in installed
+        .iter()
+        .filter(|metadata| removed.contains(&metadata.name))
+      {
+        append_install_report(
+          report_file,
+          &InstallReport {
+            timestamp: report_timestamp(),
+            action: "uninstall",
+            name: Some(&metadata.name),
+            url: Some(&metadata.module_url),
+            args: &metadata.args,
+            outcome: "success",
+            error: None,
+          },
+        )?;
+      }
+    }
+    return Ok(());
+  }
+
+  let Some(name) = uninstall_flags.name else {
+    return Err(generic_error("An executable name was not provided."));
+  };
+
+  let metadata = match &report_file {
+    Some(_) if is_safe_name(&name) => {
+      read_metadata(&executable_path(&installation_dir, &name))
+    }
+    _ => None,
+  };
+  let factory = CliFactory::from_flags(flags);
+  let result = Installer::new(root, factory.http_client_provider().clone())
+    .uninstall(&name)
+    .await;
+  if let Some(report_file) = &report_file {
+    append_install_report(
+      report_file,
+      &InstallReport {
+        timestamp: report_timestamp(),
+        action: "uninstall",
+        name: Some(&name),
+        url: metadata
+          .as_ref()
+          .map(|metadata| metadata.module_url.as_str()),
+        args: metadata
+          .as_ref()
+          .map(|metadata| metadata.args.as_slice())
+          .unwrap_or_default(),
+        outcome: if result.is_ok() { "success" } else { "failure" },
+        error: result.as_ref().err().map(|err| format!("{err:#}")),
+      },
+    )?;
+  }
+  result?;
+
+  log::info!("✅ Successfully uninstalled {}", name);
+  Ok(())
+}
+
+/// Removes every installation in the installation directory. Executables
+/// that don't carry the marker written by `deno install` are left alone,
+/// as are files without installation metadata. Returns the removed names.
+fn uninstall_all(installation_dir: &Path) -> Result<Vec<String>, AnyError> {
+  let mut removed = Vec::new();
+  for metadata in read_installed_metadata(installation_dir)? {
+    if !is_safe_name(&metadata.name) {
+      continue;
+    }
+    let mut file_path = installation_dir.join(&metadata.name);
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+    if !is_generated_executable(&file_path) {
+      log::warn!(
+        "{} Skipping {} as it wasn't generated by deno install",
+        colors::yellow("Warning"),
+        file_path.display()
+      );
+      continue;
+    }
+    if remove_installation(&RealInstallerFs, installation_dir, &metadata.name)?
+    {
+      removed.push(metadata.name);
+    }
+  }
+  if removed.is_empty() {
+    log::info!("No installations found");
+  } else {
+    log::info!(
+      "✅ Successfully uninstalled {} installation(s)",
+      removed.len()
+    );
+  }
+  Ok(removed)
+}
+
+/// The comment marking files written by `deno install`. The version is bumped
+/// when the marker changes so that older markers keep being recognized.
+const GENERATED_MARKER: &str = "deno-install v1";
+
+/// The marker written by `deno install` before it was versioned.
+const LEGACY_GENERATED_MARKER: &str = "generated by deno install";
+
+/// Whether `text` contains a marker written by `deno install`.
+fn has_generated_marker(text: &str) -> bool {
+  text.contains(GENERATED_MARKER) || text.contains(LEGACY_GENERATED_MARKER)
+}
+
+/// Whether the executable at `file_path` contains the marker written by
+/// `deno install`.
+fn is_generated_executable(file_path: &Path) -> bool {
+  fs::read_to_string(file_path)
+    .map(|text| has_generated_marker(&text))
+    .unwrap_or(false)
+}
+
+/// Removes the executable(s) for `name` from the installation directory
+/// along with any companion files. Returns `false` if nothing was installed
+/// under that name.
+fn remove_installation(
+  fs: &dyn InstallerFs,
+  installation_dir: &Path,
+  name: &str,
+) -> Result<bool, AnyError> {
+  ensure_safe_name(name)?;
+  let file_path = installation_dir.join(name);
+  let metadata_path = get_metadata_path(&file_path);
+  let maybe_metadata = fs
+    .read_to_string(&metadata_path)
+    .ok()
+    .and_then(|text| serde_json::from_str::<InstallMetadata>(&text).ok());
+
+  if !remove_executable_files(fs, &file_path)? {
+    return Ok(false);
+  }
+
+  // There might be some extra files to delete
+  // Note: tsconfig.json is legacy. We renamed it to deno.json.
+  // Remove cleaning it up after January 2024
+  for ext in ["tsconfig.json", "deno.json", "lock.json"] {
+    let file_path = file_path.with_extension(ext);
+    if fs.exists(&file_path) {
+      fs.remove_file(&file_path)?;
+      log::info!("deleted {}", file_path.to_string_lossy());
+    }
+  }
+
+  // the companion files copied next to the executable
+  for ext in ["deno.json", "lock.json", "import_map.json"] {
+    let file_path = get_hidden_file_with_ext(&file_path, ext);
+    if fs.exists(&file_path) {
+      fs.remove_file(&file_path)?;
+      log::info!("deleted {}", file_path.to_string_lossy());
+    }
+  }
+
+  for ext in ["grants", "grant.js"] {
+    let file_path = get_hidden_file_with_ext(&file_path, ext);
+    if fs.exists(&file_path) {
+      fs.remove_file(&file_path)?;
+    }
+  }
+
+  if let Some(metadata) = maybe_metadata {
+    for alias in metadata.aliases.iter().filter(|alias| is_safe_name(alias)) {
+      remove_executable_files(fs, &installation_dir.join(alias))?;
+    }
+    // only remove desktop entries that weren't replaced by someone else
+    if let Some(desktop_entry) = metadata.desktop_entry.as_deref() {
+      let desktop_entry = Path::new(desktop_entry);
+      if fs
+        .read_to_string(desktop_entry)
+        .is_ok_and(|text| has_generated_marker(&text))
+      {
+        fs.remove_file(desktop_entry)?;
+        log::info!("deleted {}", desktop_entry.to_string_lossy());
+      }
+    }
+  }
+
+  if fs.exists(&metadata_path) {
+    fs.remove_file(&metadata_path)?;
+  }
+  update_install_index(fs, installation_dir);
+
+  Ok(true)
+}
+
+/// Removes the executable at `file_path` along with its `.cmd` and `.bat`
+/// variants on Windows. Returns `false` if none existed.
+fn remove_executable_files(
+  fs: &dyn InstallerFs,
+  file_path: &Path,
+) -> Result<bool, AnyError> {
+  let mut removed = false;
+
+  if fs.exists(file_path) {
+    fs.remove_file(file_path)?;
+    log::info!("deleted {}", file_path.to_string_lossy());
+    removed = true
+  };
+
+  if cfg!(windows) {
+    for ext in ["cmd", "bat"] {
+      let file_path = file_path.with_extension(ext);
+      if fs.exists(&file_path) {
+        fs.remove_file(&file_path)?;
+        log::info!("deleted {}", file_path.to_string_lossy());
+        removed = true
+      }
+    }
+  }
+
+  Ok(removed)
+}
+
+/// The JSON Schema of [`InstallMetadata`], printed by
+/// `deno install --json-schema`.
+const INSTALL_METADATA_SCHEMA: &str =
+  include_str!("../schemas/install-metadata.v1.json");
+
+/// Information about an installation that is stored alongside the
+/// executable so that it can be inspected later on.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InstallMetadata {
+  name: String,
+  module_url: String,
+  args: Vec<String>,
+  #[serde(default)]
+  aliases: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  min_deno_version: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pre_run: Option<String>,
+  /// Directory the executable changes to before running the module.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  working_dir: Option<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  companions: Vec<CompanionSource>,
+  /// `KEY=VALUE` environment variables set by the executable.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  env: Vec<String>,
+  /// The `--wrapper-comment` of the executable, joined into a single line.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  wrapper_comment: Option<String>,
+  /// The registry the module was published to, if it could be determined.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  registry: Option<RegistrySource>,
+  /// `ETag` of a remote module when installed with `--only-if-newer`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  etag: Option<String>,
+  /// `Last-Modified` of a remote module when installed with
+  /// `--only-if-newer`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  last_modified: Option<String>,
+  /// The detached signature a remote module was verified with when
+  /// installed with `--verify-signature`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  signature: Option<VerifiedSignature>,
+  /// When the installation was last verified with `--touch`, in RFC 3339
+  /// format.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  last_verified: Option<String>,
+  /// The path of the desktop entry written with `--desktop-entry`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  desktop_entry: Option<String>,
+  /// The `gh:` shorthand the module URL was expanded from.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  shorthand: Option<String>,
+  /// When the executable was installed, in RFC 3339 format.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  installed_at: Option<String>,
+  /// The file names of the executables written for the name, like the `.cmd`
+  /// file and the shell script on Windows.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  wrappers: Vec<String>,
+  /// The path of the `--template` the shell script was rendered from.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  template: Option<String>,
+  /// The deno flags the module was installed with, see
+  /// [`recorded_deno_flags`].
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  deno_flags: Vec<String>,
+}
+
+/// A successful verification of a module against its detached signature.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VerifiedSignature {
+  /// The base64 encoded Ed25519 public key.
+  public_key: String,
+  signature_url: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum RegistryKind {
+  DenoLandX,
+  Npm,
+  Jsr,
+}
+
+/// The registry package and exact version an installed module comes from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RegistrySource {
+  kind: RegistryKind,
+  package: String,
+  version: String,
+}
+
+static DENO_LAND_X_RE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(r"^https://deno\.land/x/([^/@]+)@([^/]+)/").expect("invalid regex")
+});
+
+static JSR_IO_RE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(r"^https://jsr\.io/(@[^/]+/[^/]+)/([^/]+)/")
+    .expect("invalid regex")
+});
+
+/// Determines the registry package of a module pinned to an exact version.
+fn detect_registry_source(module_url: &Url) -> Option<RegistrySource> {
+  let (kind, package, version) = if let Ok(npm_ref) =
+    NpmPackageReqReference::from_specifier(module_url)
+  {
+    let req = npm_ref.into_inner().req;
+    (RegistryKind::Npm, req.name, req.version_req.to_string())
+  } else if let Ok(jsr_ref) = JsrPackageReqReference::from_specifier(module_url)
+  {
+    let req = jsr_ref.into_inner().req;
+    (RegistryKind::Jsr, req.name, req.version_req.to_string())
+  } else if let Some(captures) = DENO_LAND_X_RE.captures(module_url.as_str()) {
+    (
+      RegistryKind::DenoLandX,
+      captures[1].to_string(),
+      captures[2].to_string(),
+    )
+  } else if let Some(captures) = JSR_IO_RE.captures(module_url.as_str()) {
+    (
+      RegistryKind::Jsr,
+      captures[1].to_string(),
+      captures[2].to_string(),
+    )
+  } else {
+    return None;
+  };
+  // only exact versions can be compared against the latest one
+  parse_registry_version(&version)?;
+  Some(RegistrySource {
+    kind,
+    package,
+    version,
+  })
+}
+
+fn parse_registry_version(version: &str) -> Option<Version> {
+  Version::parse_standard(version.strip_prefix('v').unwrap_or(version)).ok()
+}
+
+/// Base URLs of the registries queried for the latest versions.
+struct RegistryUrls {
+  deno_land_x: Url,
+  npm: Url,
+  jsr: Url,
+}
+
+impl Default for RegistryUrls {
+  fn default() -> Self {
+    Self {
+      deno_land_x: Url::parse("https://cdn.deno.land/").unwrap(),
+      npm: crate::args::npm_registry_url().clone(),
+      jsr: crate::args::jsr_url().clone(),
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+struct LatestVersionInfo {
+  latest: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NpmPackageInfo {
+  #[serde(rename = "dist-tags")]
+  dist_tags: LatestVersionInfo,
+}
+
+async fn fetch_latest_version(
+  http_client_provider: &HttpClientProvider,
+  registry_urls: &RegistryUrls,
+  source: &RegistrySource,
+  timeout: Duration,
+) -> Result<String, AnyError> {
+  let client = http_client_provider.get_or_create()?;
+  let url = match source.kind {
+    RegistryKind::DenoLandX => registry_urls
+      .deno_land_x
+      .join(&format!("{}/meta/versions.json", source.package))?,
+    RegistryKind::Npm => registry_urls.npm.join(&source.package)?,
+    RegistryKind::Jsr => registry_urls
+      .jsr
+      .join(&format!("{}/meta.json", source.package))?,
+  };
+  let text = with_timeout(
+    timeout,
+    "fetching the latest version from",
+    &url,
+    client.download_text(url.clone()),
+  )
+  .await?;
+  let latest = match source.kind {
+    RegistryKind::Npm => {
+      serde_json::from_str::<NpmPackageInfo>(&text)?
+        .dist_tags
+        .latest
+    }
+    RegistryKind::DenoLandX | RegistryKind::Jsr => {
+      serde_json::from_str::<LatestVersionInfo>(&text)?.latest
+    }
+  };
+  Ok(latest)
+}
+
+/// Pins a `jsr:` module without an exact version to the newest published
+/// version matching its version requirement, so that the executable doesn't
+/// pick up new versions on every run. Returns `None` for other modules and
+/// ones that are pinned already.
+async fn pin_jsr_module(
+  http_client_provider: &HttpClientProvider,
+  registry_urls: &RegistryUrls,
+  module_url: &Url,
+  timeout: Duration,
+) -> Result<Option<Url>, AnyError> {
+  let Ok(jsr_ref) = JsrPackageReqReference::from_specifier(module_url) else {
+    return Ok(None);
+  };
+  let package_ref = jsr_ref.into_inner();
+  let req = &package_ref.req;
+  if Version::parse_standard(&req.version_req.to_string()).is_ok() {
+    return Ok(None);
+  }
+  let client = http_client_provider.get_or_create()?;
+  let url = registry_urls.jsr.join(&format!("{}/meta.json", req.name))?;
+  let text = with_timeout(
+    timeout,
+    "resolving the version of",
+    &url,
+    client.download_text(url.clone()),
+  )
+  .await?;
+  let package_info: JsrPackageInfo = serde_json::from_str(&text)
+    .with_context(|| format!("error parsing {url}"))?;
+  let version = package_info
+    .versions
+    .iter()
+    .filter(|(version, info)| {
+      !info.yanked
+        && req.version_req.tag().is_none()
+        && req.version_req.matches(version)
+    })
+    .map(|(version, _)| version)
+    .max()
+    .ok_or_else(|| {
+      install_error(
+        InstallErrorKind::InvalidModuleUrl,
+        format!(
+          "No published version of jsr:{} matches {}",
+          req.name, req.version_req
+        ),
+      )
+    })?;
+  let pinned = match &package_ref.sub_path {
+    Some(sub_path) => format!("jsr:{}@{version}/{sub_path}", req.name),
+    None => format!("jsr:{}@{version}", req.name),
+  };
+  Ok(Some(Url::parse(&pinned)?))
+}
+
+/// How long a single network request of the installer may take by default.
+const DEFAULT_NETWORK_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn network_timeout(install_flags_global: &InstallFlagsGlobal) -> Duration {
+  install_flags_global
+    .timeout
+    .map(Duration::from_secs)
+    .unwrap_or(DEFAULT_NETWORK_TIMEOUT)
+}
+
+/// Fails with an error naming the operation and URL when `future` doesn't
+/// complete within `timeout`.
+async fn with_timeout<T>(
+  timeout: Duration,
+  operation: &str,
+  url: &Url,
+  future: impl std::future::Future<Output = Result<T, AnyError>>,
+) -> Result<T, AnyError> {
+  match tokio::time::timeout(timeout, future).await {
+    Ok(result) => result,
+    Err(_) => Err(generic_error(format!(
+      "Timed out after {timeout:?} {operation} {url}"
+    ))),
+  }
+}
+
+/// A newer version of a registry package than the installed one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AvailableUpdate {
+  name: String,
+  current_version: String,
+  latest_version: String,
+}
+
+/// Queries the registries of the registry-backed installations in
+/// `installation_dir` for newer versions. Nothing is updated.
+async fn check_updates(
+  http_client_provider: &HttpClientProvider,
+  registry_urls: &RegistryUrls,
+  installation_dir: &Path,
+  timeout: Duration,
+) -> Result<Vec<AvailableUpdate>, AnyError> {
+  let mut updates = Vec::new();
+  for metadata in read_installed_metadata(installation_dir)? {
+    let Some(source) = &metadata.registry else {
+      continue;
+    };
+    let latest_version = match fetch_latest_version(
+      http_client_provider,
+      registry_urls,
+      source,
+      timeout,
+    )
+    .await
+    {
+      Ok(latest_version) => latest_version,
+      Err(err) => {
+        log::warn!(
+          "{} Failed checking {} for updates: {:#}",
+          colors::yellow("Warning"),
+          metadata.name,
+          err
+        );
+        continue;
+      }
+    };
+    let is_newer = match (
+      parse_registry_version(&source.version),
+      parse_registry_version(&latest_version),
+    ) {
+      (Some(current), Some(latest)) => latest > current,
+      _ => false,
+    };
+    if is_newer {
+      updates.push(AvailableUpdate {
+        name: metadata.name,
+        current_version: source.version.clone(),
+        latest_version,
+      });
+    }
+  }
+  Ok(updates)
+}
+
+/// A companion file (config, lockfile or import map) copied next to the
+/// executable, along with where it was copied from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CompanionSource {
+  path: PathBuf,
+  /// The path or URL of the original file.
+  source: String,
+  /// JSON merged over the original import map, if any.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  import_map_overlay: Option<String>,
+}
+
+impl CompanionSource {
+  /// Reads the contents the companion should have from its original source.
+  /// Downloading a remote import map gives up after `timeout`.
+  async fn read(
+    &self,
+    http_client_provider: &HttpClientProvider,
+    timeout: Duration,
+  ) -> Result<String, AnyError> {
+    let Some(overlay) = &self.import_map_overlay else {
+      return fs::read_to_string(&self.source)
+        .with_context(|| format!("error reading {}", self.source));
+    };
+
+    let overlay: serde_json::Value = serde_json::from_str(overlay)
+      .context("Invalid JSON provided to --import-map-overlay")?;
+    if !overlay.is_object() {
+      return Err(generic_error(
+        "The --import-map-overlay must be a JSON object",
+      ));
+    }
+    let import_map_url = Url::parse(&self.source)?;
+    let text = if import_map_url.scheme() == "file" {
+      let import_map_path = import_map_url.to_file_path().map_err(|_| {
+        generic_error(format!("Invalid file URL: {import_map_url}"))
+      })?;
+      fs::read_to_string(&import_map_path).with_context(|| {
+        format!("error reading {}", import_map_path.display())
+      })?
+    } else {
+      let client = http_client_provider.get_or_create()?;
+      with_timeout(
+        timeout,
+        "downloading",
+        &import_map_url,
+        client.download_text(import_map_url.clone()),
+      )
+      .await
+      .with_context(|| format!("error downloading {import_map_url}"))?
+    };
+    let mut import_map: serde_json::Value = serde_json::from_str(&text)
+      .with_context(|| format!("error parsing {import_map_url}"))?;
+    merge_json_objects(&mut import_map, overlay);
+    Ok(serde_json::to_string_pretty(&import_map)?)
+  }
+}
+
+/// Copies the companion files of the installation `name` again from their
+/// original sources, leaving the executable untouched. Returns the paths of
+/// the refreshed companions.
+async fn refresh_companions(
+  fs: &dyn InstallerFs,
+  http_client_provider: &HttpClientProvider,
+  name: &str,
+  root: &Path,
+  timeout: Duration,
+) -> Result<Vec<PathBuf>, AnyError> {
+  ensure_safe_name(name)?;
+  let file_path = get_installation_dir(root)?.join(name);
+  let Some(metadata) = read_metadata(&file_path) else {
+    return Err(generic_error(format!(
+      "No installation metadata found for {name}"
+    )));
+  };
+  let mut refreshed = Vec::with_capacity(metadata.companions.len());
+  for companion in metadata.companions {
+    let contents = companion.read(http_client_provider, timeout).await?;
+    fs.write(&companion.path, &contents)?;
+    log::info!("Refreshed {}", companion.path.display());
+    refreshed.push(companion.path);
+  }
+  Ok(refreshed)
+}
+
+/// Replaces the companion files of the installation `name` with the config,
+/// lockfile and import map provided in `flags`. The executable references
+/// its companions by stable paths, so it is left untouched.
+async fn overwrite_companions(
+  fs: &dyn InstallerFs,
+  http_client_provider: &HttpClientProvider,
+  flags: &Flags,
+  install_flags_global: &InstallFlagsGlobal,
+  name: &str,
+  root: &Path,
+) -> Result<Vec<PathBuf>, AnyError> {
+  let cwd = std::env::current_dir().context("Unable to get CWD")?;
+  let base_dir = resolve_base_dir(install_flags_global, &cwd);
+  ensure_safe_name(name)?;
+  let mut file_path = get_installation_dir(root)?.join(name);
+  if cfg!(windows) {
+    file_path = file_path.with_extension("cmd");
+  }
+  let executable = match fs.read_to_string(&file_path) {
+    Ok(executable) => executable,
+    Err(err) if err.kind() == io::ErrorKind::NotFound => {
+      return Err(install_error(
+        InstallErrorKind::NotFound,
+        format!("No installation found for {name}"),
+      ));
+    }
+    Err(err) => {
+      return Err(err)
+        .with_context(|| format!("Failed to read {}", file_path.display()));
+    }
+  };
+
+  let mut companions = Vec::new();
+  if let ConfigFlag::Path(config_path) = &flags.config_flag {
+    companions.push(CompanionSource {
+      path: get_hidden_file_with_ext(&file_path, "deno.json"),
+      source: base_dir.join(config_path).to_string_lossy().to_string(),
+      import_map_overlay: None,
+    });
+  }
+  if let Some(lock_path) = &flags.lock {
+    companions.push(CompanionSource {
+      path: get_hidden_file_with_ext(&file_path, "lock.json"),
+      source: base_dir.join(lock_path).to_string_lossy().to_string(),
+      import_map_overlay: None,
+    });
+  }
+  if let Some(import_map_path) = &flags.import_map_path {
+    companions.push(CompanionSource {
+      path: get_hidden_file_with_ext(&file_path, "import_map.json"),
+      source: resolve_url_or_path(import_map_path, &base_dir)?.to_string(),
+      import_map_overlay: Some(
+        install_flags_global
+          .import_map_overlay
+          .clone()
+          .unwrap_or_else(|| "{}".to_string()),
+      ),
+    });
+  }
+  if companions.is_empty() {
+    return Err(generic_error(
+      "Provide the new config (--config), lockfile (--lock) or import map (--import-map) to overwrite",
+    ));
+  }
+
+  let mut contents = Vec::with_capacity(companions.len());
+  for companion in &companions {
+    if !executable.contains(companion.path.to_string_lossy().as_ref()) {
+      return Err(generic_error(format!(
+        "The executable of {name} doesn't use {}. Reinstall it instead.",
+        companion.path.display()
+      )));
+    }
+    contents.push(
+      companion
+        .read(http_client_provider, network_timeout(install_flags_global))
+        .await?,
+    );
+  }
+
+  let mut metadata = read_metadata(&file_path);
+  let mut overwritten = Vec::with_capacity(companions.len());
+  for (companion, contents) in companions.into_iter().zip(contents) {
+    fs.write(&companion.path, &contents).with_context(|| {
+      format!("Failed to write {}", companion.path.display())
+    })?;
+    log::info!("Overwrote {}", companion.path.display());
+    overwritten.push(companion.path.clone());
+    if let Some(metadata) = &mut metadata {
+      metadata.companions.retain(|c| c.path != companion.path);
+      metadata.companions.push(companion);
+    }
+  }
+  if let Some(metadata) = metadata {
+    let metadata_path = get_metadata_path(&file_path);
+    fs.write(&metadata_path, &serde_json::to_string_pretty(&metadata)?)
+      .with_context(|| {
+        format!("Failed to write {}", metadata_path.display())
+      })?;
+    update_install_index(fs, &get_installation_dir(root)?);
+  }
+  Ok(overwritten)
+}
+
+/// Returns the offset of the `deno` command in an invocation line, skipping
+/// quoted text like the values of the environment variables set before it.
+fn find_deno_command(line: &str) -> Option<usize> {
+  let mut quote = None;
+  let mut escaped = false;
+  let mut word_start = true;
+  for (index, c) in line.char_indices() {
+    if escaped {
+      escaped = false;
+      word_start = false;
+      continue;
+    }
+    match (quote, c) {
+      (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+      (Some('\''), _) => {}
+      (_, '\\') => escaped = true,
+      (None, '\'' | '"') => quote = Some(c),
+      (None, _) if word_start && line[index..].starts_with("deno ") => {
+        return Some(index)
+      }
+      _ => {}
+    }
+    word_start = quote.is_none() && matches!(c, ' ' | '@');
+  }
+  None
+}
+
+/// Splits a generated wrapper script into the text before the arguments of
+/// its deno invocation, the arguments and the text after them. The
+/// invocation is the last line forwarding the wrapper's own arguments.
+fn split_wrapper_invocation(contents: &str) -> Option<(&str, &str, &str)> {
+  let mut line_start = 0;
+  let mut found = None;
+  for line in contents.split_inclusive('\n') {
+    let trimmed = line.trim_end_matches(['\r', '\n']);
+    let forwarded = trimmed
+      .strip_suffix(" \"$@\"")
+      .or_else(|| trimmed.strip_suffix(" %*"));
+    if let Some(invocation) = forwarded {
+      if let Some(deno) = find_deno_command(invocation) {
+        found = Some((
+          line_start + deno + "deno ".len(),
+          line_start + invocation.len(),
+        ));
+      }
+    }
+    line_start += line.len();
+  }
+  let (start, end) = found?;
+  Some((&contents[..start], &contents[start..end], &contents[end..]))
+}
+
+/// The shim data the wrapper template of an installation is rendered from,
+/// with a placeholder in place of the arguments of the deno invocation.
+fn template_shim_data(
+  installation_dir: &Path,
+  metadata: &InstallMetadata,
+) -> ShimData {
+  let file_path = executable_path(installation_dir, &metadata.name);
+  let grant_state_file = get_hidden_file_with_ext(&file_path, "grant.js")
+    .exists()
+    .then(|| get_hidden_file_with_ext(&file_path, "grants"));
+  let existing = fs::read_to_string(&file_path).unwrap_or_default();
+  // installations predating the list of wrappers used the default ones
+  let (bat, output_format) = if metadata.wrappers.is_empty() {
+    (false, InstallOutputFormat::All)
+  } else {
+    let has_wrapper =
+      |file_name: String| metadata.wrappers.contains(&file_name);
+    let cmd = has_wrapper(format!("{}.cmd", metadata.name));
+    let sh = has_wrapper(metadata.name.clone());
+    let output_format = match (cmd, sh) {
+      (true, false) => InstallOutputFormat::Cmd,
+      (false, true) => InstallOutputFormat::Sh,
+      _ => InstallOutputFormat::All,
+    };
+    (has_wrapper(format!("{}.bat", metadata.name)), output_format)
+  };
+  ShimData {
+    name: metadata.name.clone(),
+    installation_dir: installation_dir.to_path_buf(),
+    args: vec![String::new()],
+    extra_files: vec![],
+    alias_paths: vec![],
+    min_deno_version: metadata
+      .min_deno_version
+      .as_ref()
+      .and_then(|version| Version::parse_standard(version).ok()),
+    pre_run: metadata.pre_run.clone(),
+    working_dir: metadata.working_dir.as_ref().map(PathBuf::from),
+    env: metadata
+      .env
+      .iter()
+      .filter_map(|env_var| parse_env_var(env_var).ok())
+      .collect(),
+    wrapper_comment: metadata.wrapper_comment.clone(),
+    custom_template: None,
+    owner_only: false,
+    shebang_env: false,
+    cmd_crlf: existing.contains("\r\n"),
+    bat,
+    output_format,
+    companions_mode: InstallCompanionsMode::Overwrite,
+    grant_state_file,
+    relocatable: existing.contains("$(dirname \"$0\")")
+      || existing.contains("%~dp0"),
+    metadata: InstallMetadata {
+      args: vec![],
+      ..metadata.clone()
+    },
+    file_path,
+  }
+}
+
+/// Renders a script argument the way the executable forwards it to deno: as
+/// a quoted `.cmd` argument, or as a shell word.
+fn render_script_arg(arg: &str, cmd: bool) -> String {
+  if cmd {
+    format!("\"{}\"", arg.replace('%', "%%"))
+  } else if cfg!(windows) {
+    format!("\"{arg}\"")
+  } else {
+    shell_escape::unix::escape(arg.into()).into_owned()
+  }
+}
+
+/// Checks that the executables written for `name`, like the `.cmd` file and
+/// the shell script on Windows, are all present and forward the installed
+/// script arguments. Returns a description of every problem found.
+fn wrapper_variant_problems(
+  installation_dir: &Path,
+  name: &str,
+) -> Result<Vec<String>, AnyError> {
+  ensure_safe_name(name)?;
+  let Some(metadata) = read_metadata(&executable_path(installation_dir, name))
+  else {
+    return Err(install_error(
+      InstallErrorKind::NotFound,
+      format!("{name} is not installed"),
+    ));
+  };
+  // installations predating the list of wrappers used the default ones
+  let wrappers = if !metadata.wrappers.is_empty() {
+    metadata.wrappers.clone()
+  } else if cfg!(windows) {
+    vec![format!("{name}.cmd"), name.to_string()]
+  } else {
+    vec![name.to_string()]
+  };
+
+  let mut problems = Vec::new();
+  let mut cmd_contents = None;
+  let mut bat_contents = None;
+  for file_name in &wrappers {
+    let path = installation_dir.join(file_name);
+    let contents = match fs::read_to_string(&path) {
+      Ok(contents) => contents,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => {
+        problems.push(format!("{} is missing", path.display()));
+        continue;
+      }
+      Err(err) => {
+        return Err(err)
+          .with_context(|| format!("error reading {}", path.display()))
+      }
+    };
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    let is_cmd = matches!(extension, Some("cmd" | "bat"));
+    let script_args = metadata
+      .args
+      .iter()
+      .map(|arg| render_script_arg(arg, is_cmd))
+      .collect::<Vec<_>>()
+      .join(" ");
+    let forwards_args = match split_wrapper_invocation(&contents) {
+      Some((_, invocation, _)) => invocation.ends_with(&script_args),
+      // shebang scripts don't have baked-in script arguments
+      None => metadata.args.is_empty(),
+    };
+    if !forwards_args {
+      problems.push(format!(
+        "{} doesn't forward the installed arguments",
+        path.display()
+      ));
+    }
+    match extension {
+      Some("cmd") => cmd_contents = Some(contents),
+      Some("bat") => bat_contents = Some(contents),
+      _ => {}
+    }
+  }
+  if let (Some(cmd), Some(bat)) = (cmd_contents, bat_contents) {
+    if cmd != bat {
+      problems.push(format!("The .cmd and .bat files of {name} differ"));
+    }
+  }
+  Ok(problems)
+}
+
+/// Regenerates the executables in the installation directory whose wrapper
+/// template differs from the one rendered by this version of deno, returning
+/// the names of the regenerated installations. The arguments of the deno
+/// invocation are kept as they are, only the template around them is
+/// compared and replaced. Shebang scripts and executables without metadata
+/// are left alone.
+fn reinstall_outdated_templates(
+  fs: &dyn InstallerFs,
+  installation_dir: &Path,
+) -> Result<Vec<String>, AnyError> {
+  let mut reinstalled = Vec::new();
+  for metadata in read_installed_metadata(installation_dir)? {
+    if !is_safe_name(&metadata.name) {
+      continue;
+    }
+    // executables rendered from a custom template are left as they are
+    if metadata.template.is_some() {
+      continue;
+    }
+    let shim_data = template_shim_data(installation_dir, &metadata);
+    let mut executables = vec![shim_data.file_path.clone()];
+    for alias in metadata.aliases.iter().filter(|alias| is_safe_name(alias)) {
+      executables.push(executable_path(installation_dir, alias));
+    }
+    let mut regenerated = false;
+    for executable in executables {
+      for (path, rendered) in render_executable_files(&shim_data, &executable) {
+        let Ok(existing) = fs.read_to_string(&path) else {
+          continue;
+        };
+        let (Some((_, args, _)), Some((before, _, after))) = (
+          split_wrapper_invocation(&existing),
+          split_wrapper_invocation(&rendered),
+        ) else {
+          continue;
+        };
+        let regenerated_contents = format!("{before}{args}{after}");
+        if regenerated_contents == existing {
+          continue;
+        }
+        fs.write(&path, &regenerated_contents).with_context(|| {
+          format!("Failed to write executable at {}", path.display())
+        })?;
+        regenerated = true;
+      }
+    }
+    if regenerated {
+      reinstalled.push(metadata.name);
+    }
+  }
+  Ok(reinstalled)
+}
+
+/// How the arguments of the deno invocation are quoted in an executable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum WrapperArgQuoting {
+  /// A `.cmd` or `.bat` file.
+  Cmd,
+  /// A shell script.
+  Sh,
+  /// The `#!/usr/bin/env -S deno run` line of a shebang script.
+  Shebang,
+}
+
+/// Splits the arguments of a deno invocation into the ranges of its words
+/// and their unquoted values.
+fn wrapper_arg_words(
+  args: &str,
+  quoting: WrapperArgQuoting,
+) -> Vec<(std::ops::Range<usize>, String)> {
+  // on Windows, shell script arguments are only double quoted
+  let single_quotes = quoting != WrapperArgQuoting::Cmd && !cfg!(windows);
+  let mut words = Vec::new();
+  let mut word: Option<(usize, String)> = None;
+  let mut quote = None;
+  let mut chars = args.char_indices().peekable();
+  while let Some((index, c)) = chars.next() {
+    if quote.is_none() && c == ' ' {
+      if let Some((start, value)) = word.take() {
+        words.push((start..index, value));
+      }
+      continue;
+    }
+    let (_, value) = word.get_or_insert_with(|| (index, String::new()));
+    let escapes = match quoting {
+      WrapperArgQuoting::Cmd => false,
+      WrapperArgQuoting::Sh => single_quotes && quote != Some('\''),
+      WrapperArgQuoting::Shebang => true,
+    };
+    match (quote, c) {
+      (_, '%')
+        if quoting == WrapperArgQuoting::Cmd
+          && chars.peek().map(|(_, c)| *c) == Some('%') =>
+      {
+        chars.next();
+        value.push('%');
+      }
+      (_, '\\') if escapes => {
+        if let Some((_, escaped)) = chars.next() {
+          value.push(escaped);
+        }
+      }
+      (Some(q), c) if c == q => quote = None,
+      (None, '"') => quote = Some('"'),
+      (None, '\'') if single_quotes => quote = Some('\''),
+      _ => value.push(c),
+    }
+  }
+  if let Some((start, value)) = word {
+    words.push((start..args.len(), value));
+  }
+  words
+}
+
+/// Points the paths among the arguments of a deno invocation whose file name
+/// is one of `file_names`, like `--config` or `--allow-write` values, at the
+/// installation directory. Other arguments are kept as they are.
+fn repoint_wrapper_args(
+  args: &str,
+  quoting: WrapperArgQuoting,
+  installation_dir: &Path,
+  file_names: &[&OsStr],
+) -> String {
+  let repoint = |value: &str| {
+    let path = Path::new(value);
+    let file_name = path.file_name()?;
+    (path.is_absolute()
+      && path.parent() != Some(installation_dir)
+      && file_names.contains(&file_name))
+    .then(|| {
+      installation_dir
+        .join(file_name)
+        .to_string_lossy()
+        .to_string()
+    })
+  };
+  let mut repointed = String::with_capacity(args.len());
+  let mut last = 0;
+  for (range, word) in wrapper_arg_words(args, quoting) {
+    let new_word = match word.split_once('=') {
+      // permission flags take comma separated lists of paths
+      Some((flag, value)) if flag.starts_with("--") => {
+        let items = value.split(',').map(|item| (item, repoint(item)));
+        let items = items.collect::<Vec<_>>();
+        items.iter().any(|(_, new)| new.is_some()).then(|| {
+          let value = items
+            .iter()
+            .map(|(item, new)| new.as_deref().unwrap_or(*item))
+            .collect::<Vec<_>>()
+            .join(",");
+          format!("{flag}={value}")
+        })
+      }
+      _ => repoint(&word),
+    };
+    let Some(new_word) = new_word else {
+      continue;
+    };
+    repointed.push_str(&args[last..range.start]);
+    repointed.push_str(&match quoting {
+      WrapperArgQuoting::Cmd => render_script_arg(&new_word, true),
+      WrapperArgQuoting::Sh => render_script_arg(&new_word, false),
+      WrapperArgQuoting::Shebang => env_split_escape(&new_word),
+    });
+    last = range.end;
+  }
+  repointed.push_str(&args[last..]);
+  repointed
+}
+
+/// Splits a shebang script into the start of its shebang line, the
+/// arguments passed to deno and the text after them.
+fn split_shebang_invocation(contents: &str) -> Option<(&str, &str, &str)> {
+  const SHEBANG_PREFIX: &str = "#!/usr/bin/env -S deno ";
+  if !contents.starts_with(SHEBANG_PREFIX) {
+    return None;
+  }
+  let end = contents.find('\n')?;
+  Some((
+    &contents[..SHEBANG_PREFIX.len()],
+    &contents[SHEBANG_PREFIX.len()..end],
+    &contents[end..],
+  ))
+}
+
+/// Regenerates the executables in the installation directory from their
+/// metadata after the root was moved, pointing them at the current location
+/// of their companion files and `--grant-prompt-once` state. These are
+/// copied next to the executables, so they are expected in the installation
+/// directory, wherever it was at installation time. Returns the names of the
+/// repaired installations.
+fn repair(
+  fs: &dyn InstallerFs,
+  installation_dir: &Path,
+) -> Result<Vec<String>, AnyError> {
+  let mut repaired = Vec::new();
+  for mut metadata in read_installed_metadata(installation_dir)? {
+    if !is_safe_name(&metadata.name) {
+      continue;
+    }
+    let mut changed = false;
+    for companion in &mut metadata.companions {
+      let Some(file_name) = companion.path.file_name() else {
+        continue;
+      };
+      let path = installation_dir.join(file_name);
+      if path != companion.path {
+        companion.path = path;
+        changed = true;
+      }
+    }
+
+    let file_path = executable_path(installation_dir, &metadata.name);
+    let state_path = get_hidden_file_with_ext(&file_path, "grants");
+    let recorder_path = get_hidden_file_with_ext(&file_path, "grant.js");
+    let file_names = metadata
+      .companions
+      .iter()
+      .map(|companion| companion.path.as_path())
+      .chain([state_path.as_path(), recorder_path.as_path()])
+      .filter_map(|path| path.file_name())
+      .collect::<Vec<_>>();
+    let shim_data = template_shim_data(installation_dir, &metadata);
+    let mut executables = vec![shim_data.file_path.clone()];
+    for alias in metadata.aliases.iter().filter(|alias| is_safe_name(alias)) {
+      executables.push(executable_path(installation_dir, alias));
+    }
+    for executable in executables {
+      for (path, rendered) in render_executable_files(&shim_data, &executable) {
+        let Ok(existing) = fs.read_to_string(&path) else {
+          continue;
+        };
+        let quoting = match path.extension().and_then(|ext| ext.to_str()) {
+          Some("cmd" | "bat") => WrapperArgQuoting::Cmd,
+          _ => WrapperArgQuoting::Sh,
+        };
+        let repoint = |args: &str, quoting: WrapperArgQuoting| {
+          repoint_wrapper_args(args, quoting, installation_dir, &file_names)
+        };
+        let contents = if let Some((before, args, after)) =
+          split_shebang_invocation(&existing)
+        {
+          format!(
+            "{before}{}{after}",
+            repoint(args, WrapperArgQuoting::Shebang)
+          )
+        } else if let Some((existing_before, args, existing_after)) =
+          split_wrapper_invocation(&existing)
+        {
+          let args = repoint(args, quoting);
+          // executables rendered from a custom template keep their template
+          match split_wrapper_invocation(&rendered)
+            .filter(|_| metadata.template.is_none())
+          {
+            Some((before, _, after)) => format!("{before}{args}{after}"),
+            None => format!("{existing_before}{args}{existing_after}"),
+          }
+        } else {
+          continue;
+        };
+        if contents != existing {
+          fs.write(&path, &contents).with_context(|| {
+            format!("Failed to write executable at {}", path.display())
+          })?;
+          changed = true;
+        }
+      }
+    }
+    if fs.exists(&recorder_path) {
+      if let Ok(module_url) = Url::parse(&metadata.module_url) {
+        let recorder = render_grant_recorder(&state_path, &module_url);
+        if fs.read_to_string(&recorder_path).ok().as_ref() != Some(&recorder) {
+          fs.write(&recorder_path, &recorder).with_context(|| {
+            format!("Failed to write {}", recorder_path.display())
+          })?;
+          changed = true;
+        }
+      }
+    }
+    if !changed {
+      continue;
+    }
+
+    let metadata_path = get_metadata_path(&file_path);
+    fs.write(&metadata_path, &serde_json::to_string_pretty(&metadata)?)
+      .with_context(|| {
+        format!("Failed to write {}", metadata_path.display())
+      })?;
+    repaired.push(metadata.name);
+  }
+  if !repaired.is_empty() {
+    update_install_index(fs, installation_dir);
+  }
+  Ok(repaired)
+}
+
+fn get_metadata_path(file_path: &Path) -> PathBuf {
+  get_hidden_file_with_ext(file_path, "install.json")
+}
+
+/// Appends `new_args` to the arguments of an existing installation, skipping
+/// any that are already present.
+fn merge_script_args(
+  installed_args: Vec<String>,
+  new_args: &[String],
+) -> Vec<String> {
+  let mut args = installed_args;
+  for arg in new_args {
+    if !args.contains(arg) {
+      args.push(arg.clone());
+    }
+  }
+  args
+}
+
+/// Reads the metadata stored alongside the executable at `file_path`, if
+/// any.
+fn read_metadata(file_path: &Path) -> Option<InstallMetadata> {
+  let text = fs::read_to_string(get_metadata_path(file_path)).ok()?;
+  serde_json::from_str(&text).ok()
+}
+
+const INSTALL_INDEX_FILE_NAME: &str = ".index.json";
+
+/// The metadata of every installation in an installation directory, kept in
+/// a single file so that listing doesn't read every metadata file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct InstallIndex {
+  installations: Vec<InstallMetadata>,
+  /// The stamps of the metadata files the index was built from, by file
+  /// name.
+  #[serde(default)]
+  files: BTreeMap<String, MetadataFileStamp>,
+}
+
+/// The modification time and size of a metadata file, to tell whether it
+/// changed since it was indexed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MetadataFileStamp {
+  modified_nanos: u64,
+  len: u64,
+}
+
+/// Returns the stamps of the given metadata files by file name, or `None`
+/// when one of them can't be read.
+fn metadata_file_stamps(
+  paths: &[PathBuf],
+) -> Option<BTreeMap<String, MetadataFileStamp>> {
+  paths
+    .iter()
+    .map(|path| {
+      let metadata = fs::metadata(path).ok()?;
+      let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?;
+      let stamp = MetadataFileStamp {
+        modified_nanos: modified.as_nanos() as u64,
+        len: metadata.len(),
+      };
+      Some((path.file_name()?.to_string_lossy().to_string(), stamp))
+    })
+    .collect()
+}
+
+/// Returns the paths of the metadata files in the installation directory,
+/// without reading them.
+fn metadata_file_paths(
+  installation_dir: &Path,
+) -> Result<Vec<PathBuf>, AnyError> {
+  let entries = match fs::read_dir(installation_dir) {
+    Ok(entries) => entries,
+    Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+    Err(err) => return Err(err.into()),
+  };
+  let mut paths = Vec::new();
+  for entry in entries {
+    let path = entry?.path();
+    let is_metadata_file = path
+      .file_name()
+      .map(|name| name.to_string_lossy())
+      .map(|name| name.starts_with('.') && name.ends_with(".install.json"))
+      .unwrap_or(false);
+    if is_metadata_file {
+      paths.push(path);
+    }
+  }
+  Ok(paths)
+}
+
+/// Reads the metadata of every installation in the installation directory
+/// from its index. The index is rebuilt when it is missing or any metadata
+/// file in the directory was added, removed or modified since it was built.
+/// Executables installed by older versions of deno have no metadata and
+/// are skipped.
+fn read_installed_metadata(
+  installation_dir: &Path,
+) -> Result<Vec<InstallMetadata>, AnyError> {
+  let paths = metadata_file_paths(installation_dir)?;
+  // stamp the files before reading them, so that changes made while they
+  // are read invalidate the index
+  let stamps = metadata_file_stamps(&paths);
+  let index_path = installation_dir.join(INSTALL_INDEX_FILE_NAME);
+  let index = fs::read_to_string(&index_path)
+    .ok()
+    .and_then(|text| serde_json::from_str::<InstallIndex>(&text).ok());
+  if let (Some(index), Some(stamps)) = (index, &stamps) {
+    if &index.files == stamps {
+      return Ok(index.installations);
+    }
+  }
+  let installed = scan_installed_metadata(&paths)?;
+  if let Some(stamps) = stamps {
+    write_install_index(&RealInstallerFs, installation_dir, &installed, stamps);
+  }
+  Ok(installed)
+}
+
+/// Rebuilds the index of the installation directory after an installation
+/// was added, changed or removed.
+fn update_install_index(fs: &dyn InstallerFs, installation_dir: &Path) {
+  let result = metadata_file_paths(installation_dir).and_then(|paths| {
+    let stamps = metadata_file_stamps(&paths)
+      .ok_or_else(|| generic_error("Failed reading a metadata file"))?;
+    Ok((scan_installed_metadata(&paths)?, stamps))
+  });
+  match result {
+    Ok((installed, stamps)) => {
+      write_install_index(fs, installation_dir, &installed, stamps)
+    }
+    Err(err) => {
+      log::debug!(
+        "Failed updating the index of {}: {:#}",
+        installation_dir.display(),
+        err
+      );
+    }
+  }
+}
+
+/// Writes the index of the installation directory, or removes it when
+/// nothing is installed. The index is only a cache, so failing to write it
+/// is not an error.
+fn write_install_index(
+  fs: &dyn InstallerFs,
+  installation_dir: &Path,
+  installed: &[InstallMetadata],
+  files: BTreeMap<String, MetadataFileStamp>,
+) {
+  let index_path = installation_dir.join(INSTALL_INDEX_FILE_NAME);
+  if installed.is_empty() {
+    let _ = fs.remove_file(&index_path);
+    return;
+  }
+  let index = InstallIndex {
+    installations: installed.to_vec(),
+    files,
+  };
+  let result = serde_json::to_string_pretty(&index)
+    .map_err(AnyError::from)
+    .and_then(|text| Ok(fs.write(&index_path, &text)?));
+  if let Err(err) = result {
+    log::debug!("Failed writing {}: {:#}", index_path.display(), err);
+  }
+}
+
+/// Reads and parses the given metadata files, sorted by name.
+fn scan_installed_metadata(
+  paths: &[PathBuf],
+) -> Result<Vec<InstallMetadata>, AnyError> {
+  let mut installed = Vec::new();
+  for path in paths {
+    let text = fs::read_to_string(path)?;
+    match serde_json::from_str::<InstallMetadata>(&text) {
+      Ok(metadata) => installed.push(metadata),
+      Err(err) => {
+        log::debug!("Failed parsing {}: {:#}", path.display(), err);
+      }
+    }
+  }
+  installed.sort_by(|a, b| a.name.cmp(&b.name));
+  Ok(installed)
+}
+
+const KNOWN_ROOTS_FILE_NAME: &str = "known_roots.json";
+
+/// Reads the installation roots recorded in the known-roots registry at
+/// `registry_path`. A missing or unreadable registry has no roots.
+fn read_known_roots(registry_path: &Path) -> Vec<PathBuf> {
+  let Ok(text) = fs::read_to_string(registry_path) else {
+    return vec![];
+  };
+  match serde_json::from_str::<Vec<PathBuf>>(&text) {
+    Ok(roots) => roots,
+    Err(err) => {
+      log::debug!("Failed parsing {}: {:#}", registry_path.display(), err);
+      vec![]
+    }
+  }
+}
+
+/// Adds `root` to the known-roots registry at `registry_path` so that it is
+/// found by `deno install --list --all-roots`.
+fn record_known_root(
+  registry_path: &Path,
+  root: &Path,
+) -> Result<(), AnyError> {
+  let mut roots = read_known_roots(registry_path);
+  if roots.iter().any(|known_root| known_root == root) {
+    return Ok(());
+  }
+  roots.push(root.to_path_buf());
+  if let Some(parent) = registry_path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  fs::write(registry_path, serde_json::to_string_pretty(&roots)?)?;
+  Ok(())
+}
+
+/// Collects the default root, the `DENO_INSTALL_ROOT` root and the roots
+/// recorded in the known-roots registry, without duplicates. Without a
+/// default root, e.g. when `HOME` isn't set, only the `DENO_INSTALL_ROOT`
+/// root is listed.
+fn get_all_installer_roots() -> Result<Vec<PathBuf>, AnyError> {
+  let env_root = get_env_installer_root()?;
+  let default_root = match get_default_installer_root() {
+    Ok(default_root) => Some(default_root),
+    Err(_) if env_root.is_some() => None,
+    Err(err) => return Err(err),
+  };
+  let mut roots = Vec::new();
+  roots.extend(default_root.clone());
+  roots.extend(env_root);
+  if let Some(default_root) = &default_root {
+    roots.extend(read_known_roots(&default_root.join(KNOWN_ROOTS_FILE_NAME)));
+  }
+  let mut seen = HashSet::new();
+  roots.retain(|root| seen.insert(root.clone()));
+  Ok(roots)
+}
+
+/// An installation found while listing one or more installation roots.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ListedInstallation {
+  root: PathBuf,
+  #[serde(flatten)]
+  metadata: InstallMetadata,
+}
+
+fn list_installations(
+  roots: &[PathBuf],
+) -> Result<Vec<ListedInstallation>, AnyError> {
+  let mut listed = Vec::new();
+  for root in roots {
+    for metadata in read_installed_metadata(&get_installation_dir(root)?)? {
+      listed.push(ListedInstallation {
+        root: root.clone(),
+        metadata,
+      });
+    }
+  }
+  Ok(listed)
+}
+
+fn list_command(
+  install_flags_global: &InstallFlagsGlobal,
+) -> Result<(), AnyError> {
+  let roots = if install_flags_global.all_roots {
+    get_all_installer_roots()?
+  } else {
+    vec![resolve_root(install_flags_global.root.as_ref())?]
+  };
+
+  let listed = list_installations(&roots)?;
+  if listed.is_empty()
+    && install_flags_global.list_format != InstallListFormat::Json
+  {
+    log::info!("No installations found");
+    return Ok(());
+  }
+  let output = render_list(
+    &listed,
+    install_flags_global.list_format,
+    install_flags_global.all_roots,
+  )?;
+  display::write_to_stdout_ignore_sigpipe(output.as_bytes())?;
+  Ok(())
+}
+
+/// Renders the listed installations in the given format. The root of each
+/// installation is only included in the plain and table formats when
+/// `show_root` is set.
+fn render_list(
+  listed: &[ListedInstallation],
+  format: InstallListFormat,
+  show_root: bool,
+) -> Result<String, AnyError> {
+  let rows = listed
+    .iter()
+    .map(|installation| {
+      let mut row = vec![
+        installation.metadata.name.clone(),
+        installation.metadata.module_url.clone(),
+      ];
+      if show_root {
+        row.push(installation.root.display().to_string());
+      }
+      row
+    })
+    .collect::<Vec<_>>();
+
+  let mut output = String::new();
+  match format {
+    InstallListFormat::Json => {
+      output = serde_json::to_string_pretty(listed)?;
+      output.push('\n');
+    }
+    InstallListFormat::Plain => {
+      for mut row in rows {
+        if show_root {
+          let root = row.pop().unwrap();
+          row.push(format!("({root})"));
+        }
+        output.push_str(&row.join(" "));
+        output.push('\n');
+      }
+    }
+    InstallListFormat::Table => {
+      let mut header = vec!["NAME".to_string(), "MODULE".to_string()];
+      if show_root {
+        header.push("ROOT".to_string());
+      }
+      let rows = std::iter::once(header).chain(rows).collect::<Vec<_>>();
+      let widths = (0..rows[0].len())
+        .map(|i| rows.iter().map(|row| row[i].chars().count()).max().unwrap())
+        .collect::<Vec<_>>();
+      for row in rows {
+        let line = row
+          .iter()
+          .zip(&widths)
+          .map(|(cell, width)| format!("{cell:width$}"))
+          .collect::<Vec<_>>()
+          .join("  ");
+        output.push_str(line.trim_end());
+        output.push('\n');
+      }
+    }
+  }
+  Ok(output)
+}
+
+struct PruneOptions {
+  dry_run: bool,
+  check_remote: bool,
+  timeout: Duration,
+}
+
+/// Checks whether the module of an installation can still be resolved.
+/// Local modules must exist on disk, while remote modules are only
+/// checked when `check_remote` is set, failing when they can't be
+/// downloaded within `timeout`.
+async fn verify_installation(
+  http_client_provider: &HttpClientProvider,
+  metadata: &InstallMetadata,
+  check_remote: bool,
+  timeout: Duration,
+) -> bool {
+  let Ok(module_url) = Url::parse(&metadata.module_url) else {
+    return false;
+  };
+  match module_url.scheme() {
+    "file" => module_url
+      .to_file_path()
+      .map(|path| path.exists())
+      .unwrap_or(false),
+    "http" | "https" if check_remote => {
+      match http_client_provider.get_or_create() {
+        Ok(client) => with_timeout(
+          timeout,
+          "downloading",
+          &module_url,
+          client.download(module_url.clone()),
+        )
+        .await
+        .is_ok(),
+        Err(_) => false,
+      }
+    }
+    _ => true,
+  }
+}
+
+/// Removes every installation whose module can no longer be found,
+/// returning the names of the pruned installations.
+async fn prune(
+  http_client_provider: &HttpClientProvider,
+  installation_dir: &Path,
+  options: PruneOptions,
+) -> Result<Vec<String>, AnyError> {
+  let mut pruned = Vec::new();
+  for metadata in read_installed_metadata(installation_dir)? {
+    if verify_installation(
+      http_client_provider,
+      &metadata,
+      options.check_remote,
+      options.timeout,
+    )
+    .await
+    {
+      continue;
+    }
+    if options.dry_run {
+      log::info!(
+        "Would prune {} (module not found: {})",
+        metadata.name,
+        metadata.module_url
+      );
+    } else {
+      remove_installation(&RealInstallerFs, installation_dir, &metadata.name)?;
+      log::info!(
+        "Pruned {} (module not found: {})",
+        metadata.name,
+        metadata.module_url
+      );
+    }
+    pruned.push(metadata.name);
+  }
+
+  if pruned.is_empty() {
+    log::info!("No broken installations found");
+  } else if options.dry_run {
+    log::info!("{} installation(s) would be pruned", pruned.len());
+  } else {
+    log::info!("✅ Successfully pruned {} installation(s)", pruned.len());
+  }
+  Ok(pruned)
+}
+
+async fn install_local(
+  flags: Arc<Flags>,
+  maybe_add_flags: Option<AddFlags>,
+) -> Result<(), AnyError> {
+  if let Some(add_flags) = maybe_add_flags {
+    return super::registry::add(flags, add_flags).await;
+  }
+
+  let factory = CliFactory::from_flags(flags);
+  crate::module_loader::load_top_level_deps(&factory).await?;
+
+  if let Some(lockfile) = factory.cli_options()?.maybe_lockfile() {
+    lockfile.write_if_changed()?;
+  }
+
+  Ok(())
+}
+
+pub async fn install_command(
+  flags: Arc<Flags>,
+  install_flags: InstallFlags,
+) -> Result<(), AnyError> {
+  match install_flags.kind {
+    InstallKind::Global(global_flags) => {
+      if !install_flags.global {
+        log::warn!("⚠️ `deno install` behavior will change in Deno 2. To preserve the current behavior use the `-g` or `--global` flag.");
+      }
+
+      install_global(flags, global_flags).await
+    }
+    InstallKind::Local(maybe_add_flags) => {
+      install_local(flags, maybe_add_flags).await
+    }
+  }
+}
+
+async fn install_global(
+  flags: Arc<Flags>,
+  install_flags_global: InstallFlagsGlobal,
+) -> Result<(), AnyError> {
+  let install_flags_global = match get_installer_root() {
+    Ok(root) => apply_install_defaults(
+      install_flags_global,
+      &root.join(INSTALL_DEFAULTS_FILE_NAME),
+    )?,
+    Err(_) => install_flags_global,
+  };
+
+  let root = || resolve_root(install_flags_global.root.as_ref());
+  match InstallMode::from_flags(&install_flags_global) {
+    InstallMode::RefreshCompanions(name) => {
+      let factory = CliFactory::from_flags(flags);
+      let refreshed = refresh_companions(
+        &RealInstallerFs,
+        factory.http_client_provider(),
+        &name,
+        &root()?,
+        network_timeout(&install_flags_global),
+      )
+      .await?;
+      if refreshed.is_empty() {
+        log::info!("{name} has no companion files to refresh");
+      }
+      Ok(())
+    }
+    InstallMode::OverwriteCompanions(name) => {
+      let factory = CliFactory::from_flags(flags.clone());
+      overwrite_companions(
+        &RealInstallerFs,
+        factory.http_client_provider(),
+        &flags,
+        &install_flags_global,
+        &name,
+        &root()?,
+      )
+      .await?;
+      Ok(())
+    }
+    InstallMode::CheckNameAvailability => {
+      let availability = check_name_availability(
+        &install_flags_global.check_name_availability,
+        install_flags_global.root.as_ref().map(PathBuf::from),
+      )?;
+      for (name, available) in availability {
+        if available {
+          log::info!("{} {}", name, colors::green("available"));
+        } else {
+          log::info!("{} {}", name, colors::yellow("installed"));
+        }
+      }
+      Ok(())
+    }
+    InstallMode::CheckUpdates => {
+      let factory = CliFactory::from_flags(flags);
+      let updates = check_updates(
+        factory.http_client_provider(),
+        &RegistryUrls::default(),
+        &get_installation_dir(&root()?)?,
+        network_timeout(&install_flags_global),
+      )
+      .await?;
+      if updates.is_empty() {
+        log::info!("All registry installations are up to date");
+      }
+      for update in updates {
+        log::info!(
+          "{} {} -> {}",
+          colors::green(&update.name),
+          update.current_version,
+          update.latest_version
+        );
+      }
+      Ok(())
+    }
+    InstallMode::Repair => {
+      let installation_dir = get_installation_dir(&root()?)?;
+      let _install_lock = lock_installation_dir(&installation_dir).await;
+      let repaired = repair(&RealInstallerFs, &installation_dir)?;
+      if repaired.is_empty() {
+        log::info!("Nothing to repair");
+      }
+      for name in repaired {
+        log::info!("{} {}", colors::green("Repaired"), name);
+      }
+      Ok(())
+    }
+    InstallMode::ReinstallOutdatedTemplates => {
+      let reinstalled = reinstall_outdated_templates(
+        &RealInstallerFs,
+        &get_installation_dir(&root()?)?,
+      )?;
+      if reinstalled.is_empty() {
+        log::info!("All executables use the current wrapper template");
+      }
+      for name in reinstalled {
+        log::info!("{} {}", colors::green("Regenerated"), name);
+      }
+      Ok(())
+    }
+    InstallMode::List => list_command(&install_flags_global),
+    InstallMode::PrintEnv => {
+      let env = render_install_env(install_flags_global.root.as_deref())?;
+      display::write_to_stdout_ignore_sigpipe(env.as_bytes())?;
+      Ok(())
+    }
+    InstallMode::JsonSchema => {
+      display::write_to_stdout_ignore_sigpipe(
+        INSTALL_METADATA_SCHEMA.as_bytes(),
+      )?;
+      Ok(())
+    }
+    InstallMode::Touch(name) => {
+      touch(&name, install_flags_global.root.as_ref().map(PathBuf::from))?;
+      log::info!("{} {}", colors::green("Verified"), name);
+      Ok(())
+    }
+    InstallMode::Which(name) => {
+      let file_path =
+        which(&name, install_flags_global.root.as_ref().map(PathBuf::from))?;
+      display::write_to_stdout_ignore_sigpipe(
+        format!("{}\n", file_path.display()).as_bytes(),
+      )?;
+      Ok(())
+    }
+    InstallMode::VerifyNameUniqueAcrossShells(name) => {
+      let problems =
+        wrapper_variant_problems(&get_installation_dir(&root()?)?, &name)?;
+      if !problems.is_empty() {
+        return Err(generic_error(format!(
+          "The executables of {name} are inconsistent:\n  {}\nReinstall it with --force to fix them.",
+          problems.join("\n  ")
+        )));
+      }
+      log::info!("The executables of {} are consistent", name);
+      Ok(())
+    }
+    InstallMode::ExportManifest => {
+      let manifest = export_manifest(&get_installation_dir(&root()?)?)?;
+      display::write_json_to_stdout(&manifest)
+    }
+    InstallMode::SelfTest => {
+      let current_exe =
+        env::current_exe().context("Unable to get current exe")?;
+      let deno_dir = current_exe.parent().unwrap_or(&current_exe);
+      let factory = CliFactory::from_flags(flags);
+      self_test(
+        factory.http_client_provider(),
+        deno_dir,
+        install_flags_global.timeout,
+      )
+      .await
+    }
+    InstallMode::Manifest => {
+      install_from_manifest(flags, install_flags_global).await
+    }
+    InstallMode::Install => {
+      install_script_command(flags, install_flags_global).await
+    }
+  }
+}
+
+/// What `deno install -g` was asked to do. The modes other than installing
+/// a script are mutually exclusive flags, so at most one of them is set.
+enum InstallMode {
+  RefreshCompanions(String),
+  OverwriteCompanions(String),
+  CheckNameAvailability,
+  CheckUpdates,
+  Repair,
+  ReinstallOutdatedTemplates,
+  List,
+  PrintEnv,
+  JsonSchema,
+  Touch(String),
+  Which(String),
+  VerifyNameUniqueAcrossShells(String),
+  ExportManifest,
+  SelfTest,
+  Manifest,
+  Install,
+}
+
+impl InstallMode {
+  fn from_flags(install_flags_global: &InstallFlagsGlobal) -> Self {
+    let flags = install_flags_global;
+    if let Some(name) = &flags.force_reinstall_deps {
+      Self::RefreshCompanions(name.clone())
+    } else if let Some(name) = &flags.overwrite_companions_only {
+      Self::OverwriteCompanions(name.clone())
+    } else if !flags.check_name_availability.is_empty() {
+      Self::CheckNameAvailability
+    } else if flags.check_updates {
+      Self::CheckUpdates
+    } else if flags.repair {
+      Self::Repair
+    } else if flags.reinstall_if_template_outdated {
+      Self::ReinstallOutdatedTemplates
+    } else if flags.list {
+      Self::List
+    } else if flags.print_env {
+      Self::PrintEnv
+    } else if flags.json_schema {
+      Self::JsonSchema
+    } else if let Some(name) = &flags.touch {
+      Self::Touch(name.clone())
+    } else if let Some(name) = &flags.which {
+      Self::Which(name.clone())
+    } else if let Some(name) = &flags.verify_name_unique_across_shells {
+      Self::VerifyNameUniqueAcrossShells(name.clone())
+    } else if flags.export_manifest {
+      Self::ExportManifest
+    } else if flags.self_test {
+      Self::SelfTest
+    } else if flags.manifest {
+      Self::Manifest
+    } else {
+      Self::Install
+    }
+  }
+}
+
+/// Installs the script of `install_flags_global`, printing the outcome in
+/// the format asked for.
+async fn install_script_command(
+  flags: Arc<Flags>,
+  install_flags_global: InstallFlagsGlobal,
+) -> Result<(), AnyError> {
+  let json = install_flags_global.json;
+  let dry_run = install_flags_global.dry_run;
+  let print_path = install_flags_global.print_path;
+  if print_path {
+    // nothing but the path should end up on stdout
+    log::set_max_level(log::LevelFilter::Error);
+  }
+  match install_script(flags, install_flags_global).await {
+    Ok(installed) if print_path => {
+      display::write_to_stdout_ignore_sigpipe(
+        print_path_output(&installed).as_bytes(),
+      )?;
+      Ok(())
+    }
+    // the plan was printed already
+    Ok(_) if json && dry_run => Ok(()),
+    Ok(installed) if json => {
+      display::write_json_to_stdout(&serde_json::json!({
+        "name": installed.name,
+        "files": installed.created_files,
+      }))
+    }
+    Ok(_) => Ok(()),
+    Err(err) if json => {
+      let exit_code = err
+        .downcast_ref::<InstallError>()
+        .map(|err| err.exit_code)
+        .unwrap_or(1);
+      Err(JsonInstallError(render_json_error(&err), exit_code).into())
+    }
+    Err(err) => Err(err),
+  }
+}
+
+async fn install_script(
+  flags: Arc<Flags>,
+  install_flags_global: InstallFlagsGlobal,
+) -> Result<InstalledScript, AnyError> {
+  let cwd = std::env::current_dir().context("Unable to get CWD")?;
+  let base_dir = resolve_base_dir(&install_flags_global, &cwd);
+  let module_specifier = resolve_bare_name(
+    &install_flags_global.module_url,
+    registry_default(&install_flags_global).as_deref(),
+    &base_dir,
+  )?;
+  let module_url = resolve_install_module_url(&module_specifier, &base_dir)?;
+
+  // ensure the module is cached, which a dry run doesn't need
+  let factory = CliFactory::from_flags(flags.clone());
+  if !install_flags_global.dry_run {
+    factory
+      .main_module_graph_container()
+      .await?
+      .load_and_type_check_files(&[module_url.to_string()])
+      .await?;
+  }
+  let http_client = factory.http_client_provider();
+
+  // create the install shim
+  let custom_root =
+    install_flags_global.root.is_some() && !install_flags_global.dry_run;
+  let root = resolve_root(install_flags_global.root.as_ref())?;
+  let installed = if install_flags_global.replace.is_some() {
+    replace_installation(http_client, &flags, install_flags_global).await?
+  } else {
+    Installer::new(root.clone(), http_client.clone())
+      .install(InstallOptions::from_flags(
+        (*flags).clone(),
+        install_flags_global,
+      ))
+      .await?
+  };
+  if custom_root {
+    // the installation succeeded, so failing to record its root only
+    // hides it from `--all-roots`
+    let recorded = get_default_installer_root().and_then(|default_root| {
+      record_known_root(&default_root.join(KNOWN_ROOTS_FILE_NAME), &root)
+    });
+    if let Err(err) = recorded {
+      log::debug!("Failed recording installation root: {:#}", err);
+    }
+  }
+  for file_path in &installed.created_files {
+    log::debug!("Created {}", file_path.display());
+  }
+  Ok(installed)
+}
+
+/// Options for installing a script with [`Installer::install`].
+#[derive(Debug, Clone, Default)]
+pub struct InstallOptions {
+  flags: Flags,
+  install_flags_global: InstallFlagsGlobal,
+}
+
+impl InstallOptions {
+  pub fn new(module_url: impl Into<String>) -> Self {
+    Self {
+      flags: Flags::default(),
+      install_flags_global: InstallFlagsGlobal {
+        module_url: module_url.into(),
+        ..Default::default()
+      },
+    }
+  }
+
+  /// The options of a `deno install` command line. Its root is replaced by
+  /// the root of the [`Installer`].
+  pub fn from_flags(
+    flags: Flags,
+    install_flags_global: InstallFlagsGlobal,
+  ) -> Self {
+    Self {
+      flags,
+      install_flags_global,
+    }
+  }
+
+  /// Name of the executable. Inferred from the module URL when not set.
+  pub fn name(mut self, name: impl Into<String>) -> Self {
+    self.install_flags_global.name = Some(name.into());
+    self
+  }
+
+  /// Seconds to wait for each network request, see `--timeout`.
+  pub fn timeout(mut self, timeout: Option<u64>) -> Self {
+    self.install_flags_global.timeout = timeout;
+    self
+  }
+}
+
+/// An installed script, as reported by [`Installer::list`] and
+/// [`Installer::info`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallationInfo {
+  pub name: String,
+  pub module_url: String,
+  pub args: Vec<String>,
+  pub aliases: Vec<String>,
+}
+
+impl From<InstallMetadata> for InstallationInfo {
+  fn from(metadata: InstallMetadata) -> Self {
+    Self {
+      name: metadata.name,
+      module_url: metadata.module_url,
+      args: metadata.args,
+      aliases: metadata.aliases,
+    }
+  }
+}
+
+/// Installs, uninstalls and inspects the scripts of a single installation
+/// root, without going through command line flags. Remote modules are
+/// fetched with the HTTP client of the caller.
+pub struct Installer {
+  root: PathBuf,
+  http_client_provider: Arc<HttpClientProvider>,
+}
+
+impl Installer {
+  pub fn new(
+    root: impl Into<PathBuf>,
+    http_client_provider: Arc<HttpClientProvider>,
+  ) -> Self {
+    Self {
+      root: root.into(),
+      http_client_provider,
+    }
+  }
+
+  fn installation_dir(&self) -> Result<PathBuf, AnyError> {
+    get_installation_dir(&self.root)
+  }
+
+  pub async fn install(
+    &self,
+    options: InstallOptions,
+  ) -> Result<InstalledScript, AnyError> {
+    let InstallOptions {
+      flags,
+      mut install_flags_global,
+    } = options;
+    install_flags_global.root = Some(self.root.to_string_lossy().into_owned());
+    create_install_shim(
+      &self.http_client_provider,
+      &flags,
+      install_flags_global,
+    )
+    .await
+  }
+
+  pub async fn uninstall(&self, name: &str) -> Result<(), AnyError> {
+    let installation_dir = self.installation_dir()?;
+    let _install_lock = lock_installation_dir(&installation_dir).await;
+    if !remove_installation(&RealInstallerFs, &installation_dir, name)? {
+      return Err(install_error(
+        InstallErrorKind::NotFound,
+        format!("No installation found for {}", name),
+      ));
+    }
+    Ok(())
+  }
+
+  pub fn list(&self) -> Result<Vec<InstallationInfo>, AnyError> {
+    Ok(
+      read_installed_metadata(&self.installation_dir()?)?
+        .into_iter()
+        .map(InstallationInfo::from)
+        .collect(),
+    )
+  }
+
+  pub fn info(&self, name: &str) -> Result<InstallationInfo, AnyError> {
+    ensure_safe_name(name)?;
+    match read_metadata(&executable_path(&self.installation_dir()?, name)) {
+      Some(metadata) => Ok(metadata.into()),
+      None => Err(install_error(
+        InstallErrorKind::NotFound,
+        format!("No installation found for {}", name),
+      )),
+    }
+  }
+}
+
+/// Returns why the executables can't find `deno` on `path` (the value of
+/// `PATH`), if they can't. They invoke a bare `deno`, so it has to be
+/// resolvable wherever they run.
+fn deno_on_path_problem(path: Option<&std::ffi::OsStr>) -> Option<String> {
+  let file_names: &[&str] = if cfg!(windows) {
+    &["deno.exe", "deno.cmd", "deno.bat"]
+  } else {
+    &["deno"]
+  };
+  let found = path.is_some_and(|path| {
+    env::split_paths(path).any(|dir| {
+      file_names
+        .iter()
+        .any(|file_name| dir.join(file_name).is_file())
+    })
+  });
+  if found {
+    return None;
+  }
+  let deno_dir = env::current_exe()
+    .ok()
+    .and_then(|exe| exe.parent().map(|dir| dir.display().to_string()))
+    .unwrap_or_else(|| "the directory of deno".to_string());
+  Some(format!(
+    "deno was not found on the PATH, so the installed executable will fail to run. Add {deno_dir} to the PATH."
+  ))
+}
+
+/// Returns the executable named `name` in a directory of `path` (the value of
+/// `PATH`) other than `installation_dir`, which an executable installed under
+/// that name would shadow or be shadowed by.
+fn shadowed_executable(
+  installation_dir: &Path,
+  name: &str,
+  path: &std::ffi::OsStr,
+) -> Option<PathBuf> {
+  let file_names = if cfg!(windows) {
+    ["exe", "cmd", "bat", "com"]
+      .iter()
+      .map(|extension| format!("{name}.{extension}"))
+      .collect::<Vec<_>>()
+  } else {
+    vec![name.to_string()]
+  };
+  env::split_paths(path)
+    .filter(|dir| dir != installation_dir)
+    .find_map(|dir| {
+      file_names
+        .iter()
+        .map(|file_name| dir.join(file_name))
+        .find(|file_path| file_path.is_file())
+    })
+}
+
+/// How much of the output of a failed `--verify-run` is shown.
+const VERIFY_RUN_OUTPUT_LIMIT: usize = 1000;
+
+/// Runs the installed executable with `args`, using the deno running the
+/// installation, and fails with the end of its output if it doesn't exit
+/// successfully.
+fn verify_run(file_path: &Path, args: &[String]) -> Result<(), AnyError> {
+  let current_exe = env::current_exe().context("Unable to get current exe")?;
+  let deno_dir = current_exe.parent().unwrap_or(&current_exe);
+  let path = env::join_paths(
+    std::iter::once(deno_dir.to_path_buf())
+      .chain(env::split_paths(&env::var_os("PATH").unwrap_or_default())),
+  )?;
+  let output = std::process::Command::new(file_path)
+    .args(args)
+    .env("PATH", path)
+    .stdin(std::process::Stdio::null())
+    .output()
+    .with_context(|| format!("Failed to run {}", file_path.display()))?;
+  if output.status.success() {
+    log::info!(
+      "{} Ran {} {}",
+      colors::green("✓"),
+      file_path.display(),
+      args.join(" ")
+    );
+    return Ok(());
+  }
+  let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+  text.push_str(&String::from_utf8_lossy(&output.stderr));
+  let text = text.trim();
+  let snippet = match text.char_indices().rev().nth(VERIFY_RUN_OUTPUT_LIMIT) {
+    Some((start, _)) => format!("...{}", &text[start..]),
+    None => text.to_string(),
+  };
+  Err(generic_error(format!(
+    "{} {} exited with {}\n{}",
+    file_path.display(),
+    args.join(" "),
+    output.status,
+    snippet
+  )))
+}
+
+const SELF_TEST_NAME: &str = "deno_install_self_test";
+const SELF_TEST_SCRIPT: &str = "console.log(Deno.args.join(\" \"));\n";
+const SELF_TEST_ARG: &str = "self-test-ok";
+
+/// Installs a small echo script into a temporary root, runs it using the
+/// deno binary found in `deno_dir` and uninstalls it again, reporting each
+/// step along the way.
+async fn self_test(
+  http_client_provider: &Arc<HttpClientProvider>,
+  deno_dir: &Path,
+  timeout: Option<u64>,
+) -> Result<(), AnyError> {
+  let temp_dir = tempfile::TempDir::new()
+    .context("Unable to create a temporary directory for the self-test")?;
+  let module_path = temp_dir.path().join("echo.ts");
+  fs::write(&module_path, SELF_TEST_SCRIPT)
+    .with_context(|| format!("error writing {}", module_path.display()))?;
+
+  let installer = Installer::new(temp_dir.path(), http_client_provider.clone());
+  let installed = installer
+    .install(
+      InstallOptions::new(module_path.to_string_lossy())
+        .name(SELF_TEST_NAME)
+        .timeout(timeout),
+    )
+    .await
+    .context("Self-test failed while installing")?;
+  let info = installer
+    .info(SELF_TEST_NAME)
+    .context("Self-test failed while reading the installation metadata")?;
+  if info.name != SELF_TEST_NAME
+    || !info.module_url.ends_with("/echo.ts")
+    || !info.args.is_empty()
+    || !info.aliases.is_empty()
+  {
+    return Err(generic_error(format!(
+      "Self-test failed: the installation metadata doesn't match the installed script: {info:?}"
+    )));
+  }
+  log::info!("{} Installed {}", colors::green("✓"), SELF_TEST_NAME);
+
+  let path = env::join_paths(
+    std::iter::once(deno_dir.to_path_buf())
+      .chain(env::split_paths(&env::var_os("PATH").unwrap_or_default())),
+  )?;
+  let output = std::process::Command::new(&installed.created_files[0])
+    .arg(SELF_TEST_ARG)
+    .env("PATH", path)
+    .output()
+    .context("Self-test failed while running the installed executable")?;
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  if !output.status.success() || stdout.trim() != SELF_TEST_ARG {
+    return Err(generic_error(format!(
+      "Self-test failed: the installed executable exited with {} and printed {:?} (expected {:?})\n{}",
+      output.status,
+      stdout.trim(),
+      SELF_TEST_ARG,
+      String::from_utf8_lossy(&output.stderr).trim(),
+    )));
+  }
+  log::info!("{} Ran {}", colors::green("✓"), SELF_TEST_NAME);
+
+  installer
+    .uninstall(SELF_TEST_NAME)
+    .await
+    .context("Self-test failed while uninstalling")?;
+  if !installer.list()?.is_empty() {
+    return Err(generic_error(
+      "Self-test failed: the installation was left behind after uninstalling it",
+    ));
+  }
+  log::info!("{} Uninstalled {}", colors::green("✓"), SELF_TEST_NAME);
+
+  log::info!("✅ Self-test passed");
+  Ok(())
+}
+
+/// A JSON manifest listing several scripts to install at once.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct InstallManifest {
+  tools: Vec<InstallManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct InstallManifestEntry {
+  module: String,
+  #[serde(default)]
+  name: Option<String>,
+  #[serde(default)]
+  args: Vec<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  aliases: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  min_deno_version: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pre_run: Option<String>,
+  /// `KEY=VALUE` environment variables set by the executable.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  env: Vec<String>,
+  /// Directory the executable changes to before running the module.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  cwd: Option<String>,
+  /// Deno flags like `--allow-net` or `--config=deno.json`, which replace
+  /// the flags of the same kind the manifest is installed with.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  deno_flags: Vec<String>,
+}
+
+/// Builds a manifest reinstalling every installation in the installation
+/// directory with the same name, module, arguments and options.
+fn export_manifest(
+  installation_dir: &Path,
+) -> Result<InstallManifest, AnyError> {
+  let tools = read_installed_metadata(installation_dir)?
+    .into_iter()
+    .map(|metadata| InstallManifestEntry {
+      module: metadata.module_url,
+      name: Some(metadata.name),
+      args: metadata.args,
+      aliases: metadata.aliases,
+      min_deno_version: metadata.min_deno_version,
+      pre_run: metadata.pre_run,
+      env: metadata.env,
+      cwd: metadata.working_dir,
+      deno_flags: metadata.deno_flags,
+    })
+    .collect();
+  Ok(InstallManifest { tools })
+}
+
+/// Reads the manifest at `manifest_path`, producing the flags for each of
+/// its entries. The root and force options are shared by every entry and
+/// relative module paths are resolved against the manifest's directory.
+fn read_install_manifest(
+  manifest_path: &Path,
+  install_flags_global: &InstallFlagsGlobal,
+) -> Result<Vec<InstallFlagsGlobal>, AnyError> {
+  let text = fs::read_to_string(manifest_path)
+    .with_context(|| format!("error reading {}", manifest_path.display()))?;
+  let manifest: InstallManifest = serde_json::from_str(&text)
+    .with_context(|| format!("error parsing {}", manifest_path.display()))?;
+  let base_dir = manifest_path.parent().unwrap_or(manifest_path);
+
+  let mut names = HashSet::new();
+  for name in manifest.tools.iter().filter_map(|e| e.name.as_ref()) {
+    if !names.insert(name) {
+      return Err(generic_error(format!(
+        "Duplicate name in install manifest: {name}"
+      )));
+    }
+  }
+
+  Ok(
+    manifest
+      .tools
+      .into_iter()
+      .map(|entry| {
+        let module_url = if specifier_has_uri_scheme(&entry.module) {
+          entry.module
+        } else {
+          base_dir.join(entry.module).to_string_lossy().to_string()
+        };
+        InstallFlagsGlobal {
+          module_url,
+          args: entry.args,
+          name: entry.name,
+          aliases: entry.aliases,
+          min_deno_version: entry
+            .min_deno_version
+            .or_else(|| install_flags_global.min_deno_version.clone()),
+          pre_run: entry
+            .pre_run
+            .or_else(|| install_flags_global.pre_run.clone()),
+          env: match entry.env.is_empty() {
+            true => install_flags_global.env.clone(),
+            false => entry.env,
+          },
+          cwd: entry
+            .cwd
+            .map(|cwd| base_dir.join(cwd).to_string_lossy().to_string())
+            .or_else(|| install_flags_global.cwd.clone()),
+          deno_flags: entry.deno_flags,
+          manifest: false,
+          // every other flag of the batch applies to each entry
+          ..install_flags_global.clone()
+        }
+      })
+      .collect(),
+  )
+}
+
+/// The parts of a `deno.lock` used to pin the entries of a manifest.
+#[derive(Debug, Default, Deserialize)]
+struct PinningLockfile {
+  #[serde(default)]
+  packages: PinningLockfilePackages,
+  #[serde(default)]
+  remote: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PinningLockfilePackages {
+  #[serde(default)]
+  specifiers: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Pins the jsr and npm modules of the manifest entries to the versions they
+/// resolved to in the lockfile at `lockfile_path`. Remote modules must be in
+/// the lockfile already so that the executables can verify their integrity.
+/// Local modules are left untouched.
+fn pin_to_lockfile(
+  entries: Vec<InstallFlagsGlobal>,
+  lockfile_path: &Path,
+) -> Result<Vec<InstallFlagsGlobal>, AnyError> {
+  let text = fs::read_to_string(lockfile_path)
+    .with_context(|| format!("error reading {}", lockfile_path.display()))?;
+  let lockfile: PinningLockfile = serde_json::from_str(&text)
+    .with_context(|| format!("error parsing {}", lockfile_path.display()))?;
+  let not_locked = |module: &str| {
+    generic_error(format!(
+      "{module} is not locked in {}",
+      lockfile_path.display()
+    ))
+  };
+
+  entries
+    .into_iter()
+    .map(|mut entry| {
+      let Ok(module_url) = Url::parse(&entry.module_url) else {
+        return Ok(entry);
+      };
+      let package_ref = if let Ok(npm_ref) =
+        NpmPackageReqReference::from_specifier(&module_url)
+      {
+        Some(("npm", npm_ref.into_inner()))
+      } else if let Ok(jsr_ref) =
+        JsrPackageReqReference::from_specifier(&module_url)
+      {
+        Some(("jsr", jsr_ref.into_inner()))
+      } else {
+        None
+      };
+      if let Some((scheme, package_ref)) = package_ref {
+        let resolved = lockfile
+          .packages
+          .specifiers
+          .get(&format!("{scheme}:{}", package_ref.req))
+          .and_then(|resolved| resolved.as_str())
+          .ok_or_else(|| not_locked(&entry.module_url))?;
+        entry.module_url = match package_ref.sub_path {
+          Some(sub_path) => format!("{resolved}/{sub_path}"),
+          None => resolved.to_string(),
+        };
+      } else if matches!(module_url.scheme(), "http" | "https")
+        && !lockfile.remote.contains_key(module_url.as_str())
+      {
+        return Err(not_locked(&entry.module_url));
+      }
+      Ok(entry)
+    })
+    .collect()
+}
+
+async fn install_from_manifest(
+  flags: Arc<Flags>,
+  install_flags_global: InstallFlagsGlobal,
+) -> Result<(), AnyError> {
+  if !install_flags_global.args.is_empty() {
+    return Err(generic_error(
+      "Script arguments can't be provided when installing from a manifest.",
+    ));
+  }
+  let cwd = std::env::current_dir().context("Unable to get CWD")?;
+  let manifest_path = cwd.join(&install_flags_global.module_url);
+  let mut entries =
+    read_install_manifest(&manifest_path, &install_flags_global)?;
+  let flags = match &install_flags_global.from_lockfile {
+    Some(lockfile) => {
+      let lockfile_path = cwd.join(lockfile);
+      entries = pin_to_lockfile(entries, &lockfile_path)?;
+      // the executables run with a copy of the lockfile
+      let mut flags = (*flags).clone();
+      flags.lock = Some(lockfile_path.to_string_lossy().to_string());
+      Arc::new(flags)
+    }
+    None => flags,
+  };
+  for entry in &entries {
+    resolve_install_module_url(&entry.module_url, &cwd)?;
+  }
+
+  if let Some(archive_path) = &install_flags_global.export_archive {
+    let archive_path = cwd.join(archive_path);
+    let format = ArchiveFormat::from_path(&archive_path)?;
+    let file = File::create(&archive_path).with_context(|| {
+      format!("Failed to create {}", archive_path.display())
+    })?;
+    let total = entries.len();
+    let factory = CliFactory::from_flags(flags.clone());
+    export_archive(
+      factory.http_client_provider(),
+      &flags,
+      entries,
+      io::BufWriter::new(file),
+      format,
+    )
+    .await?;
+    log::info!(
+      "✅ Exported {} scripts to {}",
+      total,
+      archive_path.display()
+    );
+    return Ok(());
+  }
+
+  // ensure all the modules are cached
+  let factory = CliFactory::from_flags(flags.clone());
+  let module_urls = entries
+    .iter()
+    .map(|entry| entry.module_url.clone())
+    .collect::<Vec<_>>();
+  if !install_flags_global.dry_run {
+    factory
+      .main_module_graph_container()
+      .await?
+      .load_and_type_check_files(&module_urls)
+      .await?;
+  }
+
+  let total = entries.len();
+  let results = create_install_shims(
+    factory.http_client_provider(),
+    &flags,
+    entries,
+    install_flags_global.concurrent,
+  )
+  .await;
+  let mut installed = Vec::with_capacity(total);
+  for (module_url, result) in module_urls.iter().zip(results) {
+    match result {
+      Ok(script) => installed.push(script),
+      Err(err) => log::error!("Failed installing {}: {:#}", module_url, err),
+    }
+  }
+  // installations to the same directory share their hints
+  for hint in batch_path_hints(&installed) {
+    log::info!("{}", hint);
+  }
+  let installed_names = installed
+    .into_iter()
+    .map(|script| script.name)
+    .collect::<Vec<_>>();
+  if installed_names.len() < total {
+    return Err(generic_error(format!(
+      "{} of {total} installations failed",
+      total - installed_names.len()
+    )));
+  }
+  log::info!(
+    "✅ Successfully installed {} scripts: {}",
+    total,
+    installed_names.join(", ")
+  );
+  Ok(())
+}
+
+/// The archive formats written by `--export-archive`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveFormat {
+  Tar,
+  TarGz,
+}
+
+impl ArchiveFormat {
+  /// Picks the format from the extension of the archive's path.
+  fn from_path(path: &Path) -> Result<Self, AnyError> {
+    let file_name = path
+      .file_name()
+      .map(|name| name.to_string_lossy().to_lowercase())
+      .unwrap_or_default();
+    if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
+      Ok(Self::TarGz)
+    } else if file_name.ends_with(".tar") {
+      Ok(Self::Tar)
+    } else {
+      Err(generic_error(format!(
+        "Unsupported archive {}, expected a .tar, .tar.gz or .tgz file",
+        path.display()
+      )))
+    }
+  }
+}
+
+/// Renders the executables and companion files of every manifest entry into
+/// an archive written to `out`, without installing anything. The paths in
+/// the archive are relative to the installation root (e.g. `bin/echo_test`)
+/// and the executables reference their companions relative to themselves,
+/// so the archive can be extracted into any root.
+async fn export_archive(
+  http_client_provider: &HttpClientProvider,
+  flags: &Flags,
+  entries: Vec<InstallFlagsGlobal>,
+  out: impl Write,
+  format: ArchiveFormat,
+) -> Result<(), AnyError> {
+  let mut files = Vec::new();
+  let mut paths = HashSet::new();
+  for entry in entries {
+    let mut shim_data = resolve_shim_data(
+      http_client_provider,
+      flags,
+      &InstallFlagsGlobal {
+        relocatable: true,
+        ..entry
+      },
+    )
+    .await?;
+    // the archives of a manifest are identical, whenever they are exported
+    shim_data.metadata.installed_at = None;
+    let (executables, others) = render_installation_files(&shim_data)?;
+    let executables = executables.into_iter().map(|(path, contents)| {
+      let mode = if shim_data.owner_only { 0o700 } else { 0o755 };
+      (path, contents, mode)
+    });
+    let others = others
+      .into_iter()
+      .map(|(path, contents)| (path, contents, 0o644));
+    for (path, contents, mode) in executables.chain(others) {
+      let root = shim_data.installation_dir.parent().unwrap();
+      let path = path.strip_prefix(root)?.to_path_buf();
+      if !paths.insert(path.clone()) {
+        return Err(generic_error(format!(
+          "{} is written by more than one script of the manifest",
+          path.display()
+        )));
+      }
+      files.push((path, contents, mode));
+    }
+  }
+  match format {
+    ArchiveFormat::Tar => {
+      write_tar(out, &files)?;
+    }
+    ArchiveFormat::TarGz => {
+      let encoder =
+        flate2::write::GzEncoder::new(out, flate2::Compression::default());
+      write_tar(encoder, &files)?.finish()?;
+    }
+  }
+  Ok(())
+}
+
+/// Writes `files` as a tar archive, returning the writer.
+fn write_tar<W: Write>(
+  out: W,
+  files: &[(PathBuf, String, u32)],
+) -> Result<W, AnyError> {
+  let mut builder = tar::Builder::new(out);
+  for (path, contents, mode) in files {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(*mode);
+    // a fixed modification time keeps the archives of a manifest identical
+    header.set_mtime(0);
+    builder.append_data(&mut header, path, contents.as_bytes())?;
+  }
+  Ok(builder.into_inner()?)
+}
+
+/// Creates the shims for several installations, running up to `concurrent`
+/// of them at a time. The results are in the same order as `entries`. The
+/// PATH hints are left to the caller, see [`batch_path_hints`].
+async fn create_install_shims(
+  http_client_provider: &HttpClientProvider,
+  flags: &Flags,
+  entries: Vec<InstallFlagsGlobal>,
+  concurrent: Option<NonZeroUsize>,
+) -> Vec<Result<InstalledScript, AnyError>> {
+  // Each shim checks its name and writes its files while holding the lock
+  // of the installation directory, so concurrent entries never interleave
+  // their writes to it.
+  let concurrent = concurrent.map(|n| n.get()).unwrap_or(1);
+  stream::iter(entries)
+    .map(|entry| report_install_shim(http_client_provider, flags, entry))
+    .buffered(concurrent)
+    .collect::<Vec<_>>()
+    .await
+}
+
+enum RemoteModuleCheck {
+  NotModified,
+  Modified {
+    etag: Option<String>,
+    last_modified: Option<String>,
+  },
+}
+
+/// Issues a conditional request for a remote module using the validators
+/// stored in the metadata of its existing installation, if any.
+async fn check_remote_module(
+  http_client_provider: &HttpClientProvider,
+  module_url: &Url,
+  existing_metadata: Option<&InstallMetadata>,
+  timeout: Duration,
+) -> Result<RemoteModuleCheck, AnyError> {
+  let client = http_client_provider.get_or_create()?;
+  let mut request = client.get(module_url.clone())?;
+  if let Some(metadata) = existing_metadata {
+    if let Some(etag) = &metadata.etag {
+      request = request.header(IF_NONE_MATCH, HeaderValue::from_str(etag)?);
+    }
+    if let Some(last_modified) = &metadata.last_modified {
+      request = request
+        .header(IF_MODIFIED_SINCE, HeaderValue::from_str(last_modified)?);
+    }
+  }
+  let response = with_timeout(timeout, "checking", module_url, async {
+    Ok(request.send().await?)
+  })
+  .await?;
+  if response.status() == StatusCode::NOT_MODIFIED {
+    return Ok(RemoteModuleCheck::NotModified);
+  }
+  if !response.status().is_success() {
+    return Err(generic_error(format!(
+      "Failed checking {module_url} for changes: {}",
+      response.status()
+    )));
+  }
+  let header = |name| {
+    response
+      .headers()
+      .get(name)
+      .and_then(|value| value.to_str().ok())
+      .map(|value| value.to_string())
+  };
+  Ok(RemoteModuleCheck::Modified {
+    etag: header(ETAG),
+    last_modified: header(LAST_MODIFIED),
+  })
+}
+
+/// Checks the Ed25519 `signature` of `contents`. Both the public key and
+/// the signature are base64 encoded.
+fn verify_signature(
+  public_key: &str,
+  contents: &[u8],
+  signature: &str,
+) -> Result<(), AnyError> {
+  let public_key = BASE64_STANDARD
+    .decode(public_key.trim())
+    .context("The public key is not valid base64")?;
+  let signature = BASE64_STANDARD
+    .decode(signature.trim())
+    .context("The signature is not valid base64")?;
+  UnparsedPublicKey::new(&ED25519, public_key)
+    .verify(contents, &signature)
+    .map_err(|_| generic_error("The signature doesn't match"))
+}
+
+/// Downloads a remote module along with its detached signature at
+/// `<module url>.sig` and verifies it with the public key in
+/// `public_key_path`.
+async fn verify_module_signature(
+  http_client_provider: &HttpClientProvider,
+  module_url: &Url,
+  public_key_path: &Path,
+  timeout: Duration,
+) -> Result<VerifiedSignature, AnyError> {
+  if !matches!(module_url.scheme(), "http" | "https") {
+    return Err(install_error(
+      InstallErrorKind::InvalidModuleUrl,
+      format!("Only remote modules can be installed with --verify-signature, got {module_url}"),
+    ));
+  }
+  let public_key = fs::read_to_string(public_key_path).with_context(|| {
+    format!("Failed reading public key {}", public_key_path.display())
+  })?;
+  let signature_url = Url::parse(&format!("{module_url}.sig"))?;
+  let client = http_client_provider.get_or_create()?;
+  let contents = with_timeout(
+    timeout,
+    "downloading",
+    module_url,
+    client.download(module_url.clone()),
+  )
+  .await?;
+  let signature = with_timeout(
+    timeout,
+    "downloading",
+    &signature_url,
+    client.download_text(signature_url.clone()),
+  )
+  .await?;
+  verify_signature(&public_key, &contents, &signature).with_context(|| {
+    format!("Signature verification of {module_url} failed. Aborting.")
+  })?;
+  Ok(VerifiedSignature {
+    public_key: public_key.trim().to_string(),
+    signature_url: signature_url.to_string(),
+  })
+}
+
+/// Appends `suffix` to the file name of `path`.
+fn with_file_name_suffix(path: &Path, suffix: &str) -> PathBuf {
+  let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+  file_name.push(suffix);
+  path.with_file_name(file_name)
+}
+
+/// Returns the first of `<file>.bak`, `<file>.bak.1`, `<file>.bak.2`, ...
+/// that doesn't exist, so that earlier backups are kept.
+fn unused_backup_path(fs: &dyn InstallerFs, path: &Path) -> PathBuf {
+  let backup_path = with_file_name_suffix(path, ".bak");
+  if !fs.exists(&backup_path) {
+    return backup_path;
+  }
+  (1..)
+    .map(|n| with_file_name_suffix(path, &format!(".bak.{n}")))
+    .find(|backup_path| !fs.exists(backup_path))
+    .unwrap()
+}
+
+/// Moves each staged file over its target, backing up the existing target
+/// first. When a step fails, the files replaced so far are restored from
+/// their backups, so the existing installation is left as it was. The caller
+/// holds the lock of the installation directory, see [`install_lock_path`].
+fn swap_in_staged_files(
+  fs: &dyn InstallerFs,
+  files: &[(&Path, &Path)],
+) -> Result<(), AnyError> {
+  let mut replaced: Vec<(&Path, Option<PathBuf>)> = Vec::new();
+  for &(path, staged_path) in files {
+    let result = (|| {
+      let backup_path = if fs.exists(path) {
+        let backup_path = unused_backup_path(fs, path);
+        fs.rename(path, &backup_path)
+          .with_context(|| format!("Failed to back up {}", path.display()))?;
+        Some(backup_path)
+      } else {
+        None
+      };
+      if let Err(err) = fs.rename(staged_path, path) {
+        if let Some(backup_path) = &backup_path {
+          let _ = fs.rename(backup_path, path);
+        }
+        return Err(
+          AnyError::from(err)
+            .context(format!("Failed to replace {}", path.display())),
+        );
+      }
+      Ok(backup_path)
+    })();
+    match result {
+      Ok(backup_path) => replaced.push((path, backup_path)),
+      Err(err) => {
+        for (path, backup_path) in replaced.into_iter().rev() {
+          let _ = match backup_path {
+            Some(backup_path) => fs.rename(&backup_path, path),
+            None => fs.remove_file(path),
+          };
+        }
+        return Err(err);
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Swaps the existing installation named in `--replace` for a new one. The
+/// new files are staged next to the existing ones and verified before the
+/// existing files are moved to `<file>.bak` (or the next free `.bak.<n>`)
+/// and the staged files renamed over them, so the executable is never
+/// missing or half written.
+async fn replace_installation(
+  http_client_provider: &HttpClientProvider,
+  flags: &Flags,
+  install_flags_global: InstallFlagsGlobal,
+) -> Result<InstalledScript, AnyError> {
+  let Some(name) = install_flags_global.replace.clone() else {
+    return Err(generic_error(
+      "An executable name to replace was not provided.",
+    ));
+  };
+  let install_flags_global = InstallFlagsGlobal {
+    name: Some(name.clone()),
+    force: true,
+    ..install_flags_global
+  };
+  let shim_data =
+    resolve_shim_data(http_client_provider, flags, &install_flags_global)
+      .await?;
+  // held until the staged files are swapped in, so that no other
+  // installation writes to the directory in between
+  let _install_lock = lock_installation_dir(&shim_data.installation_dir).await;
+  if !shim_data.file_path.exists() {
+    return Err(install_error(
+      InstallErrorKind::NotFound,
+      format!("No installation found for {name}"),
+    ));
+  }
+
+  let (executables, others) = render_installation_files(&shim_data)?;
+  let files = executables
+    .iter()
+    .map(|file| (file, true))
+    .chain(others.iter().map(|file| (file, false)))
+    .map(|((path, contents), is_executable)| {
+      (
+        path,
+        with_file_name_suffix(path, ".new"),
+        contents,
+        is_executable,
+      )
+    })
+    .collect::<Vec<_>>();
+  let fs = &RealInstallerFs;
+  let remove_staged = || {
+    for (_, staged_path, _, _) in &files {
+      let _ = fs.remove_file(staged_path);
+    }
+  };
+
+  for (_, staged_path, contents, is_executable) in &files {
+    let result = fs
+      .write(staged_path, contents)
+      .with_context(|| format!("Failed to write {}", staged_path.display()))
+      .and_then(|_| {
+        if *is_executable {
+          set_executable_permissions(fs, &shim_data, staged_path)
+        } else {
+          Ok(())
+        }
+      });
+    if let Err(err) = result {
+      remove_staged();
+      return Err(err);
+    }
+  }
+  if !verify_installation(
+    http_client_provider,
+    &shim_data.metadata,
+    true,
+    network_timeout(&install_flags_global),
+  )
+  .await
+  {
+    remove_staged();
+    return Err(generic_error(format!(
+      "The new module for {name} could not be found: {}",
+      shim_data.metadata.module_url
+    )));
+  }
+
+  let swaps = files
+    .iter()
+    .map(|(path, staged_path, ..)| (path.as_path(), staged_path.as_path()))
+    .collect::<Vec<_>>();
+  if let Err(err) = swap_in_staged_files(fs, &swaps) {
+    remove_staged();
+    return Err(err);
+  }
+  update_install_index(fs, &shim_data.installation_dir);
+
+  log::info!("✅ Successfully replaced {}", name);
+  let path_hints = path_hints(&shim_data.installation_dir);
+  for hint in &path_hints {
+    log::info!("{}", hint);
+  }
+  Ok(InstalledScript {
+    name: shim_data.name,
+    file_path: shim_data.file_path,
+    created_files: files.into_iter().map(|(path, ..)| path.clone()).collect(),
+    path_hints,
+  })
+}
+
+/// A successfully installed script.
+#[derive(Debug)]
+pub struct InstalledScript {
+  pub name: String,
+  /// The path of the main executable.
+  pub file_path: PathBuf,
+  /// Every file written by the installation: the executables, companion
+  /// files and metadata.
+  pub created_files: Vec<PathBuf>,
+  /// Hints for putting the installation directory on the PATH, printed once
+  /// the installation (or the batch it belongs to) is done.
+  pub path_hints: Vec<String>,
+}
+
+/// A line of the file passed to `--report-file`.
+#[derive(Serialize)]
+struct InstallReport<'a> {
+  timestamp: String,
+  action: &'static str,
+  name: Option<&'a str>,
+  url: Option<&'a str>,
+  args: &'a [String],
+  outcome: &'static str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>,
+}
+
+/// Appends a report as a single JSON line. The line is written with one
+/// `write` to a file opened in append mode, so concurrent installations
+/// reporting to the same file never interleave their lines.
+fn append_install_report(
+  report_file: &Path,
+  report: &InstallReport,
+) -> Result<(), AnyError> {
+  let mut line = serde_json::to_vec(report)?;
+  line.push(b'\n');
+  let mut file = fs::OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(report_file)
+    .with_context(|| {
+      format!("Failed opening report file {}", report_file.display())
+    })?;
+  file.write_all(&line).with_context(|| {
+    format!("Failed writing report file {}", report_file.display())
+  })?;
+  Ok(())
+}
+
+fn report_timestamp() -> String {
+  chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+async fn create_install_shim(
+  http_client_provider: &HttpClientProvider,
+  flags: &Flags,
+  install_flags_global: InstallFlagsGlobal,
+) -> Result<InstalledScript, AnyError> {
+  let installed =
+    report_install_shim(http_client_provider, flags, install_flags_global)
+      .await?;
+  for hint in &installed.path_hints {
+    log::info!("{}", hint);
+  }
+  Ok(installed)
+}
+
+/// Installs a script without printing its PATH hints, appending the outcome
+/// to the `--report-file`, if any.
+async fn report_install_shim(
+  http_client_provider: &HttpClientProvider,
+  flags: &Flags,
+  install_flags_global: InstallFlagsGlobal,
+) -> Result<InstalledScript, AnyError> {
+  let report_file = match &install_flags_global.report_file {
+    Some(report_file) if !install_flags_global.dry_run => {
+      std::env::current_dir()?.join(report_file)
+    }
+    _ => {
+      return write_install_shim(
+        http_client_provider,
+        flags,
+        install_flags_global,
+      )
+      .await
+    }
+  };
+  let result = write_install_shim(
+    http_client_provider,
+    flags,
+    install_flags_global.clone(),
+  )
+  .await;
+  let report = InstallReport {
+    timestamp: report_timestamp(),
+    action: "install",
+    name: match &result {
+      Ok(installed) => Some(installed.name.as_str()),
+      Err(_) => install_flags_global.name.as_deref(),
+    },
+    url: Some(&install_flags_global.module_url),
+    args: &install_flags_global.args,
+    outcome: match &result {
+      Ok(installed) if installed.created_files.is_empty() => "skipped",
+      Ok(_) => "success",
+      Err(_) => "failure",
+    },
+    error: result.as_ref().err().map(|err| format!("{err:#}")),
+  };
+  append_install_report(&report_file, &report)?;
+  result
+}
+
+async fn write_install_shim(
+  http_client_provider: &HttpClientProvider,
+  flags: &Flags,
+  install_flags_global: InstallFlagsGlobal,
+) -> Result<InstalledScript, AnyError> {
+  let mut shim_data =
+    resolve_shim_data(http_client_provider, flags, &install_flags_global)
+      .await?;
+
+  if install_flags_global.only_if_newer {
+    let module_url = Url::parse(&shim_data.metadata.module_url)?;
+    if matches!(module_url.scheme(), "http" | "https") {
+      let existing_metadata = read_metadata(&shim_data.file_path)
+        .filter(|metadata| metadata.module_url == module_url.as_str())
+        .filter(|_| shim_data.file_path.exists());
+      match check_remote_module(
+        http_client_provider,
+        &module_url,
+        existing_metadata.as_ref(),
+        network_timeout(&install_flags_global),
+      )
+      .await?
+      {
+        RemoteModuleCheck::NotModified => {
+          log::info!("{} is up to date, skipping reinstall", shim_data.name);
+          return Ok(InstalledScript {
+            name: shim_data.name,
+            file_path: shim_data.file_path,
+            created_files: vec![],
+            path_hints: vec![],
+          });
+        }
+        RemoteModuleCheck::Modified {
+          etag,
+          last_modified,
+        } => {
+          shim_data.metadata.etag = etag;
+          shim_data.metadata.last_modified = last_modified;
+        }
+      }
+    }
+  }
+
+  if let Some(max_age) = install_flags_global.overwrite_if_older {
+    let installed_at = read_metadata(&shim_data.file_path)
+      .filter(|_| shim_data.file_path.exists())
+      .and_then(|metadata| metadata.installed_at);
+    if let Some(installed_at) = installed_at {
+      if !is_older_than(&installed_at, max_age, chrono::Utc::now()) {
+        log::info!(
+          "{} was installed less than {} ago, skipping reinstall",
+          shim_data.name,
+          format_age(max_age)
+        );
+        return Ok(InstalledScript {
+          name: shim_data.name,
+          file_path: shim_data.file_path,
+          created_files: vec![],
+          path_hints: vec![],
+        });
+      }
+    }
+  }
+
+  if let Some(public_key_path) = &install_flags_global.verify_signature {
+    let module_url = Url::parse(&shim_data.metadata.module_url)?;
+    let cwd = std::env::current_dir().context("Unable to get CWD")?;
+    shim_data.metadata.signature = Some(
+      verify_module_signature(
+        http_client_provider,
+        &module_url,
+        &cwd.join(public_key_path),
+        network_timeout(&install_flags_global),
+      )
+      .await?,
+    );
+  }
+
+  if install_flags_global.verify_deno_on_path {
+    if let Some(problem) = deno_on_path_problem(env::var_os("PATH").as_deref())
+    {
+      if install_flags_global.strict {
+        return Err(generic_error(problem));
+      }
+      log::warn!("{} {}", colors::yellow("Warning"), problem);
+    }
+  }
+
+  if install_flags_global.no_shadow {
+    let path = env::var_os("PATH").unwrap_or_default();
+    for name in
+      std::iter::once(&shim_data.name).chain(&shim_data.metadata.aliases)
+    {
+      if let Some(shadowed) =
+        shadowed_executable(&shim_data.installation_dir, name, &path)
+      {
+        return Err(install_error(
+          InstallErrorKind::InvalidName,
+          format!(
+            "{name} already resolves to {} on the PATH. Choose a different name or pass --allow-shadow.",
+            shadowed.display()
+          ),
+        ));
+      }
+    }
+  }
+
+  if install_flags_global.dry_run && install_flags_global.json {
+    display::write_json_to_stdout(&render_dry_run_plan(&shim_data))?;
+    return Ok(InstalledScript {
+      name: shim_data.name,
+      file_path: shim_data.file_path,
+      created_files: vec![],
+      path_hints: vec![],
+    });
+  }
+
+  if install_flags_global.dry_run {
+    let mut output = render_dry_run(&shim_data, install_flags_global.verbose)?;
+    if install_flags_global.force && shim_data.file_path.exists() {
+      output.push_str(&render_dry_run_diff(&shim_data)?);
+    }
+    log::info!("{}", output.trim_end());
+    return Ok(InstalledScript {
+      name: shim_data.name,
+      file_path: shim_data.file_path,
+      created_files: vec![],
+      path_hints: vec![],
+    });
+  }
+
+  // ensure directory exists
+  if let Ok(metadata) = fs::metadata(&shim_data.installation_dir) {
+    if !metadata.is_dir() {
+      return Err(generic_error("Installation path is not a directory"));
+    }
+  } else {
+    create_installation_dir(
+      &shim_data.installation_dir,
+      install_flags_global.wrapper_dir_permissions,
+    )?;
+  };
+  let _install_lock = lock_installation_dir(&shim_data.installation_dir).await;
+  if let Some(desktop_entry) = &shim_data.metadata.desktop_entry {
+    if let Some(applications_dir) = Path::new(desktop_entry).parent() {
+      fs::create_dir_all(applications_dir).with_context(|| {
+        format!("Failed to create {}", applications_dir.display())
+      })?;
+    }
+  }
+
+  if install_flags_global.fail_if_exists {
+    let existing = std::iter::once(&shim_data.file_path)
+      .chain(&shim_data.alias_paths)
+      .find(|path| path.exists());
+    if let Some(path) = existing {
+      return Err(
+        InstallError {
+          kind: InstallErrorKind::AlreadyInstalled,
+          message: format!("{} already exists", path.display()),
+          exit_code: ALREADY_INSTALLED_EXIT_CODE,
+        }
+        .into(),
+      );
+    }
+  }
+
+  // with --overwrite-if-older, existing installations left by now are old
+  let mut overwrite = install_flags_global.force
+    || install_flags_global.overwrite_if_older.is_some();
+  if shim_data.file_path.exists() && !overwrite {
+    let maybe_existing_url = read_metadata(&shim_data.file_path)
+      .map(|metadata| metadata.module_url)
+      .filter(|_| {
+        install_flags_global.replace_existing_of_same_url
+          || install_flags_global.only_if_newer
+      });
+    match maybe_existing_url {
+      Some(url) if url == shim_data.metadata.module_url => {
+        // updating an installation of the same module doesn't need --force
+        overwrite = true;
+      }
+      Some(url) => {
+        return Err(install_error(
+          InstallErrorKind::AlreadyInstalled,
+          format!("Existing installation found for a different module ({url}). Aborting (Use -f to overwrite)."),
+        ));
+      }
+      None => {
+        return Err(install_error(
+          InstallErrorKind::AlreadyInstalled,
+          "Existing installation found. Aborting (Use -f to overwrite).",
+        ));
+      }
+    }
+  };
+  for alias_path in &shim_data.alias_paths {
+    if alias_path.exists() && !overwrite {
+      return Err(install_error(
+        InstallErrorKind::AlreadyInstalled,
+        format!(
+          "Existing installation found for alias {}. Aborting (Use -f to overwrite).",
+          alias_path.display()
+        ),
+      ));
+    }
+  }
+
+  if cfg!(windows) {
+    if let Some(warning) = cmd_line_length_warning(&shim_data) {
+      log::warn!("{} {}", colors::yellow("Warning"), warning);
+    }
+  }
+
+  let created_files = write_installation_files(&RealInstallerFs, &shim_data)
+    .map_err(|err| {
+      explain_read_only_installation_dir(&shim_data.installation_dir, err)
+    })?;
+
+  if install_flags_global.verify_run {
+    let verify_args = install_flags_global
+      .verify_args
+      .clone()
+      .unwrap_or_else(|| vec!["--version".to_string()]);
+    if let Err(err) = verify_run(&shim_data.file_path, &verify_args) {
+      remove_installation(
+        &RealInstallerFs,
+        &shim_data.installation_dir,
+        &shim_data.name,
+      )?;
+      return Err(err.context(format!(
+        "Verifying {} failed, it was uninstalled again",
+        shim_data.name
+      )));
+    }
+  }
+
+  log::info!("✅ Successfully installed {}", shim_data.name);
+  log::info!("{}", shim_data.file_path.display());
+  if cfg!(windows) {
+    let display_path = shim_data.file_path.with_extension("");
+    log::info!("{} (shell)", display_path.display());
+  }
+  for alias_path in &shim_data.alias_paths {
+    log::info!("{} (alias)", alias_path.display());
+  }
+
+  Ok(InstalledScript {
+    path_hints: path_hints(&shim_data.installation_dir),
+    name: shim_data.name,
+    file_path: shim_data.file_path,
+    created_files,
+  })
+}
+
+/// The lock file held while checking for and writing an installation to
+/// `installation_dir`. It's kept next to the directory rather than in it, so
+/// that it isn't mistaken for an executable.
+fn install_lock_path(installation_dir: &Path) -> PathBuf {
+  installation_dir.with_file_name(INSTALL_LOCK_FILE_NAME)
+}
+
+const INSTALL_LOCK_FILE_NAME: &str = ".install.lock";
+
+/// Waits for the lock of `installation_dir`, which is held while adding,
+/// replacing or removing the files of its installations.
+async fn lock_installation_dir(
+  installation_dir: &Path,
+) -> LaxSingleProcessFsFlag {
+  LaxSingleProcessFsFlag::lock(
+    install_lock_path(installation_dir),
+    "Waiting for another installation to the same directory to finish...",
+  )
+  .await
+}
+
+/// The hints printed after installing to `installation_dir`: a note about
+/// duplicate PATH entries, or how to add it to the PATH.
+fn path_hints(installation_dir: &Path) -> Vec<String> {
+  let mut hints = Vec::new();
+  if let Some(note) = env::var_os("PATH")
+    .and_then(|paths| duplicate_path_entries_note(installation_dir, &paths))
+  {
+    hints.push(format!("ℹ️  {}", note));
+  }
+  if !is_in_path(installation_dir) {
+    let installation_dir_str = installation_dir.to_string_lossy();
+    hints.push(format!(
+      "ℹ️  Add {} to PATH\n    {}",
+      installation_dir_str,
+      add_to_path_command(
+        &installation_dir_str,
+        env::var("SHELL").ok().as_deref()
+      )
+    ));
+  }
+  hints
+}
+
+/// The PATH hints of a batch of installations, without duplicates, so that
+/// installing many executables to the same directory prints them once.
+fn batch_path_hints<'a>(
+  installed: impl IntoIterator<Item = &'a InstalledScript>,
+) -> Vec<String> {
+  let mut hints: Vec<String> = Vec::new();
+  for hint in installed.into_iter().flat_map(|script| &script.path_hints) {
+    if !hints.contains(hint) {
+      hints.push(hint.clone());
+    }
+  }
+  hints
+}
+
+/// Whether the RFC 3339 timestamp `installed_at` is more than `max_age`
+/// seconds before `now`. Timestamps that can't be parsed count as old.
+fn is_older_than(
+  installed_at: &str,
+  max_age: u64,
+  now: chrono::DateTime<chrono::Utc>,
+) -> bool {
+  let Ok(installed_at) = chrono::DateTime::parse_from_rfc3339(installed_at)
+  else {
+    return true;
+  };
+  let age = now.signed_duration_since(installed_at);
+  age.num_seconds() > max_age as i64
+}
+
+/// Formats an age in seconds with the largest unit it is a multiple of.
+fn format_age(seconds: u64) -> String {
+  for (unit, unit_seconds) in
+    [("w", 604800), ("d", 86400), ("h", 3600), ("m", 60)]
+  {
+    if seconds >= unit_seconds && seconds % unit_seconds == 0 {
+      return format!("{}{unit}", seconds / unit_seconds);
+    }
+  }
+  format!("{seconds}s")
+}
+
+/// Creates the installation directory along with its missing parents. On
+/// Unix, the directories created here are given `mode` when provided, while
+/// existing ones are left untouched.
+#[cfg_attr(windows, allow(unused_variables))]
+fn create_installation_dir(
+  installation_dir: &Path,
+  mode: Option<u32>,
+) -> Result<(), AnyError> {
+  let created_dirs = installation_dir
+    .ancestors()
+    .take_while(|dir| !dir.exists())
+    .map(Path::to_path_buf)
+    .collect::<Vec<_>>();
+  fs::create_dir_all(installation_dir)?;
+  #[cfg(not(windows))]
+  if let Some(mode) = mode {
+    for dir in &created_dirs {
+      fs::set_permissions(dir, fs::Permissions::from_mode(mode)).with_context(
+        || format!("Failed to set the permissions of {}", dir.display()),
+      )?;
+    }
+  }
+  Ok(())
+}
+
+/// Writes the executables, companion files and metadata of an installation.
+fn write_installation_files(
+  fs: &dyn InstallerFs,
+  shim_data: &ShimData,
+) -> Result<Vec<PathBuf>, AnyError> {
+  // companions are checked first so that nothing is written on error
+  let mut extra_files = Vec::with_capacity(shim_data.extra_files.len());
+  for (path, contents) in &shim_data.extra_files {
+    let is_edited = fs
+      .read_to_string(path)
+      .is_ok_and(|existing| existing != *contents);
+    if is_edited {
+      match shim_data.companions_mode {
+        InstallCompanionsMode::Overwrite => {}
+        InstallCompanionsMode::Preserve => {
+          log::info!("Preserving {}", path.display());
+          continue;
+        }
+        InstallCompanionsMode::Error => {
+          return Err(install_error(
+            InstallErrorKind::AlreadyInstalled,
+            format!(
+              "{} already exists with different content. Use --companions overwrite to replace it or --companions preserve to keep it.",
+              path.display()
+            ),
+          ));
+        }
+      }
+    }
+    extra_files.push((path, contents));
+  }
+
+  let mut created_files =
+    generate_executable_file(fs, shim_data, &shim_data.file_path)?;
+  for alias_path in &shim_data.alias_paths {
+    created_files.extend(generate_executable_file(fs, shim_data, alias_path)?);
+  }
+  for (path, contents) in extra_files {
+    fs.write(path, contents)
+      .with_context(|| format!("Failed to write {}", path.display()))?;
+    created_files.push(path.clone());
+  }
+  let metadata_path = get_metadata_path(&shim_data.file_path);
+  fs.write(
+    &metadata_path,
+    &serde_json::to_string_pretty(&shim_data.metadata)?,
+  )
+  .with_context(|| format!("Failed to write {}", metadata_path.display()))?;
+  created_files.push(metadata_path);
+  update_install_index(fs, &shim_data.installation_dir);
+  Ok(created_files)
+}
+
+/// A `bin` directory symlinked into a read-only mount fails with errors that
+/// only mention the file being written, so point out the symlink instead.
+fn explain_read_only_installation_dir(
+  installation_dir: &Path,
+  err: AnyError,
+) -> AnyError {
+  let is_symlink = fs::symlink_metadata(installation_dir)
+    .map(|metadata| metadata.file_type().is_symlink())
+    .unwrap_or(false);
+  let is_read_only = err
+    .chain()
+    .filter_map(|err| err.downcast_ref::<io::Error>())
+    .any(is_read_only_error);
+  if !is_symlink || !is_read_only {
+    return err;
+  }
+  let target = match fs::read_link(installation_dir) {
+    Ok(target) => target.display().to_string(),
+    Err(_) => "another location".to_string(),
+  };
+  err.context(format!(
+    "{} is a symlink to {}, which appears to be on a read-only mount. Point it to a writable directory or use --root.",
+    installation_dir.display(),
+    target
+  ))
+}
+
+fn is_read_only_error(err: &io::Error) -> bool {
+  #[cfg(unix)]
+  if err.raw_os_error() == Some(libc::EROFS) {
+    return true;
+  }
+  err.kind() == io::ErrorKind::PermissionDenied
+}
+
+struct ShimData {
+  name: String,
+  installation_dir: PathBuf,
+  file_path: PathBuf,
+  args: Vec<String>,
+  extra_files: Vec<(PathBuf, String)>,
+  alias_paths: Vec<PathBuf>,
+  min_deno_version: Option<Version>,
+  pre_run: Option<String>,
+  /// Directory the executable changes to before invoking deno.
+  working_dir: Option<PathBuf>,
+  /// Environment variables set before invoking deno.
+  env: Vec<(String, String)>,
+  /// Extra comment following the generated marker, on a single line.
+  wrapper_comment: Option<String>,
+  /// The rendered `--template` the shell script is written from, with the
+  /// `{{DENO_ARGS}}` placeholder left in place.
+  custom_template: Option<String>,
+  /// Only make the executable accessible to its owner (Unix only).
+  #[cfg_attr(windows, allow(dead_code))]
+  owner_only: bool,
+  /// Write a `#!/usr/bin/env -S deno run` script when possible (Unix only).
+  #[cfg_attr(windows, allow(dead_code))]
+  shebang_env: bool,
+  /// Write the `.cmd` file with CRLF line endings (Windows only).
+  #[cfg_attr(not(windows), allow(dead_code))]
+  cmd_crlf: bool,
+  /// Also write a copy of the `.cmd` file with a `.bat` extension (Windows
+  /// only).
+  #[cfg_attr(not(windows), allow(dead_code))]
+  bat: bool,
+  /// Which of the `.cmd` file and the shell script to write (Windows only).
+  #[cfg_attr(not(windows), allow(dead_code))]
+  output_format: InstallOutputFormat,
+  /// What to do with existing companion files whose content differs.
+  companions_mode: InstallCompanionsMode,
+  /// File the permissions recorded with `--grant-prompt-once` are read from.
+  grant_state_file: Option<PathBuf>,
+  /// Reference the files in the installation directory relative to the
+  /// executables.
+  relocatable: bool,
+  metadata: InstallMetadata,
+}
+
+async fn resolve_shim_data(
+  http_client_provider: &HttpClientProvider,
+  flags: &Flags,
+  install_flags_global: &InstallFlagsGlobal,
+) -> Result<ShimData, AnyError> {
+  resolve_shim_data_with_registries(
+    http_client_provider,
+    flags,
+    install_flags_global,
+    &RegistryUrls::default(),
+  )
+  .await
+}
+
+async fn resolve_shim_data_with_registries(
+  http_client_provider: &HttpClientProvider,
+  flags: &Flags,
+  install_flags_global: &InstallFlagsGlobal,
+  registry_urls: &RegistryUrls,
+) -> Result<ShimData, AnyError> {
+  let entry_flags;
+  let flags = if install_flags_global.deno_flags.is_empty() {
+    flags
+  } else {
+    entry_flags =
+      apply_entry_deno_flags(flags, &install_flags_global.deno_flags)?;
+    &entry_flags
+  };
+  let cwd = std::env::current_dir().context("Unable to get CWD")?;
+  let timeout = network_timeout(install_flags_global);
+  let root = resolve_root(install_flags_global.root.as_ref())?;
+  let installation_dir = get_installation_dir(&root)?;
+  let base_dir = resolve_base_dir(install_flags_global, &cwd);
+  validate_script_input(install_flags_global)?;
+  let module_specifier = resolve_bare_name(
+    &install_flags_global.module_url,
+    registry_default(install_flags_global).as_deref(),
+    &base_dir,
+  )?;
+
+  // Check if module_url is remote
+  // Note: parsing normalizes `file://localhost/` URLs to `file:///`, which
+  // is the form forwarded to `deno run` by the generated executables.
+  let mut module_url = resolve_url_or_path(&module_specifier, &base_dir)
+    .map_err(|err| module_url_error(&module_specifier, &err.to_string()))?;
+  ensure_supported_scheme(&module_url)?;
+  if !install_flags_global.no_pin {
+    if let Some(pinned) =
+      pin_jsr_module(http_client_provider, registry_urls, &module_url, timeout)
+        .await?
+    {
+      module_url = pinned;
+    }
+  }
+
+  // Windows executables get their `.cmd` extension in its place
+  let keep_extension = install_flags_global.keep_extension && !cfg!(windows);
+  let name = if install_flags_global.name.is_some() {
+    install_flags_global.name.clone()
+  } else if let Some(index) = install_flags_global.name_from_arg {
+    Some(name_from_url_segment(&module_url, index)?)
+  } else {
+    infer_name_from_url_with_generic_names(
+      http_client_provider,
+      &module_url,
+      &install_flags_global.generic_stems,
+      &install_flags_global.generic_parents,
+      timeout,
+    )
+    .await
+    .map(|name| match keep_extension {
+      true => keep_module_extension(name, &module_url),
+      false => name,
+    })
+    .map(|name| match &install_flags_global.strip_prefix {
+      Some(prefix) => strip_name_prefix(name, prefix),
+      None => name,
+    })
+  };
+
+  let name = match name {
+    Some(name) => name,
+    None => resolve_missing_name(
+      install_flags_global.stdin_name && io::stdin().is_terminal(),
+      &mut io::stdin().lock(),
+      &mut io::stderr(),
+    )?,
+  };
+
+  if keep_extension {
+    validate_name_with_extension(&name)?;
+  } else if install_flags_global.strict_name {
+    validate_strict_name(&name)?;
+  } else {
+    validate_name(&name)?;
+  }
+  // Windows file names are case-insensitive, so lowercasing them keeps the
+  // file names the same across platforms while the name in the metadata
+  // stays as provided
+  let lowercase_file_names =
+    cfg!(windows) && install_flags_global.lowercase_windows_name;
+  let file_name = |name: &str| {
+    if lowercase_file_names {
+      name.to_lowercase()
+    } else {
+      name.to_string()
+    }
+  };
+  let mut file_path = installation_dir.join(file_name(&name));
+
+  if cfg!(windows) {
+    file_path = file_path.with_extension("cmd");
+  }
+
+  let mut alias_paths = Vec::with_capacity(install_flags_global.aliases.len());
+  for alias in &install_flags_global.aliases {
+    if install_flags_global.strict_name {
+      validate_strict_name(alias)?;
+    } else {
+      validate_name(alias)?;
+    }
+    if *alias == name {
+      return Err(generic_error(format!(
+        "Alias {alias} is the same as the executable name"
+      )));
+    }
+    let mut alias_path = installation_dir.join(file_name(alias));
+    if cfg!(windows) {
+      alias_path = alias_path.with_extension("cmd");
+    }
+    alias_paths.push(alias_path);
+  }
+
+  // installing an executable (or the file it is about to be written to)
+  // would make the executable run deno on itself
+  if let Ok(module_path) = module_url.to_file_path() {
+    let module_path = canonicalize_path_maybe_not_exists(&module_path)?;
+    let installation_dir =
+      canonicalize_path_maybe_not_exists(&installation_dir)?;
+    if module_path.parent() == Some(installation_dir.as_path()) {
+      return Err(generic_error(format!(
+        "{} is in the installation directory {}, so the executable would run an installed executable instead of a script. Install the original script instead.",
+        module_path.display(),
+        installation_dir.display()
+      )));
+    }
+  }
+
+  let min_deno_version = install_flags_global
+    .min_deno_version
+    .as_ref()
+    .map(|version| {
+      Version::parse_standard(version).map_err(|_| {
+        generic_error(format!("Invalid minimum deno version: {version}"))
+      })
+    })
+    .transpose()?;
+
+  if cfg!(windows) && install_flags_global.owner_only {
+    log::warn!(
+      "{} --owner-only has no effect on Windows.",
+      colors::yellow("Warning"),
+    );
+  }
+  if cfg!(windows) && install_flags_global.keep_extension {
+    log::warn!(
+      "{} --keep-extension has no effect on Windows.",
+      colors::yellow("Warning"),
+    );
+  }
+  if cfg!(windows) && install_flags_global.shebang_env {
+    log::warn!(
+      "{} --shebang-env has no effect on Windows.",
+      colors::yellow("Warning"),
+    );
+  }
+
+  let env = install_flags_global
+    .env
+    .iter()
+    .map(|env_var| parse_env_var(env_var))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let pre_run = install_flags_global.pre_run.clone();
+  if let Some(pre_run) = &pre_run {
+    if pre_run.contains(['\n', '\r']) {
+      return Err(generic_error("The --pre-run command must be a single line"));
+    }
+    log::warn!(
+      "{} The --pre-run command is inserted as is into the generated scripts, which may not work for every shell they target.",
+      colors::yellow("Warning"),
+    );
+  }
+
+  let working_dir = match &install_flags_global.cwd {
+    Some(dir) => {
+      let dir = canonicalize_path_maybe_not_exists(&cwd.join(dir))?;
+      if !dir.is_dir() {
+        return Err(generic_error(format!(
+          "The working directory {} does not exist",
+          dir.display()
+        )));
+      }
+      Some(dir)
+    }
+    None => None,
+  };
+
+  let mut extra_files: Vec<(PathBuf, String)> = vec![];
+  let mut companions: Vec<CompanionSource> = vec![];
+
+  let deno_flags = recorded_deno_flags(flags, &base_dir)?;
+  let mut executable_args = vec!["run".to_string()];
+  executable_args.extend(runtime_args(flags));
+  let mut permission_args = flags.to_permission_args();
+  for arg in permission_args.iter_mut() {
+    if let Some(hosts) = arg.strip_prefix("--allow-net=") {
+      let hosts = hosts
+        .split(',')
+        .map(|host| {
+          let (host, warning) = normalize_net_host(host);
+          if let Some(warning) = warning {
+            log::warn!("{} {}", colors::yellow("Warning"), warning);
+          }
+          host
+        })
+        .collect::<Vec<_>>();
+      *arg = format!("--allow-net={}", hosts.join(","));
+    }
+  }
+  if install_flags_global.verify_permissions
+    || install_flags_global.permission_policy.is_some()
+  {
+    let policy = match &install_flags_global.permission_policy {
+      Some(policy_path) => read_permission_policy(&cwd.join(policy_path))?,
+      None => PermissionPolicy::default(),
+    };
+    policy.check(&permission_args)?;
+  }
+  executable_args.extend(permission_args);
+  if let Some(url) = flags.location.as_ref() {
+    executable_args.push("--location".to_string());
+    executable_args.push(url.to_string());
+  }
+  if let Some(CaData::File(ca_file)) = &flags.ca_data {
+    executable_args.push("--cert".to_string());
+    executable_args.push(ca_file.to_owned())
+  }
+  if let Some(log_level) = flags.log_level {
+    if log_level == Level::Error {
+      executable_args.push("--quiet".to_string());
+    } else {
+      executable_args.push("--log-level".to_string());
+      let log_level = match log_level {
+        Level::Debug => "debug",
+        Level::Info => "info",
+        _ => {
+          return Err(generic_error(format!("invalid log level {log_level}")))
+        }
+      };
+      executable_args.push(log_level.to_string());
+    }
+  }
+
+  // we should avoid a default branch here to ensure we continue to cover any
+  // changes to this flag.
+  match flags.type_check_mode {
+    TypeCheckMode::All => executable_args.push("--check=all".to_string()),
+    TypeCheckMode::None => {}
+    TypeCheckMode::Local => executable_args.push("--check".to_string()),
+  }
+
+  if flags.unstable_config.legacy_flag_enabled {
+    executable_args.push("--unstable".to_string());
+  }
+
+  for feature in &flags.unstable_config.features {
+    executable_args.push(format!("--unstable-{}", feature));
+  }
+
+  if flags.no_remote {
+    executable_args.push("--no-remote".to_string());
+  }
+
+  if flags.no_npm {
+    executable_args.push("--no-npm".to_string());
+  }
+
+  // npm packages installed by the executable run the same lifecycle scripts
+  // as when it was installed
+  if let Some(node_modules_dir) = flags.node_modules_dir {
+    executable_args.push(format!("--node-modules-dir={node_modules_dir}"));
+  }
+
+  match &flags.allow_scripts {
+    PackagesAllowedScripts::All => {
+      executable_args.push("--allow-scripts".to_string())
+    }
+    PackagesAllowedScripts::Some(packages) => {
+      executable_args.push(format!("--allow-scripts={}", packages.join(",")))
+    }
+    PackagesAllowedScripts::None => {}
+  }
+
+  if flags.lock_write {
+    executable_args.push("--lock-write".to_string());
+  }
+
+  if flags.cached_only {
+    executable_args.push("--cached-only".to_string());
+    if matches!(module_url.scheme(), "http" | "https")
+      && !is_module_cached(flags.cache_path.clone(), &module_url)?
+    {
+      log::warn!(
+        "{} {} is not cached yet. {} will fail until it is, as it runs with --cached-only.",
+        colors::yellow("Warning"),
+        module_url,
+        name
+      );
+    }
+  }
+
+  if flags.frozen_lockfile {
+    executable_args.push("--frozen".to_string());
+  }
+
+  if resolve_no_prompt(&flags.permissions) {
+    executable_args.push("--no-prompt".to_string());
+  }
+
+  if let Some(inspect) = flags.inspect {
+    executable_args.push(format!("--inspect={inspect}"));
+  }
+
+  if let Some(inspect_brk) = flags.inspect_brk {
+    executable_args.push(format!("--inspect-brk={inspect_brk}"));
+  }
+
+  if let Some(import_map_path) = &flags.import_map_path {
+    let import_map_url = resolve_url_or_path(import_map_path, &base_dir)?;
+    executable_args.push("--import-map".to_string());
+    if let Some(overlay) = &install_flags_global.import_map_overlay {
+      let copy_path = get_hidden_file_with_ext(&file_path, "import_map.json");
+      executable_args.push(copy_path.to_str().unwrap().to_string());
+      let companion = CompanionSource {
+        path: copy_path.clone(),
+        source: import_map_url.to_string(),
+        import_map_overlay: Some(overlay.clone()),
+      };
+      extra_files.push((
+        copy_path,
+        companion.read(http_client_provider, timeout).await?,
+      ));
+      companions.push(companion);
+    } else {
+      executable_args.push(import_map_url.to_string());
+    }
+  } else if install_flags_global.auto_import_map {
+    let auto_import_map = match module_url.to_file_path() {
+      Ok(module_path) => find_auto_import_map(&module_path)?,
+      Err(_) => None,
+    };
+    if let Some(auto_import_map) = auto_import_map {
+      let copy_path = get_hidden_file_with_ext(&file_path, "import_map.json");
+      executable_args.push("--import-map".to_string());
+      executable_args.push(copy_path.to_str().unwrap().to_string());
+      match auto_import_map {
+        AutoImportMap::File(import_map_path) => {
+          let companion = CompanionSource {
+            path: copy_path.clone(),
+            source: import_map_path.to_string_lossy().to_string(),
+            import_map_overlay: None,
+          };
+          extra_files.push((
+            copy_path,
+            companion.read(http_client_provider, timeout).await?,
+          ));
+          companions.push(companion);
+        }
+        // not a companion, refreshing it would copy the whole config
+        AutoImportMap::Inline(import_map) => {
+          extra_files.push((copy_path, import_map));
+        }
+      }
+    }
+  }
+
+  if let ConfigFlag::Path(config_path) = &flags.config_flag {
+    let copy_path = get_hidden_file_with_ext(&file_path, "deno.json");
+    executable_args.push("--config".to_string());
+    executable_args.push(copy_path.to_str().unwrap().to_string());
+    let companion = CompanionSource {
+      path: copy_path.clone(),
+      source: base_dir.join(config_path).to_string_lossy().to_string(),
+      import_map_overlay: None,
+    };
+    extra_files.push((
+      copy_path,
+      companion.read(http_client_provider, timeout).await?,
+    ));
+    companions.push(companion);
+  } else {
+    executable_args.push("--no-config".to_string());
+  }
+
+  if flags.no_lock {
+    executable_args.push("--no-lock".to_string());
+  } else if let (Some(lock_path), true) =
+    (&flags.lock, install_flags_global.lock_no_copy)
+  {
+    let lock_path =
+      canonicalize_path_maybe_not_exists(&base_dir.join(lock_path))?;
+    log::warn!(
+      "{} {} uses the lockfile at {} directly. Moving or deleting it will break the installation.",
+      colors::yellow("Warning"),
+      name,
+      lock_path.display()
+    );
+    executable_args.push("--lock".to_string());
+    executable_args.push(lock_path.to_string_lossy().to_string());
+  } else if flags.lock.is_some()
+    // always use a lockfile for an npm entrypoint unless --no-lock
+    || NpmPackageReqReference::from_specifier(&module_url).is_ok()
+  {
+    let copy_path = get_hidden_file_with_ext(&file_path, "lock.json");
+    executable_args.push("--lock".to_string());
+    executable_args.push(copy_path.to_str().unwrap().to_string());
+
+    if let Some(lock_path) = &flags.lock {
+      let companion = CompanionSource {
+        path: copy_path.clone(),
+        source: base_dir.join(lock_path).to_string_lossy().to_string(),
+        import_map_overlay: None,
+      };
+      extra_files.push((
+        copy_path,
+        companion.read(http_client_provider, timeout).await?,
+      ));
+      companions.push(companion);
+    } else {
+      // Provide an empty lockfile so that this overwrites any existing lockfile
+      // from a previous installation. This will get populated on first run.
+      extra_files.push((copy_path, "{}".to_string()));
+    }
+  }
+
+  let mut desktop_entry = None;
+  if install_flags_global.desktop_entry {
+    if cfg!(target_os = "linux") {
+      let path = applications_dir(
+        env::var_os("XDG_DATA_HOME").as_deref(),
+        env::var_os("HOME").as_deref(),
+      )?
+      .join(format!("{name}.desktop"));
+      // icon theme names are kept as is
+      let icon = install_flags_global.desktop_icon.as_ref().map(|icon| {
+        if icon.contains('/') {
+          cwd.join(icon).to_string_lossy().to_string()
+        } else {
+          icon.clone()
+        }
+      });
+      extra_files.push((
+        path.clone(),
+        render_desktop_entry(&name, &file_path, icon.as_deref()),
+      ));
+      desktop_entry = Some(path.to_string_lossy().to_string());
+    } else {
+      log::warn!(
+        "{} --desktop-entry is only supported on Linux.",
+        colors::yellow("Warning"),
+      );
+    }
+  }
+
+  let mut grant_state_file = None;
+  let mut entrypoint = module_url.to_string();
+  if install_flags_global.grant_prompt_once {
+    if install_flags_global.verify_permissions
+      || install_flags_global.permission_policy.is_some()
+    {
+      return Err(generic_error(
+        "--grant-prompt-once can't be used with --verify-permissions or --permission-policy, as the permissions it records are granted without being verified.",
+      ));
+    }
+    if module_url.scheme() == "npm" {
+      return Err(install_error(
+        InstallErrorKind::InvalidModuleUrl,
+        format!(
+          "--grant-prompt-once can't install {module_url}, as it imports the module and importing an npm package doesn't run its bin."
+        ),
+      ));
+    }
+    if may_check_main_module(&module_url) {
+      log::warn!(
+        "{} With --grant-prompt-once, {module_url} is imported by a generated entrypoint rather than run, so import.meta.main is false in it and code guarded by it won't run.",
+        colors::yellow("Warning"),
+      );
+    }
+    let state_path = get_hidden_file_with_ext(&file_path, "grants");
+    let recorder_path = get_hidden_file_with_ext(&file_path, "grant.js");
+    if !flags.permissions.allow_all {
+      let state_path = state_path.to_string_lossy();
+      let write_arg = executable_args.iter_mut().find(|arg| {
+        arg.as_str() == "--allow-write" || arg.starts_with("--allow-write=")
+      });
+      match write_arg {
+        // writing anywhere is allowed already
+        Some(arg) if arg.as_str() == "--allow-write" => {}
+        Some(arg) => {
+          arg.push(',');
+          arg.push_str(&state_path);
+        }
+        None => executable_args.push(format!("--allow-write={state_path}")),
+      }
+    }
+    extra_files.push((
+      recorder_path.clone(),
+      render_grant_recorder(&state_path, &module_url),
+    ));
+    entrypoint = recorder_path.to_string_lossy().to_string();
+    grant_state_file = Some(state_path);
+  }
+
+  let script_args = if install_flags_global.merge_args {
+    let installed_args = read_metadata(&file_path)
+      .map(|metadata| metadata.args)
+      .unwrap_or_default();
+    merge_script_args(installed_args, &install_flags_global.args)
+  } else {
+    install_flags_global.args.clone()
+  };
+
+  let mut executable_args = dedup_single_valued_flags(executable_args);
+  executable_args.push(entrypoint);
+  executable_args.extend_from_slice(&script_args);
+
+  let wrapper_comment = install_flags_global
+    .wrapper_comment
+    .as_ref()
+    .map(|comment| comment.lines().collect::<Vec<_>>().join(" "));
+
+  let template_path = install_flags_global
+    .template
+    .as_ref()
+    .map(|template| cwd.join(template));
+  let custom_template = match &template_path {
+    Some(template_path) => {
+      let template = fs::read_to_string(template_path).with_context(|| {
+        format!("Failed reading template {}", template_path.display())
+      })?;
+      Some(render_custom_template(
+        &template,
+        &name,
+        module_url.as_str(),
+        &install_flags_global.template_vars,
+      )?)
+    }
+    None => None,
+  };
+
+  let metadata = InstallMetadata {
+    name: name.clone(),
+    module_url: module_url.to_string(),
+    args: script_args,
+    aliases: install_flags_global.aliases.clone(),
+    min_deno_version: min_deno_version.as_ref().map(|v| v.to_string()),
+    pre_run: pre_run.clone(),
+    working_dir: working_dir
+      .as_ref()
+      .map(|dir| dir.to_string_lossy().to_string()),
+    companions,
+    env: install_flags_global.env.clone(),
+    wrapper_comment: wrapper_comment.clone(),
+    registry: detect_registry_source(&module_url),
+    shorthand: install_flags_global
+      .module_url
+      .starts_with("gh:")
+      .then(|| install_flags_global.module_url.clone()),
+    desktop_entry,
+    installed_at: Some(
+      chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+    ),
+    template: template_path
+      .as_ref()
+      .map(|path| path.to_string_lossy().to_string()),
+    deno_flags,
+    ..Default::default()
+  };
+
+  // the shebang script imports the module, which only behaves like running
+  // it when the module doesn't check whether it is the main module
+  let shebang_env = install_flags_global.shebang_env
+    && !cfg!(windows)
+    && {
+      let reason = if module_url.scheme() == "npm" {
+        Some("importing an npm package doesn't run its bin")
+      } else if may_check_main_module(&module_url) {
+        Some("it may check import.meta.main or Deno.mainModule, which tell running it apart from importing it")
+      } else {
+        None
+      };
+      if let Some(reason) = reason {
+        log::warn!(
+        "{} Writing a shell script for {name} instead of a --shebang-env script, as that script would import {module_url} and {reason}.",
+        colors::yellow("Warning"),
+      );
+      }
+      reason.is_none()
+    };
+
+  let mut shim_data = ShimData {
+    name,
+    installation_dir,
+    file_path,
+    args: executable_args,
+    extra_files,
+    alias_paths,
+    min_deno_version,
+    pre_run,
+    working_dir,
+    env,
+    wrapper_comment,
+    custom_template,
+    owner_only: install_flags_global.owner_only,
+    shebang_env,
+    cmd_crlf: install_flags_global.cmd_crlf,
+    bat: install_flags_global.bat,
+    output_format: install_flags_global.output_format,
+    companions_mode: install_flags_global.companions_mode,
+    grant_state_file,
+    relocatable: install_flags_global.relocatable,
+    metadata,
+  };
+  check_custom_template(&shim_data)?;
+  shim_data.metadata.wrappers =
+    render_executable_files(&shim_data, &shim_data.file_path)
+      .into_iter()
+      .filter_map(|(path, _)| {
+        Some(path.file_name()?.to_string_lossy().to_string())
+      })
+      .collect();
+  Ok(shim_data)
+}
+
+/// Asks for the name of the executable when it wasn't provided and couldn't
+/// be inferred. Names are read from `input` until a valid one is entered.
+/// Without `interactive`, this fails right away.
+fn resolve_missing_name(
+  interactive: bool,
+  input: &mut impl BufRead,
+  output: &mut impl Write,
+) -> Result<String, AnyError> {
+  let not_provided = || {
+    generic_error(
+      "An executable name was not provided. One could not be inferred from the URL. Aborting.",
+    )
+  };
+  if !interactive {
+    return Err(not_provided());
+  }
+  loop {
+    write!(output, "Executable name: ")?;
+    output.flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+      writeln!(output)?;
+      return Err(not_provided());
+    }
+    let name = line.trim();
+    match validate_name(name) {
+      Ok(()) => return Ok(name.to_string()),
+      Err(err) if !name.is_empty() => writeln!(output, "{err}")?,
+      Err(_) => {}
+    }
+  }
+}
+
+/// The directory relative module, config, lockfile and import map paths are
+/// resolved against: `--base-dir` or the current working directory.
+fn resolve_base_dir(
+  install_flags_global: &InstallFlagsGlobal,
+  cwd: &Path,
+) -> PathBuf {
+  match &install_flags_global.base_dir {
+    Some(base_dir) => cwd.join(base_dir),
+    None => cwd.to_path_buf(),
+  }
+}
+
+fn module_url_error(specifier: &str, reason: &str) -> AnyError {
+  let message = if specifier_has_uri_scheme(specifier) {
+    format!("Remote URL could not be parsed: {specifier}\n  {reason}")
+  } else {
+    format!("Local path does not exist or is invalid: {specifier}\n  {reason}")
+  };
+  install_error(InstallErrorKind::InvalidModuleUrl, message)
+}
+
+/// The schemes of the modules the generated executables can run.
+const SUPPORTED_SCHEMES: [&str; 6] =
+  ["file", "http", "https", "npm", "jsr", "data"];
+
+fn ensure_supported_scheme(module_url: &Url) -> Result<(), AnyError> {
+  if SUPPORTED_SCHEMES.contains(&module_url.scheme()) {
+    return Ok(());
+  }
+  Err(install_error(
+    InstallErrorKind::InvalidModuleUrl,
+    format!(
+      "Unsupported scheme \"{}\" of {module_url}. Supported schemes are: {}",
+      module_url.scheme(),
+      SUPPORTED_SCHEMES.join(", ")
+    ),
+  ))
+}
+
+/// Resolves the module specifier passed to `deno install`, producing an
+/// error tailored to whether the specifier looked like a URL or a path.
+fn resolve_install_module_url(
+  specifier: &str,
+  cwd: &Path,
+) -> Result<Url, AnyError> {
+  let module_url = resolve_url_or_path(specifier, cwd)
+    .map_err(|err| module_url_error(specifier, &err.to_string()))?;
+  ensure_supported_scheme(&module_url)?;
+  if !specifier_has_uri_scheme(specifier) {
+    let exists = module_url
+      .to_file_path()
+      .map(|path| path.exists())
+      .unwrap_or(false);
+    if !exists {
+      return Err(module_url_error(specifier, "No such file or directory"));
+    }
+  }
+  Ok(module_url)
+}
+
+/// The import map declared by the `deno.json` next to a local module, picked
+/// up with `--auto-import-map`.
+#[derive(Debug, PartialEq, Eq)]
+enum AutoImportMap {
+  /// The import map file referenced by `importMap`.
+  File(PathBuf),
+  /// An import map made of the `imports` and `scopes` of the config.
+  Inline(String),
+}
+
+fn find_auto_import_map(
+  module_path: &Path,
+) -> Result<Option<AutoImportMap>, AnyError> {
+  let Some(dir) = module_path.parent() else {
+    return Ok(None);
+  };
+  let config_path = dir.join("deno.json");
+  let text = match fs::read_to_string(&config_path) {
+    Ok(text) => text,
+    Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+    Err(err) => {
+      return Err(err)
+        .with_context(|| format!("error reading {}", config_path.display()))
+    }
+  };
+  let config: serde_json::Value = serde_json::from_str(&text)
+    .with_context(|| format!("error parsing {}", config_path.display()))?;
+  if let Some(import_map) = config.get("importMap").and_then(|v| v.as_str()) {
+    log::info!(
+      "Using the import map {} of {}",
+      import_map,
+      config_path.display()
+    );
+    return Ok(Some(AutoImportMap::File(dir.join(import_map))));
+  }
+  let mut import_map = serde_json::Map::new();
+  for key in ["imports", "scopes"] {
+    if let Some(value) = config.get(key) {
+      import_map.insert(key.to_string(), value.clone());
+    }
+  }
+  if import_map.is_empty() {
+    return Ok(None);
+  }
+  log::info!("Using the imports of {}", config_path.display());
+  Ok(Some(AutoImportMap::Inline(serde_json::to_string_pretty(
+    &import_map,
+  )?)))
+}
+
+/// The permissions a `--grant-prompt-once` executable records. Only
+/// permissions granted to all of their kind are recorded.
+const RECORDED_PERMISSIONS: [&str; 7] =
+  ["read", "write", "net", "env", "run", "sys", "ffi"];
+
+/// Renders the entrypoint of a `--grant-prompt-once` executable. It imports
+/// the installed module and, when the program exits, writes the permissions
+/// granted so far to `state_path` as `--allow-*` flags, one per line.
+/// As the module is imported, `import.meta.main` is false in it.
+fn render_grant_recorder(state_path: &Path, module_url: &Url) -> String {
+  let json = |value: &str| serde_json::Value::from(value).to_string();
+  let names = RECORDED_PERMISSIONS
+    .iter()
+    .map(|name| json(name))
+    .collect::<Vec<_>>()
+    .join(", ");
+  format!(
+    r#"// {GENERATED_MARKER}
+const statePath = {};
+globalThis.addEventListener("unload", () => {{
+  const granted = [{names}].filter(
+    (name) => Deno.permissions.querySync({{ name }}).state === "granted",
+  );
+  try {{
+    Deno.writeTextFileSync(
+      statePath,
+      granted.map((name) => `--allow-${{name}}\n`).join(""),
+    );
+  }} catch {{
+    // failing to record the grants must not fail the program
+  }}
+}});
+await import({});
+"#,
+    json(&state_path.to_string_lossy()),
+    json(module_url.as_str()),
+  )
+}
+
+/// The index of the deno invocation argument before which the flags
+/// recorded with `--grant-prompt-once` are spliced in, which is right before
+/// the entrypoint.
+fn grant_flags_index(shim_data: &ShimData) -> usize {
+  shim_data.args.len() - shim_data.metadata.args.len() - 1
+}
+
+fn get_hidden_file_with_ext(file_path: &Path, ext: &str) -> PathBuf {
+  // use a dot file to prevent the file from showing up in some
+  // users shell auto-complete since this directory is on the PATH
+  // Only the `.cmd` extension of Windows executables is replaced, so that
+  // `server.ts` installed with `--keep-extension` and `server` don't share
+  // their hidden files.
+  let name = if cfg!(windows) {
+    file_path.file_stem()
+  } else {
+    file_path.file_name()
+  };
+  file_path.with_file_name(format!(
+    ".{}.{}",
+    name.unwrap().to_string_lossy(),
+    ext
+  ))
+}
+
+/// Returns the directory desktop entries of the user are written to:
+/// `$XDG_DATA_HOME/applications`, or `~/.local/share/applications` when
+/// `XDG_DATA_HOME` isn't an absolute path.
+fn applications_dir(
+  xdg_data_home: Option<&std::ffi::OsStr>,
+  home: Option<&std::ffi::OsStr>,
+) -> Result<PathBuf, AnyError> {
+  // relative paths are invalid according to the XDG base directory spec
+  let data_home = match xdg_data_home.map(PathBuf::from) {
+    Some(data_home) if data_home.is_absolute() => data_home,
+    _ => match home.filter(|home| !home.is_empty()) {
+      Some(home) => Path::new(home).join(".local").join("share"),
+      None => {
+        return Err(generic_error(
+          "Unable to determine the applications directory for --desktop-entry: $HOME is not defined.",
+        ))
+      }
+    },
+  };
+  let dir = data_home.join("applications");
+  if dir.exists() && !dir.is_dir() {
+    return Err(generic_error(format!(
+      "The applications directory {} is not a directory",
+      dir.display()
+    )));
+  }
+  Ok(dir)
+}
+
+/// Escapes a string value of a desktop entry.
+fn escape_desktop_entry_value(value: &str) -> String {
+  value
+    .replace('\\', "\\\\")
+    .replace('\n', "\\n")
+    .replace('\t', "\\t")
+    .replace('\r', "\\r")
+}
+
+/// Quotes an argument of the `Exec` key of a desktop entry, escaping the
+/// characters that are reserved in quoted arguments and field codes.
+fn quote_desktop_exec_arg(arg: &str) -> String {
+  let mut quoted = String::with_capacity(arg.len() + 2);
+  quoted.push('"');
+  for c in arg.chars() {
+    match c {
+      '"' | '`' | '$' | '\\' => {
+        quoted.push('\\');
+        quoted.push(c);
+      }
+      '%' => quoted.push_str("%%"),
+      c => quoted.push(c),
+    }
+  }
+  quoted.push('"');
+  quoted
+}
+
+/// Renders a desktop entry launching the executable at `file_path`.
+fn render_desktop_entry(
+  name: &str,
+  file_path: &Path,
+  icon: Option<&str>,
+) -> String {
+  let mut entry = format!(
+    "# {GENERATED_MARKER}\n[Desktop Entry]\nType=Application\nName={}\nExec={}\nTerminal=false\n",
+    escape_desktop_entry_value(name),
+    escape_desktop_entry_value(&quote_desktop_exec_arg(
+      &file_path.to_string_lossy()
+    )),
+  );
+  if let Some(icon) = icon {
+    entry.push_str(&format!("Icon={}\n", escape_desktop_entry_value(icon)));
+  }
+  entry
+}
+
+/// Returns the command that adds `installation_dir` to the PATH in the
+/// user's shell.
+fn add_to_path_command(installation_dir: &str, shell: Option<&str>) -> String {
+  if cfg!(windows) {
+    return format!("set PATH=%PATH%;{}", installation_dir);
+  }
+  let is_fish = shell
+    .and_then(|shell| Path::new(shell).file_name())
+    .map(|name| name == "fish")
+    .unwrap_or(false);
+  if is_fish {
+    format!("fish_add_path {}", installation_dir)
+  } else {
+    format!("export PATH=\"{}:$PATH\"", installation_dir)
+  }
+}
+
+/// Permissions an installed command is not allowed to be granted, checked
+/// when installing with `--verify-permissions`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct PermissionPolicy {
+  /// Forbidden permission flags, e.g. `allow-run`. Forbidding a flag also
+  /// forbids its scoped variants, like `--allow-run=deno`.
+  forbid: Vec<String>,
+}
+
+impl Default for PermissionPolicy {
+  fn default() -> Self {
+    Self {
+      forbid: vec!["allow-all".to_string(), "allow-run".to_string()],
+    }
+  }
+}
+
+impl PermissionPolicy {
+  fn check(&self, permission_args: &[String]) -> Result<(), AnyError> {
+    let offending = permission_args
+      .iter()
+      .filter(|arg| {
+        let flag = arg.split('=').next().unwrap_or(arg).trim_start_matches('-');
+        self
+          .forbid
+          .iter()
+          .any(|forbidden| forbidden.trim_start_matches('-') == flag)
+      })
+      .map(|arg| arg.as_str())
+      .collect::<Vec<_>>();
+    if offending.is_empty() {
+      Ok(())
+    } else {
+      Err(generic_error(format!(
+        "The permissions of the installed command are not allowed by the permission policy: {}",
+        offending.join(", ")
+      )))
+    }
+  }
+}
+
+fn read_permission_policy(
+  policy_path: &Path,
+) -> Result<PermissionPolicy, AnyError> {
+  let text = fs::read_to_string(policy_path)
+    .with_context(|| format!("error reading {}", policy_path.display()))?;
+  serde_json::from_str(&text)
+    .with_context(|| format!("error parsing {}", policy_path.display()))
+}
+
+/// The permission, config, lockfile, import map and type checking flags of
+/// an installation, recorded in its metadata so that an exported manifest
+/// reinstalls it the same way. Paths are made absolute, as the manifest may
+/// be installed from anywhere.
+fn recorded_deno_flags(
+  flags: &Flags,
+  base_dir: &Path,
+) -> Result<Vec<String>, AnyError> {
+  let mut deno_flags = flags.to_permission_args();
+  match &flags.config_flag {
+    ConfigFlag::Path(config_path) => deno_flags
+      .push(format!("--config={}", base_dir.join(config_path).display())),
+    ConfigFlag::Disabled => deno_flags.push("--no-config".to_string()),
+    ConfigFlag::Discover => {}
+  }
+  if flags.no_lock {
+    deno_flags.push("--no-lock".to_string());
+  } else if let Some(lock_path) = &flags.lock {
+    deno_flags.push(format!("--lock={}", base_dir.join(lock_path).display()));
+  }
+  if let Some(import_map_path) = &flags.import_map_path {
+    let import_map_url = resolve_url_or_path(import_map_path, base_dir)?;
+    deno_flags.push(format!("--import-map={import_map_url}"));
+  }
+  deno_flags.push(
+    match flags.type_check_mode {
+      TypeCheckMode::All => "--check=all",
+      TypeCheckMode::None => "--no-check",
+      TypeCheckMode::Local => "--check",
+    }
+    .to_string(),
+  );
+  Ok(deno_flags)
+}
+
+/// Applies the deno flags of a manifest entry to the flags the manifest is
+/// installed with. Each kind of flag the entry sets replaces the one of the
+/// batch, so an entry with `--allow-net` doesn't get `--allow-run` of the
+/// batch as well.
+fn apply_entry_deno_flags(
+  flags: &Flags,
+  deno_flags: &[String],
+) -> Result<Flags, AnyError> {
+  if let Some(arg) = deno_flags.iter().find(|arg| !arg.starts_with('-')) {
+    return Err(generic_error(format!(
+      "Invalid deno flag in install manifest: {arg}"
+    )));
+  }
+  let mut args = vec!["deno", "install", "-g"];
+  args.extend(deno_flags.iter().map(String::as_str));
+  args.push("module");
+  let entry_flags = flags_from_vec(args.into_iter().map(Into::into).collect())
+    .map_err(|err| {
+      generic_error(format!(
+        "Invalid deno flags in install manifest: {}\n{err}",
+        deno_flags.join(" ")
+      ))
+    })?;
+
+  let mut flags = flags.clone();
+  if entry_flags.permissions.has_permission() {
+    flags.permissions = PermissionFlags {
+      no_prompt: flags.permissions.no_prompt,
+      ..entry_flags.permissions
+    };
+  }
+  if !matches!(entry_flags.config_flag, ConfigFlag::Discover) {
+    flags.config_flag = entry_flags.config_flag;
+  }
+  if entry_flags.no_lock || entry_flags.lock.is_some() {
+    flags.no_lock = entry_flags.no_lock;
+    flags.lock = entry_flags.lock;
+  }
+  if entry_flags.import_map_path.is_some() {
+    flags.import_map_path = entry_flags.import_map_path;
+  }
+  if deno_flags
+    .iter()
+    .any(|arg| arg == "--no-check" || arg.starts_with("--check"))
+  {
+    flags.type_check_mode = entry_flags.type_check_mode;
+  }
+  Ok(flags)
+}
+
+/// Flags of `deno run` that take a single value, which are forwarded at most
+/// once.
+const SINGLE_VALUED_FLAGS: [&str; 2] = ["--location", "--cert"];
+
+/// Drops repeated occurrences of single-valued flags, in both their
+/// `--flag value` and `--flag=value` forms, keeping the first one.
+fn dedup_single_valued_flags(args: Vec<String>) -> Vec<String> {
+  let mut seen = HashSet::new();
+  let mut deduped = Vec::with_capacity(args.len());
+  let mut args = args.into_iter();
+  while let Some(arg) = args.next() {
+    let name = arg.split('=').next().unwrap_or(&arg).to_string();
+    if !SINGLE_VALUED_FLAGS.contains(&name.as_str()) {
+      deduped.push(arg);
+      continue;
+    }
+    let value = if arg.contains('=') { None } else { args.next() };
+    if seen.insert(name) {
+      deduped.push(arg);
+      deduped.extend(value);
+    }
+  }
+  deduped
+}
+
+/// Flags configuring the runtime itself rather than the program being run.
+/// deno only accepts them after the subcommand, so they always directly
+/// follow `run` in the generated invocation, v8 flags first.
+fn runtime_args(flags: &Flags) -> Vec<String> {
+  let mut args = Vec::new();
+  if !flags.v8_flags.is_empty() {
+    args.push(format!("--v8-flags={}", flags.v8_flags.join(",")));
+  }
+  if let Some(seed) = flags.seed {
+    args.push("--seed".to_string());
+    args.push(seed.to_string());
+  }
+  args
+}
+
+/// Merges `overlay` into `base`, recursing into objects present in both.
+/// Any other value in `overlay` replaces the one in `base`.
+fn merge_json_objects(
+  base: &mut serde_json::Value,
+  overlay: serde_json::Value,
+) {
+  match (base, overlay) {
+    (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+      for (key, value) in overlay {
+        match base.get_mut(&key) {
+          Some(base_value) => merge_json_objects(base_value, value),
+          None => {
+            base.insert(key, value);
+          }
+        }
+      }
+    }
+    (base, overlay) => *base = overlay,
+  }
+}
+
+/// Returns the source of a local module, or of a remote module in the
+/// DENO_DIR cache, if it can be read.
+fn read_module_source(module_url: &Url) -> Option<String> {
+  match module_url.scheme() {
+    "file" => fs::read_to_string(module_url.to_file_path().ok()?).ok(),
+    "http" | "https" => {
+      let deno_dir = DenoDir::new(None).ok()?;
+      let http_cache =
+        GlobalHttpCache::new(deno_dir.deps_folder_path(), RealDenoCacheEnv);
+      let cache_key = http_cache.cache_item_key(module_url).ok()?;
+      let headers = http_cache.read_headers(&cache_key).ok()??;
+      if headers.contains_key("location") {
+        return None;
+      }
+      let bytes = http_cache
+        .read_file_bytes(
+          &cache_key,
+          None,
+          deno_cache_dir::GlobalToLocalCopy::Disallow,
+        )
+        .ok()??;
+      String::from_utf8(bytes).ok()
+    }
+    _ => None,
+  }
+}
+
+/// Whether the module may tell being run apart from being imported, through
+/// `import.meta.main` or `Deno.mainModule`. Modules whose source can't be
+/// read are assumed to.
+fn may_check_main_module(module_url: &Url) -> bool {
+  read_module_source(module_url).map_or(true, |source| {
+    source.contains("import.meta.main") || source.contains("Deno.mainModule")
+  })
+}
+
+/// Whether the remote module is in the DENO_DIR cache. Without a custom
+/// root, the DENO_DIR is read from `$DENO_DIR` like any other subcommand.
+fn is_module_cached(
+  maybe_custom_root: Option<PathBuf>,
+  module_url: &Url,
+) -> Result<bool, AnyError> {
+  let deno_dir = DenoDir::new(maybe_custom_root)?;
+  let http_cache =
+    GlobalHttpCache::new(deno_dir.deps_folder_path(), RealDenoCacheEnv);
+  let cache_key = http_cache.cache_item_key(module_url)?;
+  Ok(http_cache.read_headers(&cache_key)?.is_some())
+}
+
+fn is_in_path(dir: &Path) -> bool {
+  env::var_os("PATH")
+    .map(|paths| count_path_entries(dir, &paths) > 0)
+    .unwrap_or(false)
+}
+
+/// Counts how many times `dir` occurs in the `PATH`-like value `paths`.
+fn count_path_entries(dir: &Path, paths: &std::ffi::OsStr) -> usize {
+  env::split_paths(paths).filter(|p| *dir == *p).count()
+}
+
+/// A note for when `dir` is listed in `paths` more than once, which is
+/// harmless but usually a leftover from editing shell profiles repeatedly.
+fn duplicate_path_entries_note(
+  dir: &Path,
+  paths: &std::ffi::OsStr,
+) -> Option<String> {
+  let count = count_path_entries(dir, paths);
+  if count > 1 {
+    Some(format!(
+      "{} appears {} times in PATH. Consider removing the duplicate entries from your shell profile.",
+      dir.display(),
+      count
+    ))
+  } else {
+    None
+  }
+}
+
+#[cfg(test)]
 mod tests {
   use super::*;
 
-  use crate::args::ConfigFlag;
-  use crate::args::PermissionFlags;
-  use crate::args::UninstallFlagsGlobal;
-  use crate::args::UnstableConfig;
-  use crate::util::fs::canonicalize_path;
-  use std::process::Command;
-  use test_util::testdata_path;
-  use test_util::TempDir;
+  use crate::args::ConfigFlag;
+  use crate::args::PermissionFlags;
+  use crate::args::UninstallFlagsGlobal;
+  use crate::args::UnstableConfig;
+  use crate::util::fs::canonicalize_path;
+  use std::process::Command;
+  use test_util::testdata_path;
+  use test_util::TempDir;
+
+  /// Serializes the tests that modify environment variables.
+  static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+  #[tokio::test]
+  async fn install_infer_name_from_url() {
+    let http_client = HttpClientProvider::new(None, None);
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/abc/server.ts").unwrap()
+      )
+      .await,
+      Some("server".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/abc/main.ts").unwrap()
+      )
+      .await,
+      Some("abc".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/abc/mod.ts").unwrap()
+      )
+      .await,
+      Some("abc".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/ab%20c/mod.ts").unwrap()
+      )
+      .await,
+      Some("ab c".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/abc/index.ts").unwrap()
+      )
+      .await,
+      Some("abc".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/abc/cli.ts").unwrap()
+      )
+      .await,
+      Some("abc".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/main.ts").unwrap()
+      )
+      .await,
+      Some("main".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com").unwrap()
+      )
+      .await,
+      None
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("file:///abc/server.ts").unwrap()
+      )
+      .await,
+      Some("server".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("file://localhost/abc/server.ts").unwrap()
+      )
+      .await,
+      Some("server".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("file:///abc/main.ts").unwrap()
+      )
+      .await,
+      Some("abc".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("file:///ab%20c/main.ts").unwrap()
+      )
+      .await,
+      Some("ab c".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("file:///main.ts").unwrap()
+      )
+      .await,
+      Some("main".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(&http_client, &Url::parse("file:///").unwrap()).await,
+      None
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/abc@0.1.0").unwrap()
+      )
+      .await,
+      Some("abc".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/abc@0.1.0/main.ts").unwrap()
+      )
+      .await,
+      Some("abc".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/abc@def@ghi").unwrap()
+      )
+      .await,
+      Some("abc@def@ghi".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/abc@def@1.2.3").unwrap()
+      )
+      .await,
+      Some("abc@def".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/a@1/b@2/main.ts").unwrap()
+      )
+      .await,
+      Some("b".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/tool@v2.1.0-beta.1/mod.ts").unwrap()
+      )
+      .await,
+      Some("tool".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/my@tool/cli.ts").unwrap()
+      )
+      .await,
+      Some("my@tool".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/@abc.ts").unwrap()
+      )
+      .await,
+      Some("@abc".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("https://example.com/@abc/mod.ts").unwrap()
+      )
+      .await,
+      Some("@abc".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("file:///@abc.ts").unwrap()
+      )
+      .await,
+      Some("@abc".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("file:///@abc/cli.ts").unwrap()
+      )
+      .await,
+      Some("@abc".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("npm:cowsay@1.2/cowthink").unwrap()
+      )
+      .await,
+      Some("cowthink".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("npm:cowsay@1.2/cowthink/test").unwrap()
+      )
+      .await,
+      Some("cowsay".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(&http_client, &Url::parse("npm:cowsay@1.2").unwrap())
+        .await,
+      Some("cowsay".to_string())
+    );
+    assert_eq!(
+      infer_name_from_url(
+        &http_client,
+        &Url::parse("npm:@types/node@1.2").unwrap()
+      )
+      .await,
+      None
+    );
+  }
+
+  #[tokio::test]
+  async fn install_infer_name_with_generic_names() {
+    let http_client = HttpClientProvider::new(None, None);
+    let infer = |url: &str, stems: &[&str], parents: &[&str]| {
+      let url = Url::parse(url).unwrap();
+      let stems = stems.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+      let parents = parents.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+      let http_client = &http_client;
+      async move {
+        infer_name_from_url_with_generic_names(
+          http_client,
+          &url,
+          &stems,
+          &parents,
+          DEFAULT_NETWORK_TIMEOUT,
+        )
+        .await
+      }
+    };
+    assert_eq!(
+      infer("https://x/tool/app.ts", &[], &[]).await,
+      Some("app".to_string())
+    );
+    assert_eq!(
+      infer("https://x/tool/app.ts", &["app"], &[]).await,
+      Some("tool".to_string())
+    );
+    assert_eq!(
+      infer("https://x/tool/src/main.ts", &[], &[]).await,
+      Some("src".to_string())
+    );
+    assert_eq!(
+      infer("https://x/tool/src/main.ts", &[], &["src"]).await,
+      Some("tool".to_string())
+    );
+    assert_eq!(
+      infer("https://x/tool/source/main.ts", &[], &["source"]).await,
+      Some("tool".to_string())
+    );
+    assert_eq!(
+      infer("https://x/src/main.ts", &[], &["src"]).await,
+      Some("src".to_string())
+    );
+  }
+
+  #[tokio::test]
+  async fn install_infer_name_from_local_manifest() {
+    let http_client = HttpClientProvider::new(None, None);
+    let temp_dir = TempDir::new();
+    let tool_dir = temp_dir.path().join("tool");
+    tool_dir.create_dir_all();
+    tool_dir.join("main.ts").write("console.log('hi');");
+    tool_dir
+      .join("deno.json")
+      .write(r#"{ "name": "cool-tool" }"#);
+
+    let module_url = Url::from_file_path(tool_dir.join("main.ts")).unwrap();
+    assert_eq!(
+      infer_name_from_url(&http_client, &module_url).await,
+      Some("cool-tool".to_string())
+    );
+
+    // the scope of a scoped package name is dropped
+    tool_dir.join("deno.json").remove_file();
+    tool_dir
+      .join("package.json")
+      .write(r#"{ "name": "@scope/cool-tool" }"#);
+    assert_eq!(
+      infer_name_from_url(&http_client, &module_url).await,
+      Some("cool-tool".to_string())
+    );
+
+    // without a declared name the file name is used
+    tool_dir.join("package.json").write("{}");
+    assert_eq!(
+      infer_name_from_url(&http_client, &module_url).await,
+      Some("tool".to_string())
+    );
+  }
+
+  #[tokio::test]
+  async fn install_unstable() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    std::fs::create_dir(&bin_dir).unwrap();
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        unstable_config: UnstableConfig {
+          legacy_flag_enabled: true,
+          ..Default::default()
+        },
+        ..Flags::default()
+      },
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec![],
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let mut file_path = bin_dir.join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+
+    assert!(file_path.exists());
+
+    let content = fs::read_to_string(file_path).unwrap();
+    if cfg!(windows) {
+      assert!(content.contains(
+        r#""run" "--unstable" "--no-config" "http://localhost:4545/echo_server.ts""#
+      ));
+    } else {
+      assert!(content.contains(
+        r#"run --unstable --no-config 'http://localhost:4545/echo_server.ts'"#
+      ));
+    }
+  }
+
+  #[tokio::test]
+  async fn install_inferred_name() {
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec![],
+        name: None,
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(shim_data.name, "echo_server");
+    assert_eq!(
+      shim_data.args,
+      vec!["run", "--no-config", "http://localhost:4545/echo_server.ts",]
+    );
+  }
+
+  #[tokio::test]
+  async fn install_unstable_legacy() {
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        unstable_config: UnstableConfig {
+          legacy_flag_enabled: true,
+          ..Default::default()
+        },
+        ..Default::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec![],
+        name: None,
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(shim_data.name, "echo_server");
+    assert_eq!(
+      shim_data.args,
+      vec![
+        "run",
+        "--unstable",
+        "--no-config",
+        "http://localhost:4545/echo_server.ts",
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn install_unstable_features() {
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        unstable_config: UnstableConfig {
+          features: vec!["kv".to_string(), "cron".to_string()],
+          ..Default::default()
+        },
+        ..Default::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec![],
+        name: None,
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(shim_data.name, "echo_server");
+    assert_eq!(
+      shim_data.args,
+      vec![
+        "run",
+        "--unstable-kv",
+        "--unstable-cron",
+        "--no-config",
+        "http://localhost:4545/echo_server.ts",
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn install_inferred_name_from_parent() {
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/subdir/main.ts".to_string(),
+        args: vec![],
+        name: None,
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(shim_data.name, "subdir");
+    assert_eq!(
+      shim_data.args,
+      vec!["run", "--no-config", "http://localhost:4545/subdir/main.ts",]
+    );
+  }
+
+  #[tokio::test]
+  async fn install_inferred_name_after_redirect_for_no_path_url() {
+    let _http_server_guard = test_util::http_server();
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4550/?redirect_to=/subdir/redirects/a.ts"
+          .to_string(),
+        args: vec![],
+        name: None,
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(shim_data.name, "a");
+    assert_eq!(
+      shim_data.args,
+      vec![
+        "run",
+        "--no-config",
+        "http://localhost:4550/?redirect_to=/subdir/redirects/a.ts",
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn install_custom_dir_option() {
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec![],
+        name: Some("echo_test".to_string()),
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(shim_data.name, "echo_test");
+    assert_eq!(
+      shim_data.args,
+      vec!["run", "--no-config", "http://localhost:4545/echo_server.ts",]
+    );
+  }
+
+  #[tokio::test]
+  async fn install_with_flags() {
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        permissions: PermissionFlags {
+          allow_net: Some(vec![]),
+          allow_read: Some(vec![]),
+          ..Default::default()
+        },
+        type_check_mode: TypeCheckMode::None,
+        log_level: Some(Level::Error),
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec!["--foobar".to_string()],
+        name: Some("echo_test".to_string()),
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(shim_data.name, "echo_test");
+    assert_eq!(
+      shim_data.args,
+      vec![
+        "run",
+        "--allow-read",
+        "--allow-net",
+        "--quiet",
+        "--no-config",
+        "http://localhost:4545/echo_server.ts",
+        "--foobar",
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn install_prompt() {
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        permissions: PermissionFlags {
+          no_prompt: true,
+          ..Default::default()
+        },
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec![],
+        name: Some("echo_test".to_string()),
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+      shim_data.args,
+      vec![
+        "run",
+        "--no-prompt",
+        "--no-config",
+        "http://localhost:4545/echo_server.ts",
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn install_allow_all() {
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        permissions: PermissionFlags {
+          allow_all: true,
+          ..Default::default()
+        },
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec![],
+        name: Some("echo_test".to_string()),
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+      shim_data.args,
+      vec![
+        "run",
+        "--allow-all",
+        "--no-config",
+        "http://localhost:4545/echo_server.ts",
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn install_npm_lockfile_default() {
+    let temp_dir = canonicalize_path(&env::temp_dir()).unwrap();
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        permissions: PermissionFlags {
+          allow_all: true,
+          ..Default::default()
+        },
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "npm:cowsay".to_string(),
+        args: vec![],
+        name: None,
+        root: Some(temp_dir.to_string_lossy().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let lock_path = temp_dir.join("bin").join(".cowsay.lock.json");
+    assert_eq!(
+      shim_data.args,
+      vec![
+        "run",
+        "--allow-all",
+        "--no-config",
+        "--lock",
+        &lock_path.to_string_lossy(),
+        "npm:cowsay"
+      ]
+    );
+    assert_eq!(shim_data.extra_files, vec![(lock_path, "{}".to_string())]);
+  }
+
+  #[tokio::test]
+  async fn install_npm_no_lock() {
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        permissions: PermissionFlags {
+          allow_all: true,
+          ..Default::default()
+        },
+        no_lock: true,
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "npm:cowsay".to_string(),
+        args: vec![],
+        name: None,
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+      shim_data.args,
+      vec![
+        "run",
+        "--allow-all",
+        "--no-config",
+        "--no-lock",
+        "npm:cowsay"
+      ]
+    );
+    assert_eq!(shim_data.extra_files, vec![]);
+  }
+
+  #[tokio::test]
+  async fn install_local_module() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    std::fs::create_dir(&bin_dir).unwrap();
+    let local_module = env::current_dir().unwrap().join("echo_server.ts");
+    let local_module_url = Url::from_file_path(&local_module).unwrap();
+    let local_module_str = local_module.to_string_lossy();
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: local_module_str.to_string(),
+        args: vec![],
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let mut file_path = bin_dir.join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+
+    assert!(file_path.exists());
+    let content = fs::read_to_string(file_path).unwrap();
+    assert!(content.contains(&local_module_url.to_string()));
+  }
+
+  #[tokio::test]
+  async fn install_force() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    std::fs::create_dir(&bin_dir).unwrap();
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec![],
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let mut file_path = bin_dir.join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+    assert!(file_path.exists());
+
+    // No force. Install failed.
+    let no_force_result = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(), // using a different URL
+        args: vec![],
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await;
+    assert!(no_force_result.is_err());
+    assert!(no_force_result
+      .unwrap_err()
+      .to_string()
+      .contains("Existing installation found"));
+    // Assert not modified
+    let file_content = fs::read_to_string(&file_path).unwrap();
+    assert!(file_content.contains("echo_server.ts"));
+
+    // Force. Install success.
+    let force_result = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(), // using a different URL
+        args: vec![],
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        force: true,
+        ..Default::default()
+      },
+    )
+    .await;
+    assert!(force_result.is_ok());
+    // Assert modified
+    let file_content_2 = fs::read_to_string(&file_path).unwrap();
+    assert!(file_content_2.contains("cat.ts"));
+  }
+
+  #[tokio::test]
+  async fn install_with_config() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    let config_file_path = temp_dir.path().join("test_tsconfig.json");
+    let config = "{}";
+    let mut config_file = File::create(&config_file_path).unwrap();
+    let result = config_file.write_all(config.as_bytes());
+    assert!(result.is_ok());
+
+    let result = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        config_flag: ConfigFlag::Path(config_file_path.to_string()),
+        ..Flags::default()
+      },
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(),
+        args: vec![],
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        force: true,
+        ..Default::default()
+      },
+    )
+    .await;
+    assert!(result.is_ok());
+
+    let config_file_name = ".echo_test.deno.json";
+
+    let file_path = bin_dir.join(config_file_name);
+    assert!(file_path.exists());
+    let content = fs::read_to_string(file_path).unwrap();
+    assert!(content == "{}");
+  }
+
+  // TODO: enable on Windows after fixing batch escaping
+  #[cfg(not(windows))]
+  #[tokio::test]
+  async fn install_shell_escaping() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    std::fs::create_dir(&bin_dir).unwrap();
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec!["\"".to_string()],
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let mut file_path = bin_dir.join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+
+    assert!(file_path.exists());
+    let content = fs::read_to_string(file_path).unwrap();
+    if cfg!(windows) {
+      // TODO: see comment above this test
+    } else {
+      assert!(content.contains(
+        r#"run --no-config 'http://localhost:4545/echo_server.ts' '"'"#
+      ));
+    }
+  }
+
+  #[tokio::test]
+  async fn install_unicode() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    std::fs::create_dir(&bin_dir).unwrap();
+    let unicode_dir = temp_dir.path().join("Magnús");
+    std::fs::create_dir(&unicode_dir).unwrap();
+    let local_module = unicode_dir.join("echo_server.ts");
+    let local_module_str = local_module.to_string_lossy();
+    std::fs::write(&local_module, "// Some JavaScript I guess").unwrap();
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: local_module_str.to_string(),
+        args: vec![],
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        force: false,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let mut file_path = bin_dir.join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+
+    // We need to actually run it to make sure the URL is interpreted correctly
+    let status = Command::new(file_path)
+      .env_clear()
+      // use the deno binary in the target directory
+      .env("PATH", test_util::target_dir())
+      .spawn()
+      .unwrap()
+      .wait()
+      .unwrap();
+    assert!(status.success());
+  }
+
+  #[tokio::test]
+  async fn install_with_import_map() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    let import_map_path = temp_dir.path().join("import_map.json");
+    let import_map_url = Url::from_file_path(&import_map_path).unwrap();
+    let import_map = "{ \"imports\": {} }";
+    let mut import_map_file = File::create(&import_map_path).unwrap();
+    let result = import_map_file.write_all(import_map.as_bytes());
+    assert!(result.is_ok());
+
+    let result = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        import_map_path: Some(import_map_path.to_string()),
+        ..Flags::default()
+      },
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(),
+        args: vec![],
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        force: true,
+        ..Default::default()
+      },
+    )
+    .await;
+    assert!(result.is_ok());
+
+    let mut file_path = bin_dir.join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+    assert!(file_path.exists());
+
+    let mut expected_string = format!(
+      "--import-map '{import_map_url}' --no-config 'http://localhost:4545/cat.ts'"
+    );
+    if cfg!(windows) {
+      expected_string = format!(
+        "\"--import-map\" \"{import_map_url}\" \"--no-config\" \"http://localhost:4545/cat.ts\""
+      );
+    }
+
+    let content = fs::read_to_string(file_path).unwrap();
+    assert!(content.contains(&expected_string));
+  }
+
+  // Regression test for https://github.com/denoland/deno/issues/10556.
+  #[tokio::test]
+  async fn install_file_url() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    let module_path = fs::canonicalize(testdata_path().join("cat.ts")).unwrap();
+    let file_module_string =
+      Url::from_file_path(module_path).unwrap().to_string();
+    assert!(file_module_string.starts_with("file:///"));
+
+    let result = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: file_module_string.to_string(),
+        args: vec![],
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        force: true,
+        ..Default::default()
+      },
+    )
+    .await;
+    assert!(result.is_ok());
+
+    let mut file_path = bin_dir.join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+    assert!(file_path.exists());
+
+    let mut expected_string =
+      format!("run --no-config '{}'", &file_module_string);
+    if cfg!(windows) {
+      expected_string =
+        format!("\"run\" \"--no-config\" \"{}\"", &file_module_string);
+    }
+
+    let content = fs::read_to_string(file_path).unwrap();
+    assert!(content.contains(&expected_string));
+  }
+
+  #[tokio::test]
+  async fn uninstall_basic() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    std::fs::create_dir(&bin_dir).unwrap();
+
+    let mut file_path = bin_dir.join("echo_test");
+    File::create(&file_path).unwrap();
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+      File::create(&file_path).unwrap();
+    }
+
+    // create extra files
+    {
+      let file_path = file_path.with_extension("deno.json");
+      File::create(file_path).unwrap();
+    }
+    {
+      // legacy tsconfig.json, make sure it's cleaned up for now
+      let file_path = file_path.with_extension("tsconfig.json");
+      File::create(file_path).unwrap();
+    }
+    {
+      let file_path = file_path.with_extension("lock.json");
+      File::create(file_path).unwrap();
+    }
+
+    uninstall(
+      Default::default(),
+      UninstallFlags {
+        kind: UninstallKind::Global(UninstallFlagsGlobal {
+          name: Some("echo_test".to_string()),
+          root: Some(temp_dir.path().to_string()),
+          ..Default::default()
+        }),
+        global: false,
+      },
+    )
+    .await
+    .unwrap();
+
+    assert!(!file_path.exists());
+    assert!(!file_path.with_extension("tsconfig.json").exists());
+    assert!(!file_path.with_extension("deno.json").exists());
+    assert!(!file_path.with_extension("lock.json").exists());
+
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+      assert!(!file_path.exists());
+    }
+  }
+
+  #[tokio::test]
+  async fn prune_removes_broken_local_installs() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    std::fs::create_dir(&bin_dir).unwrap();
+    let kept_module = temp_dir.path().join("kept.ts");
+    kept_module.write("console.log('kept');");
+    let broken_module = temp_dir.path().join("broken.ts");
+    broken_module.write("console.log('broken');");
+
+    for (module, name) in [(&kept_module, "kept"), (&broken_module, "broken")] {
+      create_install_shim(
+        &HttpClientProvider::new(None, None),
+        &Flags::default(),
+        InstallFlagsGlobal {
+          module_url: module.to_string(),
+          args: vec![],
+          name: Some(name.to_string()),
+          root: Some(temp_dir.path().to_string()),
+          force: false,
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap();
+    }
+    broken_module.remove_file();
+
+    let mut kept_path = bin_dir.join("kept");
+    let mut broken_path = bin_dir.join("broken");
+    if cfg!(windows) {
+      kept_path = kept_path.with_extension("cmd");
+      broken_path = broken_path.with_extension("cmd");
+    }
+
+    // dry run doesn't remove anything
+    let pruned = prune(
+      &HttpClientProvider::new(None, None),
+      bin_dir.as_path(),
+      PruneOptions {
+        dry_run: true,
+        check_remote: false,
+        timeout: DEFAULT_NETWORK_TIMEOUT,
+      },
+    )
+    .await
+    .unwrap();
+    assert_eq!(pruned, vec!["broken".to_string()]);
+    assert!(broken_path.exists());
+
+    let pruned = prune(
+      &HttpClientProvider::new(None, None),
+      bin_dir.as_path(),
+      PruneOptions {
+        dry_run: false,
+        check_remote: false,
+        timeout: DEFAULT_NETWORK_TIMEOUT,
+      },
+    )
+    .await
+    .unwrap();
+    assert_eq!(pruned, vec!["broken".to_string()]);
+    assert!(kept_path.exists());
+    assert!(!broken_path.exists());
+    assert!(!get_metadata_path(broken_path.as_path()).exists());
+    assert!(get_metadata_path(kept_path.as_path()).exists());
+  }
+
+  #[tokio::test]
+  async fn install_defaults_file() {
+    let temp_dir = TempDir::new();
+    let defaults_path = temp_dir.path().join("install.json");
+    defaults_path
+      .write(r#"{ "root": "custom_root", "shell": "sh", "mode": "700" }"#);
+
+    let install_flags_global = apply_install_defaults(
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec![],
+        name: Some("echo_test".to_string()),
+        root: None,
+        force: false,
+        ..Default::default()
+      },
+      defaults_path.as_path(),
+    )
+    .unwrap();
+    assert_eq!(install_flags_global.output_format, InstallOutputFormat::Sh);
+    assert_eq!(install_flags_global.wrapper_dir_permissions, Some(0o700));
+
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &install_flags_global,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+      shim_data.installation_dir,
+      canonicalize_path_maybe_not_exists(
+        &temp_dir
+          .path()
+          .join("custom_root")
+          .join("bin")
+          .to_path_buf()
+      )
+      .unwrap()
+    );
+
+    // the command line takes precedence
+    let cli_root = temp_dir.path().join("cli_root");
+    let install_flags_global = apply_install_defaults(
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec![],
+        name: Some("echo_test".to_string()),
+        root: Some(cli_root.to_string()),
+        output_format: InstallOutputFormat::Cmd,
+        wrapper_dir_permissions: Some(0o755),
+        ..Default::default()
+      },
+      defaults_path.as_path(),
+    )
+    .unwrap();
+    assert_eq!(install_flags_global.root, Some(cli_root.to_string()));
+    assert_eq!(install_flags_global.output_format, InstallOutputFormat::Cmd);
+    assert_eq!(install_flags_global.wrapper_dir_permissions, Some(0o755));
+
+    // invalid values are reported against the defaults file
+    let invalid_path = temp_dir.path().join("invalid.json");
+    invalid_path.write(r#"{ "shell": "fish" }"#);
+    let err = apply_install_defaults(
+      InstallFlagsGlobal::default(),
+      invalid_path.as_path(),
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("invalid shell \"fish\""), "{err}");
+
+    // a missing defaults file leaves the flags untouched
+    let install_flags_global = apply_install_defaults(
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec![],
+        name: None,
+        root: None,
+        force: false,
+        ..Default::default()
+      },
+      temp_dir.path().join("missing.json").as_path(),
+    )
+    .unwrap();
+    assert_eq!(install_flags_global.root, None);
+    assert_eq!(install_flags_global.output_format, InstallOutputFormat::All);
+    assert_eq!(install_flags_global.wrapper_dir_permissions, None);
+  }
+
+  #[test]
+  fn install_module_url_errors() {
+    let temp_dir = TempDir::new();
+    let cwd = temp_dir.path().to_path_buf();
+
+    let err = resolve_install_module_url("http://[::1/mod.ts", &cwd)
+      .unwrap_err()
+      .to_string();
+    assert!(
+      err.starts_with("Remote URL could not be parsed: http://[::1/mod.ts"),
+      "{err}"
+    );
+
+    let err = resolve_install_module_url("./does_not_exist.ts", &cwd)
+      .unwrap_err()
+      .to_string();
+    assert!(
+      err.starts_with(
+        "Local path does not exist or is invalid: ./does_not_exist.ts"
+      ),
+      "{err}"
+    );
+
+    temp_dir.write("exists.ts", "");
+    let module_url = resolve_install_module_url("./exists.ts", &cwd).unwrap();
+    assert_eq!(module_url, temp_dir.path().join("exists.ts").uri_file());
+  }
+
+  #[tokio::test]
+  async fn install_manifest_concurrent() {
+    let temp_dir = TempDir::new();
+    let manifest_path = temp_dir.path().join("tools.json");
+    manifest_path.write(
+      r#"{
+        "tools": [
+          { "module": "http://localhost:4545/echo_server.ts", "name": "a" },
+          { "module": "http://localhost:4545/cat.ts" },
+          { "module": "./local.ts", "name": "c", "args": ["--foo"] },
+          { "module": "http://localhost:4545/subdir/main.ts" }
+        ]
+      }"#,
+    );
+    temp_dir.write("local.ts", "console.log('local');");
+
+    let entries = read_install_manifest(
+      manifest_path.as_path(),
+      &InstallFlagsGlobal {
+        module_url: manifest_path.to_string(),
+        root: Some(temp_dir.path().to_string()),
+        manifest: true,
+        ..Default::default()
+      },
+    )
+    .unwrap();
+    let results = create_install_shims(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      entries,
+      NonZeroUsize::new(3),
+    )
+    .await;
+    let names = results
+      .into_iter()
+      .map(|result| result.unwrap().name)
+      .collect::<Vec<_>>();
+    assert_eq!(names, vec!["a", "cat", "c", "subdir"]);
+
+    let bin_dir = temp_dir.path().join("bin");
+    for name in &names {
+      let mut file_path = bin_dir.join(name);
+      if cfg!(windows) {
+        file_path = file_path.with_extension("cmd");
+      }
+      assert!(file_path.exists());
+    }
+    let mut local_path = bin_dir.join("c");
+    if cfg!(windows) {
+      local_path = local_path.with_extension("cmd");
+    }
+    let content = local_path.read_to_string();
+    assert!(content
+      .contains(&temp_dir.path().join("local.ts").uri_file().to_string()));
+    assert!(content.contains("--foo"));
+    // the entries were written under the lock of the directory
+    assert!(temp_dir.path().join(INSTALL_LOCK_FILE_NAME).exists());
+  }
+
+  #[test]
+  fn install_manifest_inherits_batch_flags() {
+    let temp_dir = TempDir::new();
+    let manifest_path = temp_dir.path().join("tools.json");
+    manifest_path.write(
+      r#"{
+        "tools": [
+          { "module": "http://localhost:4545/echo_server.ts", "name": "a" },
+          { "module": "http://localhost:4545/cat.ts", "preRun": "echo cat" }
+        ]
+      }"#,
+    );
+    let entries = read_install_manifest(
+      manifest_path.as_path(),
+      &InstallFlagsGlobal {
+        module_url: manifest_path.to_string(),
+        manifest: true,
+        owner_only: true,
+        dry_run: true,
+        wrapper_comment: Some("managed".to_string()),
+        pre_run: Some("echo batch".to_string()),
+        ..Default::default()
+      },
+    )
+    .unwrap();
+    for entry in &entries {
+      assert!(entry.owner_only);
+      assert!(entry.dry_run);
+      assert!(!entry.manifest);
+      assert_eq!(entry.wrapper_comment.as_deref(), Some("managed"));
+    }
+    assert_eq!(entries[0].pre_run.as_deref(), Some("echo batch"));
+    assert_eq!(entries[1].pre_run.as_deref(), Some("echo cat"));
+  }
+
+  #[test]
+  fn install_manifest_duplicate_names() {
+    let temp_dir = TempDir::new();
+    let manifest_path = temp_dir.path().join("tools.json");
+    manifest_path.write(
+      r#"{
+        "tools": [
+          { "module": "http://localhost:4545/echo_server.ts", "name": "a" },
+          { "module": "http://localhost:4545/cat.ts", "name": "a" }
+        ]
+      }"#,
+    );
+    let err = read_install_manifest(
+      manifest_path.as_path(),
+      &InstallFlagsGlobal {
+        module_url: manifest_path.to_string(),
+        manifest: true,
+        ..Default::default()
+      },
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "Duplicate name in install manifest: a");
+  }
+
+  #[tokio::test]
+  async fn install_aliases() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("git-helper".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        aliases: vec!["g".to_string(), "gh".to_string()],
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let file_paths = ["git-helper", "g", "gh"]
+      .iter()
+      .map(|name| {
+        let file_path = bin_dir.join(name);
+        if cfg!(windows) {
+          file_path.with_extension("cmd")
+        } else {
+          file_path
+        }
+      })
+      .collect::<Vec<_>>();
+    for file_path in &file_paths {
+      let content = file_path.read_to_string();
+      assert!(content.contains("http://localhost:4545/echo_server.ts"));
+    }
+
+    uninstall(
+      Default::default(),
+      UninstallFlags {
+        kind: UninstallKind::Global(UninstallFlagsGlobal {
+          name: Some("git-helper".to_string()),
+          root: Some(temp_dir.path().to_string()),
+          ..Default::default()
+        }),
+        global: true,
+      },
+    )
+    .await
+    .unwrap();
+
+    for file_path in &file_paths {
+      assert!(!file_path.exists());
+    }
+  }
+
+  #[tokio::test]
+  async fn install_invalid_alias() {
+    let result = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        aliases: vec!["not/valid".to_string()],
+        ..Default::default()
+      },
+    )
+    .await;
+    assert_eq!(
+      result.err().unwrap().to_string(),
+      "Invalid executable name: not/valid"
+    );
+  }
+
+  #[cfg(not(windows))]
+  #[test]
+  fn add_to_path_command_shells() {
+    assert_eq!(
+      add_to_path_command("/home/user/.deno/bin", Some("/usr/bin/fish")),
+      "fish_add_path /home/user/.deno/bin"
+    );
+    assert_eq!(
+      add_to_path_command("/home/user/.deno/bin", Some("/bin/bash")),
+      "export PATH=\"/home/user/.deno/bin:$PATH\""
+    );
+    assert_eq!(
+      add_to_path_command("/home/user/.deno/bin", None),
+      "export PATH=\"/home/user/.deno/bin:$PATH\""
+    );
+  }
+
+  #[tokio::test]
+  async fn is_installed_before_and_after_install() {
+    let temp_dir = TempDir::new();
+    let root = temp_dir.path().to_path_buf();
+    assert!(!is_installed("echo_test", Some(root.clone())).unwrap());
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert!(is_installed("echo_test", Some(root.clone())).unwrap());
+    assert!(!is_installed("other", Some(root)).unwrap());
+  }
+
+  #[tokio::test]
+  async fn install_min_deno_version() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        min_deno_version: Some("1.40.2".to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let mut file_path = bin_dir.join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+    let content = file_path.read_to_string();
+    assert!(content.contains("1040002"));
+    assert!(content.contains("echo_test requires deno 1.40.2 or newer"));
+    // the check runs before deno is invoked
+    let deno_index = if cfg!(windows) {
+      content.find("@deno").unwrap()
+    } else {
+      content.find("exec deno").unwrap()
+    };
+    assert!(content.find("1040002").unwrap() < deno_index);
+
+    let metadata: InstallMetadata = serde_json::from_str(
+      &fs::read_to_string(get_metadata_path(file_path.as_path())).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(metadata.min_deno_version, Some("1.40.2".to_string()));
+
+    let result = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        root: Some(temp_dir.path().to_string()),
+        min_deno_version: Some("latest".to_string()),
+        ..Default::default()
+      },
+    )
+    .await;
+    assert_eq!(
+      result.err().unwrap().to_string(),
+      "Invalid minimum deno version: latest"
+    );
+  }
+
+  #[tokio::test]
+  async fn install_replace_existing_of_same_url() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    let mut file_path = bin_dir.join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    // same url refreshes the args without --force
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec!["--foobar".to_string()],
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        replace_existing_of_same_url: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    assert!(file_path.read_to_string().contains("--foobar"));
+
+    // a different url still requires --force
+    let err = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        replace_existing_of_same_url: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Existing installation found for a different module (http://localhost:4545/echo_server.ts). Aborting (Use -f to overwrite)."
+    );
+    assert!(file_path.read_to_string().contains("echo_server.ts"));
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        force: true,
+        replace_existing_of_same_url: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    assert!(file_path.read_to_string().contains("cat.ts"));
+  }
+
+  #[tokio::test]
+  async fn install_pre_run() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        pre_run: Some("echo pre-run".to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let mut file_path = bin_dir.join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+    let content = file_path.read_to_string();
+    let (pre_run, deno) = if cfg!(windows) {
+      ("@echo pre-run\n", "@deno ")
+    } else {
+      ("echo pre-run\n", "exec deno ")
+    };
+    assert!(content.find(pre_run).unwrap() < content.find(deno).unwrap());
+
+    let result = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        root: Some(temp_dir.path().to_string()),
+        pre_run: Some("echo a\necho b".to_string()),
+        ..Default::default()
+      },
+    )
+    .await;
+    assert_eq!(
+      result.err().unwrap().to_string(),
+      "The --pre-run command must be a single line"
+    );
+  }
+
+  #[tokio::test]
+  async fn install_returns_created_files() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    let config_file_path = temp_dir.path().join("deno.json");
+    config_file_path.write("{}");
+
+    let installed = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        config_flag: ConfigFlag::Path(config_file_path.to_string()),
+        ..Flags::default()
+      },
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let installation_dir =
+      canonicalize_path_maybe_not_exists(bin_dir.as_path()).unwrap();
+    let mut file_path = installation_dir.join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+    assert_eq!(installed.name, "echo_test");
+    assert!(installed.created_files.contains(&file_path));
+    assert!(installed
+      .created_files
+      .contains(&installation_dir.join(".echo_test.deno.json")));
+    assert!(installed
+      .created_files
+      .contains(&get_metadata_path(&file_path)));
+    for created_file in &installed.created_files {
+      assert!(created_file.exists());
+    }
+  }
+
+  #[cfg(not(windows))]
+  #[tokio::test]
+  async fn install_owner_only() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+
+    for (name, owner_only, mode) in
+      [("owner_only", true, 0o700), ("default_mode", false, 0o755)]
+    {
+      create_install_shim(
+        &HttpClientProvider::new(None, None),
+        &Flags::default(),
+        InstallFlagsGlobal {
+          module_url: "http://localhost:4545/echo_server.ts".to_string(),
+          name: Some(name.to_string()),
+          root: Some(temp_dir.path().to_string()),
+          owner_only,
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap();
+
+      let permissions = fs::metadata(bin_dir.join(name)).unwrap().permissions();
+      assert_eq!(permissions.mode() & 0o777, mode);
+    }
+  }
+
+  #[tokio::test]
+  async fn install_self_test() {
+    // use the deno binary in the target directory
+    self_test(
+      &Arc::new(HttpClientProvider::new(None, None)),
+      test_util::target_dir().as_path(),
+      None,
+    )
+    .await
+    .unwrap();
+  }
+
+  #[tokio::test]
+  async fn cmd_line_length_warning_for_long_args() {
+    let temp_dir = TempDir::new();
+    let mut shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    assert_eq!(cmd_line_length_warning(&shim_data), None);
+
+    let mut args = (0..1000)
+      .map(|i| format!("--arg-{i}"))
+      .collect::<Vec<String>>();
+    args.push("http://localhost:4545/echo_server.ts".to_string());
+    shim_data.args = args;
+    let warning = cmd_line_length_warning(&shim_data).unwrap();
+    assert!(warning.contains("echo_test"));
+    assert!(warning.contains("8191"));
+
+    // relocatable paths are measured with %~dp0 expanded
+    shim_data.installation_dir =
+      PathBuf::from("/home/someone/.local/share/deno/installations/bin");
+    shim_data.relocatable = true;
+    shim_data.args = (0..300)
+      .map(|i| {
+        shim_data
+          .installation_dir
+          .join(format!("{i}"))
+          .to_string_lossy()
+          .to_string()
+      })
+      .collect();
+    let invocation = cmd_invocation(&shim_data);
+    assert!(invocation.contains("\"%~dp0299\""));
+    assert!(invocation.len() + CMD_LINE_RUNTIME_ARGS_ROOM <= CMD_LINE_LIMIT);
+    assert!(cmd_line_length_warning(&shim_data).is_some());
+
+    // the recorded grants are spliced in before the entrypoint
+    shim_data.relocatable = false;
+    shim_data.args = vec!["run".to_string(), "main.js".to_string()];
+    shim_data.grant_state_file = Some(PathBuf::from("state"));
+    assert_eq!(
+      cmd_invocation(&shim_data),
+      "@deno \"run\" %grants% \"main.js\" %*"
+    );
+  }
+
+  #[tokio::test]
+  async fn install_with_import_map_overlay() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    let import_map_path = temp_dir.path().join("import_map.json");
+    import_map_path.write(
+      r#"{
+  "imports": {
+    "a": "https://example.com/a.ts",
+    "b": "https://example.com/b.ts"
+  },
+  "scopes": {
+    "https://example.com/": { "c": "https://example.com/c.ts" }
+  }
+}"#,
+    );
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        import_map_path: Some(import_map_path.to_string()),
+        ..Flags::default()
+      },
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        import_map_overlay: Some(
+          r#"{ "imports": { "b": "./b.ts", "d": "./d.ts" } }"#.to_string(),
+        ),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let mut file_path = bin_dir.join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+    let import_map_copy_path =
+      get_hidden_file_with_ext(file_path.as_path(), "import_map.json");
+    let content = fs::read_to_string(file_path).unwrap();
+    assert!(content.contains(import_map_copy_path.to_str().unwrap()));
+
+    let merged: serde_json::Value =
+      serde_json::from_str(&fs::read_to_string(import_map_copy_path).unwrap())
+        .unwrap();
+    assert_eq!(
+      merged,
+      serde_json::json!({
+        "imports": {
+          "a": "https://example.com/a.ts",
+          "b": "./b.ts",
+          "d": "./d.ts"
+        },
+        "scopes": {
+          "https://example.com/": { "c": "https://example.com/c.ts" }
+        }
+      })
+    );
+  }
+
+  #[tokio::test]
+  async fn list_installations_across_roots() {
+    let temp_dir = TempDir::new();
+    let first_root = temp_dir.path().join("first");
+    let second_root = temp_dir.path().join("second");
+
+    for (root, name) in
+      [(&first_root, "first_tool"), (&second_root, "second_tool")]
+    {
+      create_install_shim(
+        &HttpClientProvider::new(None, None),
+        &Flags::default(),
+        InstallFlagsGlobal {
+          module_url: "http://localhost:4545/echo_server.ts".to_string(),
+          name: Some(name.to_string()),
+          root: Some(root.to_string()),
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap();
+    }
+
+    let registry_path = temp_dir.path().join(KNOWN_ROOTS_FILE_NAME);
+    record_known_root(registry_path.as_path(), first_root.as_path()).unwrap();
+    record_known_root(registry_path.as_path(), second_root.as_path()).unwrap();
+    // recording a root twice doesn't duplicate it
+    record_known_root(registry_path.as_path(), second_root.as_path()).unwrap();
+    let roots = read_known_roots(registry_path.as_path());
+    assert_eq!(
+      roots,
+      vec![first_root.to_path_buf(), second_root.to_path_buf()]
+    );
+
+    let listed = list_installations(&roots).unwrap();
+    assert_eq!(
+      listed
+        .iter()
+        .map(|i| (i.root.clone(), i.metadata.name.as_str()))
+        .collect::<Vec<_>>(),
+      vec![
+        (first_root.to_path_buf(), "first_tool"),
+        (second_root.to_path_buf(), "second_tool"),
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn install_v8_flags_and_seed_position() {
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        v8_flags: vec![
+          "--expose-gc".to_string(),
+          "--max-old-space-size=64".to_string(),
+        ],
+        seed: Some(42),
+        permissions: PermissionFlags {
+          allow_net: Some(vec![]),
+          ..Default::default()
+        },
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec!["--foo".to_string()],
+        name: None,
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+      shim_data.args,
+      vec![
+        "run",
+        "--v8-flags=--expose-gc,--max-old-space-size=64",
+        "--seed",
+        "42",
+        "--allow-net",
+        "--no-config",
+        "http://localhost:4545/echo_server.ts",
+        "--foo",
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn install_verify_permissions() {
+    let temp_dir = TempDir::new();
+    let policy_path = temp_dir.path().join("policy.json");
+    policy_path.write(r#"{ "forbid": ["allow-run", "allow-ffi"] }"#);
+
+    let result = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        permissions: PermissionFlags {
+          allow_net: Some(vec![]),
+          allow_run: Some(vec!["git".to_string()]),
+          ..Default::default()
+        },
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        root: Some(temp_dir.path().to_string()),
+        verify_permissions: true,
+        permission_policy: Some(policy_path.to_string()),
+        ..Default::default()
+      },
+    )
+    .await;
+    assert_eq!(
+      result.err().unwrap().to_string(),
+      "The permissions of the installed command are not allowed by the permission policy: --allow-run=git"
+    );
+
+    // the default policy forbids --allow-all
+    let result = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        permissions: PermissionFlags {
+          allow_all: true,
+          ..Default::default()
+        },
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        root: Some(temp_dir.path().to_string()),
+        verify_permissions: true,
+        ..Default::default()
+      },
+    )
+    .await;
+    assert!(result.is_err());
+
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        permissions: PermissionFlags {
+          allow_net: Some(vec![]),
+          ..Default::default()
+        },
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        root: Some(temp_dir.path().to_string()),
+        verify_permissions: true,
+        permission_policy: Some(policy_path.to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    assert!(shim_data.args.contains(&"--allow-net".to_string()));
+  }
+
+  #[tokio::test]
+  async fn install_dry_run_verbose() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    let config_file_path = temp_dir.path().join("test_deno.json");
+    let config = "{ \"tasks\": { \"start\": \"echo\" } }";
+    config_file_path.write(config);
+
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        config_flag: ConfigFlag::Path(config_file_path.to_string()),
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        dry_run: true,
+        verbose: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let output = render_dry_run(&shim_data, true).unwrap();
+    for (path, contents) in
+      render_executable_files(&shim_data, &shim_data.file_path)
+    {
+      assert!(output.contains(&path.display().to_string()));
+      assert!(output.contains(&contents));
+    }
+    let config_copy_path =
+      get_hidden_file_with_ext(&shim_data.file_path, "deno.json");
+    assert!(output.contains(&format!(
+      "{}:\n{}",
+      config_copy_path.display(),
+      config
+    )));
+
+    let output = render_dry_run(&shim_data, false).unwrap();
+    assert!(output.contains(&config_copy_path.display().to_string()));
+    assert!(!output.contains(config));
+
+    // nothing is written
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        dry_run: true,
+        verbose: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    assert!(!bin_dir.exists());
+  }
+
+  #[tokio::test]
+  async fn refresh_companions_from_sources() {
+    let temp_dir = TempDir::new();
+    let config_file_path = temp_dir.path().join("test_deno.json");
+    config_file_path.write("{ \"tasks\": {} }");
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        config_flag: ConfigFlag::Path(config_file_path.to_string()),
+        ..Flags::default()
+      },
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let mut file_path = temp_dir.path().join("bin/echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+    let wrapper = fs::read_to_string(&file_path).unwrap();
+    let config_copy_path =
+      get_hidden_file_with_ext(file_path.as_path(), "deno.json");
+    assert_eq!(
+      fs::read_to_string(&config_copy_path).unwrap(),
+      "{ \"tasks\": {} }"
+    );
+
+    config_file_path.write("{ \"tasks\": { \"start\": \"echo\" } }");
+    let refreshed = refresh_companions(
+      &RealInstallerFs,
+      &HttpClientProvider::new(None, None),
+      "echo_test",
+      temp_dir.path().as_path(),
+      DEFAULT_NETWORK_TIMEOUT,
+    )
+    .await
+    .unwrap();
+    assert_eq!(refreshed, vec![config_copy_path.clone()]);
+    assert_eq!(
+      fs::read_to_string(&config_copy_path).unwrap(),
+      "{ \"tasks\": { \"start\": \"echo\" } }"
+    );
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), wrapper);
+  }
+
+  #[cfg(windows)]
+  #[tokio::test]
+  async fn install_cmd_crlf() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        cmd_crlf: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let cmd_content =
+      fs::read_to_string(bin_dir.join("echo_test.cmd")).unwrap();
+    assert!(cmd_content.contains("\r\n"));
+    assert!(!cmd_content.replace("\r\n", "").contains('\n'));
+
+    let sh_content = fs::read_to_string(bin_dir.join("echo_test")).unwrap();
+    assert!(sh_content.contains('\n'));
+    assert!(!sh_content.contains('\r'));
+  }
+
+  #[cfg(windows)]
+  #[tokio::test]
+  async fn install_bat() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        bat: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let cmd_content =
+      fs::read_to_string(bin_dir.join("echo_test.cmd")).unwrap();
+    let bat_content =
+      fs::read_to_string(bin_dir.join("echo_test.bat")).unwrap();
+    assert_eq!(bat_content, cmd_content);
+
+    assert!(remove_installation(
+      &RealInstallerFs,
+      bin_dir.as_path(),
+      "echo_test"
+    )
+    .unwrap());
+    assert!(!bin_dir.join("echo_test.bat").exists());
+  }
+
+  #[cfg(windows)]
+  #[tokio::test]
+  async fn install_output_format_cmd() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        output_format: InstallOutputFormat::Cmd,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert!(bin_dir.join("echo_test.cmd").exists());
+    assert!(!bin_dir.join("echo_test").exists());
+  }
+
+  #[cfg(windows)]
+  #[tokio::test]
+  async fn install_cmd_echo_off() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let cmd_content =
+      fs::read_to_string(bin_dir.join("echo_test.cmd")).unwrap();
+    assert!(cmd_content.starts_with("@echo off\n% deno-install v1 %\n"));
+    let sh_content = fs::read_to_string(bin_dir.join("echo_test")).unwrap();
+    assert!(!sh_content.contains("echo off"));
+  }
+
+  #[test]
+  fn installer_root_precedence() {
+    let _guard = ENV_LOCK.lock().unwrap();
+    let temp_dir = TempDir::new();
+    let env_root = temp_dir.path().join("env_root");
+    let data_home = temp_dir.path().join("data_home");
+    let home = temp_dir.path().join("home");
+    let home_env_var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    let vars = ["DENO_INSTALL_ROOT", "XDG_DATA_HOME", home_env_var];
+    let original = vars.map(|var| (var, env::var_os(var)));
+
+    env::set_var("DENO_INSTALL_ROOT", env_root.as_path());
+    env::set_var("XDG_DATA_HOME", data_home.as_path());
+    env::set_var(home_env_var, home.as_path());
+    let env_result = get_installer_root();
+
+    env::remove_var("DENO_INSTALL_ROOT");
+    let data_home_result = get_installer_root();
+
+    env::remove_var("XDG_DATA_HOME");
+    let home_result = get_installer_root();
+
+    // an existing ~/.deno is kept over XDG_DATA_HOME
+    home.join(".deno").create_dir_all();
+    env::set_var("XDG_DATA_HOME", data_home.as_path());
+    let existing_home_result = get_installer_root_with_source();
+    env::remove_var("XDG_DATA_HOME");
+
+    env::remove_var(home_env_var);
+    let missing_home_result = get_installer_root();
+
+    for (var, value) in original {
+      match value {
+        Some(value) => env::set_var(var, value),
+        None => env::remove_var(var),
+      }
+    }
+
+    assert_eq!(
+      env_result.unwrap(),
+      canonicalize_path_maybe_not_exists(env_root.as_path()).unwrap()
+    );
+    if cfg!(target_os = "linux") {
+      assert_eq!(
+        data_home_result.unwrap(),
+        data_home.join("deno").to_path_buf()
+      );
+    } else {
+      assert_eq!(data_home_result.unwrap(), home.join(".deno").to_path_buf());
+    }
+    assert_eq!(home_result.unwrap(), home.join(".deno").to_path_buf());
+    assert_eq!(
+      existing_home_result.unwrap(),
+      (home.join(".deno").to_path_buf(), home_env_var)
+    );
+    assert!(missing_home_result
+      .unwrap_err()
+      .to_string()
+      .contains("Set DENO_INSTALL_ROOT or use --root"));
+  }
+
+  #[test]
+  fn all_installer_roots_without_home() {
+    let _guard = ENV_LOCK.lock().unwrap();
+    let temp_dir = TempDir::new();
+    let home_env_var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    let vars = ["DENO_INSTALL_ROOT", "XDG_DATA_HOME", home_env_var];
+    let original = vars.map(|var| (var, env::var_os(var)));
+
+    env::remove_var("XDG_DATA_HOME");
+    env::remove_var(home_env_var);
+    env::set_var("DENO_INSTALL_ROOT", temp_dir.path());
+    let env_root_result = get_all_installer_roots();
+    env::remove_var("DENO_INSTALL_ROOT");
+    let no_root_result = get_all_installer_roots();
+
+    for (var, value) in original {
+      match value {
+        Some(value) => env::set_var(var, value),
+        None => env::remove_var(var),
+      }
+    }
+
+    assert_eq!(
+      env_root_result.unwrap(),
+      vec![canonicalize_path_maybe_not_exists(temp_dir.path()).unwrap()]
+    );
+    assert!(no_root_result.is_err());
+  }
+
+  #[tokio::test]
+  async fn install_only_if_newer() {
+    let _http_server_guard = test_util::http_server();
+    let temp_dir = TempDir::new();
+    let install_flags_global = InstallFlagsGlobal {
+      module_url: "http://localhost:4545/etag_script.ts".to_string(),
+      name: Some("etag_test".to_string()),
+      root: Some(temp_dir.path().to_string()),
+      only_if_newer: true,
+      ..Default::default()
+    };
+
+    let installed = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      install_flags_global.clone(),
+    )
+    .await
+    .unwrap();
+    assert!(!installed.created_files.is_empty());
+    let file_path = temp_dir.path().join("bin/etag_test");
+    let metadata = read_metadata(file_path.as_path()).unwrap();
+    assert_eq!(metadata.etag, Some("33a64df551425fcc55e".to_string()));
+
+    // the server responds with 304 Not Modified
+    let installed = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      install_flags_global.clone(),
+    )
+    .await
+    .unwrap();
+    assert!(installed.created_files.is_empty());
+
+    // the server responds with 200 OK
+    fs::write(
+      get_metadata_path(file_path.as_path()),
+      serde_json::to_string(&InstallMetadata {
+        etag: Some("outdated".to_string()),
+        ..metadata
+      })
+      .unwrap(),
+    )
+    .unwrap();
+    let installed = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      install_flags_global,
+    )
+    .await
+    .unwrap();
+    assert!(!installed.created_files.is_empty());
+    let metadata = read_metadata(file_path.as_path()).unwrap();
+    assert_eq!(metadata.etag, Some("33a64df551425fcc55e".to_string()));
+  }
+
+  #[tokio::test]
+  async fn install_env() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        env: vec![
+          "RUST_LOG=debug".to_string(),
+          "GREETING=hello big world".to_string(),
+        ],
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let sh_content = fs::read_to_string(bin_dir.join("echo_test")).unwrap();
+    if cfg!(windows) {
+      assert!(sh_content
+        .contains("RUST_LOG='debug' GREETING='hello big world' deno run"));
+      let cmd_content =
+        fs::read_to_string(bin_dir.join("echo_test.cmd")).unwrap();
+      assert!(cmd_content.contains("@set \"RUST_LOG=debug\"\n"));
+      assert!(cmd_content.contains("@set \"GREETING=hello big world\"\n"));
+    } else {
+      assert!(sh_content
+        .contains("RUST_LOG='debug' GREETING='hello big world' exec deno run"));
+
+      // the variables reach the deno process
+      let fake_deno_dir = temp_dir.path().join("fake_deno");
+      fake_deno_dir.create_dir_all();
+      let fake_deno = fake_deno_dir.join("deno");
+      fake_deno.write("#!/bin/sh\necho \"$RUST_LOG $GREETING\"\n");
+      fs::set_permissions(&fake_deno, fs::Permissions::from_mode(0o755))
+        .unwrap();
+      let output = Command::new(bin_dir.join("echo_test"))
+        .env_clear()
+        .env("PATH", fake_deno_dir.as_path())
+        .output()
+        .unwrap();
+      assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "debug hello big world\n"
+      );
+    }
+
+    let metadata = read_metadata(bin_dir.join("echo_test").as_path()).unwrap();
+    assert_eq!(
+      metadata.env,
+      vec![
+        "RUST_LOG=debug".to_string(),
+        "GREETING=hello big world".to_string()
+      ]
+    );
+  }
+
+  #[test]
+  fn env_var_validation() {
+    assert_eq!(
+      parse_env_var("A_B=c=d").unwrap(),
+      ("A_B".to_string(), "c=d".to_string())
+    );
+    assert_eq!(
+      parse_env_var("EMPTY=").unwrap(),
+      ("EMPTY".to_string(), "".to_string())
+    );
+    assert!(parse_env_var("NO_VALUE").is_err());
+    assert!(parse_env_var("1ABC=x").is_err());
+    assert!(parse_env_var("A-B=x").is_err());
+    assert!(parse_env_var("A=x\ny").is_err());
+    assert_eq!(
+      sh_env_assignments(&[("A".to_string(), "it's".to_string())]),
+      r"A='it'\''s' "
+    );
+  }
+
+  #[test]
+  fn render_list_formats() {
+    let listed = vec![
+      ListedInstallation {
+        root: PathBuf::from("/root_a"),
+        metadata: InstallMetadata {
+          name: "file_server".to_string(),
+          module_url: "jsr:@std/http/file-server".to_string(),
+          ..Default::default()
+        },
+      },
+      ListedInstallation {
+        root: PathBuf::from("/root_b"),
+        metadata: InstallMetadata {
+          name: "fmt".to_string(),
+          module_url: "https://example.com/fmt.ts".to_string(),
+          args: vec!["--check".to_string()],
+          ..Default::default()
+        },
+      },
+    ];
+
+    assert_eq!(
+      render_list(&listed, InstallListFormat::Plain, false).unwrap(),
+      "file_server jsr:@std/http/file-server\nfmt https://example.com/fmt.ts\n"
+    );
+    assert_eq!(
+      render_list(&listed, InstallListFormat::Plain, true).unwrap(),
+      concat!(
+        "file_server jsr:@std/http/file-server (/root_a)\n",
+        "fmt https://example.com/fmt.ts (/root_b)\n",
+      )
+    );
+    assert_eq!(
+      render_list(&listed, InstallListFormat::Table, false).unwrap(),
+      concat!(
+        "NAME         MODULE\n",
+        "file_server  jsr:@std/http/file-server\n",
+        "fmt          https://example.com/fmt.ts\n",
+      )
+    );
+
+    let json: serde_json::Value = serde_json::from_str(
+      &render_list(&listed, InstallListFormat::Json, false).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+      json,
+      serde_json::json!([
+        {
+          "root": "/root_a",
+          "name": "file_server",
+          "moduleUrl": "jsr:@std/http/file-server",
+          "args": [],
+          "aliases": [],
+        },
+        {
+          "root": "/root_b",
+          "name": "fmt",
+          "moduleUrl": "https://example.com/fmt.ts",
+          "args": ["--check"],
+          "aliases": [],
+        },
+      ])
+    );
+  }
+
+  #[tokio::test]
+  async fn install_file_localhost_url() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    let module_path = temp_dir.path().join("server.ts");
+    module_path.write("console.log('hello');");
+    let file_module_url = Url::from_file_path(module_path.as_path()).unwrap();
+    let localhost_module_string =
+      file_module_url
+        .as_str()
+        .replacen("file:///", "file://localhost/", 1);
+
+    let installed = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: localhost_module_string,
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    assert_eq!(installed.name, "server");
+
+    let mut file_path = bin_dir.join("server");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+    let content = fs::read_to_string(&file_path).unwrap();
+    assert!(content.contains(file_module_url.as_str()));
+    assert!(!content.contains("localhost"));
+
+    let status = Command::new(file_path)
+      .env_clear()
+      // use the deno binary in the target directory
+      .env("PATH", test_util::target_dir())
+      .spawn()
+      .unwrap()
+      .wait()
+      .unwrap();
+    assert!(status.success());
+  }
+
+  #[tokio::test]
+  async fn install_strip_prefix() {
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        module_url: "https://deno.land/x/really_long_module_name@1.0.0/mod.ts"
+          .to_string(),
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        strip_prefix: Some("really_long_".to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    assert_eq!(shim_data.name, "module_name");
+
+    assert_eq!(strip_name_prefix("tool".to_string(), "other_"), "tool");
+    assert_eq!(strip_name_prefix("tool".to_string(), "tool"), "tool");
+  }
+
+  #[test]
+  fn registry_source_detection() {
+    let detect = |url: &str| detect_registry_source(&Url::parse(url).unwrap());
+    assert_eq!(
+      detect("https://deno.land/x/my_tool@v1.0.0/cli.ts"),
+      Some(RegistrySource {
+        kind: RegistryKind::DenoLandX,
+        package: "my_tool".to_string(),
+        version: "v1.0.0".to_string(),
+      })
+    );
+    assert_eq!(
+      detect("npm:cowsay@1.5.0"),
+      Some(RegistrySource {
+        kind: RegistryKind::Npm,
+        package: "cowsay".to_string(),
+        version: "1.5.0".to_string(),
+      })
+    );
+    assert_eq!(
+      detect("jsr:@std/http@1.0.0/file-server"),
+      Some(RegistrySource {
+        kind: RegistryKind::Jsr,
+        package: "@std/http".to_string(),
+        version: "1.0.0".to_string(),
+      })
+    );
+    assert_eq!(
+      detect("https://jsr.io/@std/http/1.0.0/file_server.ts"),
+      Some(RegistrySource {
+        kind: RegistryKind::Jsr,
+        package: "@std/http".to_string(),
+        version: "1.0.0".to_string(),
+      })
+    );
+    // not pinned to an exact version
+    assert_eq!(detect("npm:cowsay@^1.5.0"), None);
+    assert_eq!(detect("https://deno.land/x/my_tool/cli.ts"), None);
+    assert_eq!(detect("http://localhost:4545/echo_server.ts"), None);
+  }
+
+  #[tokio::test]
+  async fn check_updates_reports_newer_versions() {
+    let _http_server_guard = test_util::http_server();
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    bin_dir.create_dir_all();
+    for (name, version) in [("outdated", "v1.0.0"), ("current", "v2.0.0")] {
+      fs::write(
+        get_metadata_path(bin_dir.join(name).as_path()),
+        serde_json::to_string(&InstallMetadata {
+          name: name.to_string(),
+          module_url: format!("https://deno.land/x/my_tool@{version}/cli.ts"),
+          registry: Some(RegistrySource {
+            kind: RegistryKind::DenoLandX,
+            package: "my_tool".to_string(),
+            version: version.to_string(),
+          }),
+          ..Default::default()
+        })
+        .unwrap(),
+      )
+      .unwrap();
+    }
+
+    let registry_urls = RegistryUrls {
+      deno_land_x: Url::parse("http://localhost:4545/install/check_updates/")
+        .unwrap(),
+      ..Default::default()
+    };
+    let updates = check_updates(
+      &HttpClientProvider::new(None, None),
+      &registry_urls,
+      bin_dir.as_path(),
+      DEFAULT_NETWORK_TIMEOUT,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+      updates,
+      vec![AvailableUpdate {
+        name: "outdated".to_string(),
+        current_version: "v1.0.0".to_string(),
+        latest_version: "v2.0.0".to_string(),
+      }]
+    );
+  }
+
+  #[tokio::test]
+  async fn install_io_error_includes_path() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    let mut file_path = bin_dir.join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+    // a directory where the executable should be written makes it fail
+    file_path.create_dir_all();
+
+    let err = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        force: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      format!("Failed to write executable at {}", file_path)
+    );
+  }
+
+  #[cfg(not(windows))]
+  #[tokio::test]
+  async fn install_shebang_env() {
+    let temp_dir = TempDir::new();
+    let module_path = temp_dir.path().join("echo.ts");
+    module_path.write("console.log(Deno.args.join(' '));");
+    let module_url = Url::from_file_path(module_path.as_path()).unwrap();
+    let install_flags_global = InstallFlagsGlobal {
+      module_url: module_url.to_string(),
+      name: Some("echo_test".to_string()),
+      root: Some(temp_dir.path().to_string()),
+      shebang_env: true,
+      ..Default::default()
+    };
+    let flags = Flags {
+      permissions: PermissionFlags {
+        allow_net: Some(vec![]),
+        ..Default::default()
+      },
+      ..Flags::default()
+    };
+
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &flags,
+      &install_flags_global,
+    )
+    .await
+    .unwrap();
+    let (_, content) =
+      render_executable_files(&shim_data, &shim_data.file_path)
+        .pop()
+        .unwrap();
+    assert!(
+      content.starts_with("#!/usr/bin/env -S deno run --ext=js --allow-net ")
+    );
+    assert!(content
+      .ends_with(&format!("\n// deno-install v1\nimport \"{module_url}\";\n")));
+
+    // modules checking whether they are the main module need the shell
+    // script, like npm packages and modules whose source can't be read
+    let guarded_path = temp_dir.path().join("guarded.ts");
+    guarded_path.write("if (import.meta.main) console.log('main');");
+    for module_url in [
+      Url::from_file_path(guarded_path.as_path())
+        .unwrap()
+        .to_string(),
+      "npm:cowsay@1.5.0".to_string(),
+      "jsr:@std/http/file-server".to_string(),
+    ] {
+      let shim_data = resolve_shim_data(
+        &HttpClientProvider::new(None, None),
+        &flags,
+        &InstallFlagsGlobal {
+          module_url: module_url.clone(),
+          ..install_flags_global.clone()
+        },
+      )
+      .await
+      .unwrap();
+      assert!(!shim_data.shebang_env, "{module_url}");
+    }
+
+    // baked-in script arguments need the shell script
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &flags,
+      &InstallFlagsGlobal {
+        args: vec!["--port".to_string(), "8080".to_string()],
+        ..install_flags_global
+      },
+    )
+    .await
+    .unwrap();
+    let (_, content) =
+      render_executable_files(&shim_data, &shim_data.file_path)
+        .pop()
+        .unwrap();
+    assert!(content.starts_with("#!/bin/sh\n"));
+  }
+
+  #[cfg(not(windows))]
+  #[test]
+  fn shebang_env_escapes_args() {
+    assert_eq!(env_split_escape("--allow-net"), "--allow-net");
+    assert_eq!(env_split_escape("--allow-env=$HOME"), "'--allow-env=$HOME'");
+    assert_eq!(env_split_escape("--location=a#b"), "'--location=a#b'");
+    assert_eq!(env_split_escape(r"C:\it's dir"), r"'C:\\it\'s dir'");
+  }
+
+  #[tokio::test]
+  async fn install_check_name_availability() {
+    let temp_dir = TempDir::new();
+    let root = temp_dir.path().to_path_buf();
+    for name in ["taken_a", "taken_b"] {
+      create_install_shim(
+        &HttpClientProvider::new(None, None),
+        &Flags::default(),
+        InstallFlagsGlobal {
+          module_url: "http://localhost:4545/echo_server.ts".to_string(),
+          name: Some(name.to_string()),
+          root: Some(temp_dir.path().to_string()),
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap();
+    }
+
+    let names =
+      ["free_a", "taken_a", "free_b", "taken_b"].map(|name| name.to_string());
+    assert_eq!(
+      check_name_availability(&names, Some(root)).unwrap(),
+      vec![
+        ("free_a".to_string(), true),
+        ("taken_a".to_string(), false),
+        ("free_b".to_string(), true),
+        ("taken_b".to_string(), false),
+      ]
+    );
+  }
+
+  #[test]
+  fn dedup_location_and_cert() {
+    assert_eq!(
+      dedup_single_valued_flags(vec![
+        "run".to_string(),
+        "--location".to_string(),
+        "https://a.example/".to_string(),
+        "--cert".to_string(),
+        "a.pem".to_string(),
+        "--allow-net".to_string(),
+        "--location=https://b.example/".to_string(),
+        "--cert".to_string(),
+        "b.pem".to_string(),
+        "--no-config".to_string(),
+      ]),
+      vec![
+        "run",
+        "--location",
+        "https://a.example/",
+        "--cert",
+        "a.pem",
+        "--allow-net",
+        "--no-config",
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn install_location_and_cert_once() {
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        location: Some(Url::parse("https://deno.land/").unwrap()),
+        ca_data: Some(CaData::File("cert.pem".to_string())),
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    for flag in SINGLE_VALUED_FLAGS {
+      assert_eq!(shim_data.args.iter().filter(|arg| *arg == flag).count(), 1);
+    }
+  }
+
+  #[tokio::test]
+  async fn install_wrapper_comment() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        wrapper_comment: Some("installed by\nprovisioning".to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let sh_content = fs::read_to_string(bin_dir.join("echo_test")).unwrap();
+    assert!(
+      sh_content.contains("# deno-install v1\n# installed by provisioning\n")
+    );
+    if cfg!(windows) {
+      let cmd_content =
+        fs::read_to_string(bin_dir.join("echo_test.cmd")).unwrap();
+      assert!(cmd_content
+        .contains("% deno-install v1 %\n% installed by provisioning %\n"));
+    }
+  }
+
+  #[tokio::test]
+  async fn install_base_dir() {
+    let temp_dir = TempDir::new();
+    let base_dir = temp_dir.path().join("tools");
+    base_dir.create_dir_all();
+    base_dir
+      .join("echo_server.ts")
+      .write("console.log('hello');");
+    base_dir.join("deno.json").write("{}");
+
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        config_flag: ConfigFlag::Path("./deno.json".to_string()),
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "./echo_server.ts".to_string(),
+        root: Some(temp_dir.path().to_string()),
+        base_dir: Some(base_dir.to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let expected_url =
+      Url::from_file_path(base_dir.join("echo_server.ts").as_path()).unwrap();
+    assert_eq!(shim_data.metadata.module_url, expected_url.to_string());
+    assert!(shim_data.args.contains(&expected_url.to_string()));
+    assert_eq!(
+      shim_data.metadata.companions[0].source,
+      base_dir.join("./deno.json").to_string()
+    );
+  }
+
+  #[tokio::test]
+  async fn overwrite_companions_only() {
+    let temp_dir = TempDir::new();
+    let config_file_path = temp_dir.path().join("deno.json");
+    config_file_path.write("{}");
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        config_flag: ConfigFlag::Path(config_file_path.to_string()),
+        ..Flags::default()
+      },
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let mut file_path = temp_dir.path().join("bin/echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+    let executable = fs::read(&file_path).unwrap();
+
+    let new_config_file_path = temp_dir.path().join("new_deno.json");
+    new_config_file_path.write("{ \"lock\": false }");
+    let new_config_flags = Flags {
+      config_flag: ConfigFlag::Path(new_config_file_path.to_string()),
+      ..Flags::default()
+    };
+    let overwritten = overwrite_companions(
+      &RealInstallerFs,
+      &HttpClientProvider::new(None, None),
+      &new_config_flags,
+      &Default::default(),
+      "echo_test",
+      temp_dir.path().as_path(),
+    )
+    .await
+    .unwrap();
+
+    let config_copy_path =
+      get_hidden_file_with_ext(file_path.as_path(), "deno.json");
+    assert_eq!(overwritten, vec![config_copy_path.clone()]);
+    assert_eq!(
+      fs::read_to_string(&config_copy_path).unwrap(),
+      "{ \"lock\": false }"
+    );
+    assert_eq!(fs::read(&file_path).unwrap(), executable);
+    let metadata = read_metadata(file_path.as_path()).unwrap();
+    assert_eq!(
+      metadata.companions[0].source,
+      new_config_file_path.to_string()
+    );
+
+    // the executable doesn't use a lockfile
+    let err = overwrite_companions(
+      &RealInstallerFs,
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        lock: Some(new_config_file_path.to_string()),
+        ..Flags::default()
+      },
+      &Default::default(),
+      "echo_test",
+      temp_dir.path().as_path(),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("Reinstall it instead"));
+
+    let err = overwrite_companions(
+      &RealInstallerFs,
+      &HttpClientProvider::new(None, None),
+      &new_config_flags,
+      &Default::default(),
+      "missing",
+      temp_dir.path().as_path(),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.to_string(), "No installation found for missing");
+  }
+
+  #[tokio::test]
+  async fn install_json_error_already_installed() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    bin_dir.create_dir_all();
+    let install_flags_global = InstallFlagsGlobal {
+      module_url: "http://localhost:4545/echo_server.ts".to_string(),
+      name: Some("echo_test".to_string()),
+      root: Some(temp_dir.path().to_string()),
+      json: true,
+      ..Default::default()
+    };
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      install_flags_global.clone(),
+    )
+    .await
+    .unwrap();
+    let err = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      install_flags_global,
+    )
+    .await
+    .unwrap_err();
+
+    let json: serde_json::Value =
+      serde_json::from_str(&render_json_error(&err)).unwrap();
+    assert_eq!(
+      json,
+      serde_json::json!({
+        "error": "Existing installation found. Aborting (Use -f to overwrite).",
+        "kind": "AlreadyInstalled",
+      })
+    );
+  }
+
+  #[test]
+  fn duplicate_path_entries() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    let other_dir = temp_dir.path().join("other");
+    let twice = env::join_paths([
+      bin_dir.as_path(),
+      other_dir.as_path(),
+      bin_dir.as_path(),
+    ])
+    .unwrap();
+    let once =
+      env::join_paths([bin_dir.as_path(), other_dir.as_path()]).unwrap();
+
+    assert_eq!(
+      duplicate_path_entries_note(bin_dir.as_path(), &twice).unwrap(),
+      format!(
+        "{} appears 2 times in PATH. Consider removing the duplicate entries from your shell profile.",
+        bin_dir.as_path().display()
+      )
+    );
+    assert_eq!(duplicate_path_entries_note(bin_dir.as_path(), &once), None);
+  }
+
+  #[tokio::test]
+  async fn installer_api() {
+    let temp_dir = TempDir::new();
+    let installer = Installer::new(
+      temp_dir.path().to_path_buf(),
+      Arc::new(HttpClientProvider::new(None, None)),
+    );
+
+    let installed = installer
+      .install(InstallOptions::from_flags(
+        Flags::default(),
+        InstallFlagsGlobal {
+          module_url: "http://localhost:4545/echo_server.ts".to_string(),
+          name: Some("echo_test".to_string()),
+          args: vec!["--foo".to_string()],
+          aliases: vec!["echo_alias".to_string()],
+          // replaced by the root of the installer
+          root: Some(temp_dir.path().join("other").to_string()),
+          ..Default::default()
+        },
+      ))
+      .await
+      .unwrap();
+    assert_eq!(installed.name, "echo_test");
+    assert!(installed.created_files.iter().all(|path| path.exists()));
+    assert!(installed
+      .file_path
+      .starts_with(temp_dir.path().join("bin").as_path()));
+
+    let info = installer.info("echo_test").unwrap();
+    assert_eq!(
+      info,
+      InstallationInfo {
+        name: "echo_test".to_string(),
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec!["--foo".to_string()],
+        aliases: vec!["echo_alias".to_string()],
+      }
+    );
+    assert_eq!(installer.list().unwrap(), vec![info]);
+
+    // reinstalling requires force
+    let options = InstallOptions::new("http://localhost:4545/echo_server.ts")
+      .name("echo_test");
+    assert!(installer.install(options.clone()).await.is_err());
+    installer
+      .install(InstallOptions::from_flags(
+        Flags::default(),
+        InstallFlagsGlobal {
+          force: true,
+          ..options.install_flags_global
+        },
+      ))
+      .await
+      .unwrap();
+    assert_eq!(
+      installer.info("echo_test").unwrap().args,
+      Vec::<String>::new()
+    );
+
+    installer.uninstall("echo_test").await.unwrap();
+    assert!(installer.list().unwrap().is_empty());
+    let err = installer.info("echo_test").unwrap_err();
+    assert_eq!(err.to_string(), "No installation found for echo_test");
+    let err = installer.uninstall("echo_test").await.unwrap_err();
+    assert_eq!(err.to_string(), "No installation found for echo_test");
+    assert!(installer.info("../echo_test").is_err());
+  }
+
+  #[test]
+  fn module_cache_check_uses_deno_dir() {
+    let _guard = ENV_LOCK.lock().unwrap();
+    let temp_dir = TempDir::new();
+    let deno_dir = temp_dir.path().join("deno_dir");
+    let module_url =
+      Url::parse("http://localhost:4545/echo_server.ts").unwrap();
+    GlobalHttpCache::new(
+      DenoDir::new(Some(deno_dir.to_path_buf()))
+        .unwrap()
+        .deps_folder_path(),
+      RealDenoCacheEnv,
+    )
+    .set(&module_url, Default::default(), b"console.log(1);")
+    .unwrap();
+
+    let original = env::var_os("DENO_DIR");
+    env::set_var("DENO_DIR", deno_dir.as_path());
+    let cached = is_module_cached(None, &module_url);
+    env::set_var("DENO_DIR", temp_dir.path().join("empty").as_path());
+    let not_cached = is_module_cached(None, &module_url);
+    match original {
+      Some(value) => env::set_var("DENO_DIR", value),
+      None => env::remove_var("DENO_DIR"),
+    }
+
+    assert!(cached.unwrap());
+    assert!(!not_cached.unwrap());
+  }
+
+  #[tokio::test]
+  async fn install_print_path() {
+    let temp_dir = TempDir::new();
+    let installed = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        print_path: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let mut file_path = canonicalize_path(temp_dir.path().as_path())
+      .unwrap()
+      .join("bin")
+      .join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+    assert_eq!(
+      print_path_output(&installed),
+      format!("{}\n", file_path.display())
+    );
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn read_only_symlinked_installation_dir() {
+    let temp_dir = TempDir::new();
+    let target = temp_dir.path().join("read_only");
+    target.create_dir_all();
+    fs::set_permissions(&target, fs::Permissions::from_mode(0o555)).unwrap();
+    let bin_dir = temp_dir.path().join("bin");
+    std::os::unix::fs::symlink(&target, &bin_dir).unwrap();
+
+    let err = explain_read_only_installation_dir(
+      bin_dir.as_path(),
+      AnyError::from(io::Error::from_raw_os_error(libc::EROFS))
+        .context("Failed to write executable"),
+    );
+    assert_eq!(
+      err.to_string(),
+      format!(
+        "{} is a symlink to {}, which appears to be on a read-only mount. Point it to a writable directory or use --root.",
+        bin_dir,
+        target
+      )
+    );
+
+    // errors unrelated to permissions are left alone
+    let err = explain_read_only_installation_dir(
+      bin_dir.as_path(),
+      io::Error::from(io::ErrorKind::NotFound).into(),
+    );
+    assert_eq!(
+      err.to_string(),
+      io::Error::from(io::ErrorKind::NotFound).to_string()
+    );
+
+    fs::set_permissions(&target, fs::Permissions::from_mode(0o755)).unwrap();
+  }
+
+  #[test]
+  fn pin_manifest_to_lockfile() {
+    let temp_dir = TempDir::new();
+    let lockfile_path = temp_dir.path().join("deno.lock");
+    lockfile_path.write(
+      r#"{
+  "version": "3",
+  "packages": {
+    "specifiers": {
+      "jsr:@std/http@^0.224.0": "jsr:@std/http@0.224.5",
+      "npm:cowsay": "npm:cowsay@1.5.0"
+    }
+  },
+  "remote": {
+    "https://deno.land/std@0.224.0/http/file_server.ts": "abc123"
+  }
+}"#,
+    );
+    let manifest_path = temp_dir.path().join("tools.json");
+    manifest_path.write(
+      r#"{
+  "tools": [
+    { "module": "jsr:@std/http@^0.224.0/file-server", "name": "serve" },
+    { "module": "npm:cowsay" },
+    { "module": "https://deno.land/std@0.224.0/http/file_server.ts", "name": "file_server" },
+    { "module": "local.ts" }
+  ]
+}"#,
+    );
+    let entries = read_install_manifest(
+      manifest_path.as_path(),
+      &InstallFlagsGlobal::default(),
+    )
+    .unwrap();
+
+    let pinned = pin_to_lockfile(entries, lockfile_path.as_path()).unwrap();
+    assert_eq!(
+      pinned
+        .iter()
+        .map(|entry| entry.module_url.as_str())
+        .collect::<Vec<_>>(),
+      vec![
+        "jsr:@std/http@0.224.5/file-server",
+        "npm:cowsay@1.5.0",
+        "https://deno.land/std@0.224.0/http/file_server.ts",
+        temp_dir.path().join("local.ts").to_string().as_str(),
+      ]
+    );
+
+    let entries = vec![InstallFlagsGlobal {
+      module_url: "https://example.com/unlocked.ts".to_string(),
+      ..Default::default()
+    }];
+    let err = pin_to_lockfile(entries, lockfile_path.as_path()).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      format!(
+        "https://example.com/unlocked.ts is not locked in {}",
+        lockfile_path
+      )
+    );
+  }
+
+  #[test]
+  fn unsafe_names() {
+    for name in ["..", "../evil", "a/b", "a\\b", ".hidden", "a..b", ""] {
+      assert!(!is_safe_name(name), "{name}");
+      let err = validate_name(name).unwrap_err();
+      assert_eq!(err.to_string(), format!("Invalid executable name: {name}"));
+    }
+    assert!(is_safe_name("file_server"));
+    assert!(is_safe_name("my-tool2"));
+
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    bin_dir.create_dir_all();
+    temp_dir.path().join("victim").write("");
+    assert!(remove_installation(
+      &RealInstallerFs,
+      bin_dir.as_path(),
+      "../victim"
+    )
+    .is_err());
+    assert!(temp_dir.path().join("victim").exists());
+  }
 
   #[tokio::test]
-  async fn install_infer_name_from_url() {
-    let http_client = HttpClientProvider::new(None, None);
+  async fn check_remote_module_timeout() {
+    // a server that accepts connections but never responds
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = tokio::spawn(async move {
+      let mut connections = Vec::new();
+      loop {
+        let (stream, _) = listener.accept().await.unwrap();
+        connections.push(stream);
+      }
+    });
+
+    let module_url =
+      Url::parse(&format!("http://127.0.0.1:{port}/mod.ts")).unwrap();
+    let result = check_remote_module(
+      &HttpClientProvider::new(None, None),
+      &module_url,
+      None,
+      Duration::from_millis(200),
+    )
+    .await;
+    server.abort();
+
+    let Err(err) = result else {
+      panic!("expected a timeout");
+    };
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("https://example.com/abc/server.ts").unwrap()
+      err.to_string(),
+      format!("Timed out after 200ms checking {module_url}")
+    );
+  }
+
+  #[tokio::test]
+  async fn uninstall_all_installations() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    let config_path = temp_dir.path().join("deno.json");
+    config_path.write("{}");
+    let lock_path = temp_dir.path().join("deno.lock");
+    lock_path.write("{}");
+    // the companion files are removed along with the executables
+    let flags = Flags {
+      config_flag: ConfigFlag::Path(config_path.to_string()),
+      lock: Some(lock_path.to_string()),
+      ..Flags::default()
+    };
+    for name in ["tool_a", "tool_b", "tool_c"] {
+      create_install_shim(
+        &HttpClientProvider::new(None, None),
+        &flags,
+        InstallFlagsGlobal {
+          module_url: "http://localhost:4545/echo_server.ts".to_string(),
+          name: Some(name.to_string()),
+          root: Some(temp_dir.path().to_string()),
+          ..Default::default()
+        },
       )
-      .await,
-      Some("server".to_string())
+      .await
+      .unwrap();
+    }
+    assert!(bin_dir.join(".tool_a.deno.json").exists());
+    assert!(bin_dir.join(".tool_a.lock.json").exists());
+    let foreign_path = bin_dir.join("foreign");
+    foreign_path.write("#!/bin/sh\necho foreign\n");
+
+    let removed = uninstall_all(bin_dir.as_path()).unwrap();
+
+    assert_eq!(removed, vec!["tool_a", "tool_b", "tool_c"]);
+    let remaining = fs::read_dir(&bin_dir)
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+      .collect::<Vec<_>>();
+    assert_eq!(remaining, vec!["foreign"]);
+  }
+
+  #[tokio::test]
+  async fn install_working_dir() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    let working_dir = temp_dir.path().join("my tools");
+    working_dir.create_dir_all();
+    let working_dir = canonicalize_path(working_dir.as_path()).unwrap();
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        cwd: Some(working_dir.to_string_lossy().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let mut file_path = bin_dir.join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+    let content = fs::read_to_string(&file_path).unwrap();
+    let (cd, deno) = if cfg!(windows) {
+      (
+        format!("@cd /d \"{}\" || exit /b 1\n", working_dir.display()),
+        "@deno ",
+      )
+    } else {
+      (
+        format!("cd '{}' || exit 1\n", working_dir.display()),
+        "exec deno ",
+      )
+    };
+    let cd_index = content.find(&cd).unwrap();
+    assert!(cd_index < content.find(deno).unwrap());
+    assert_eq!(
+      read_metadata(&bin_dir.join("echo_test"))
+        .unwrap()
+        .working_dir,
+      Some(working_dir.to_string_lossy().to_string())
     );
+
+    let err = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        cwd: Some(temp_dir.path().join("missing").to_string()),
+        force: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().starts_with("The working directory"));
+  }
+
+  #[tokio::test]
+  async fn install_infer_name_from_encoded_parent() {
+    let module_url = "https://example.com/my%20tool/main.ts";
     assert_eq!(
       infer_name_from_url(
-        &http_client,
-        &Url::parse("https://example.com/abc/main.ts").unwrap()
+        &HttpClientProvider::new(None, None),
+        &Url::parse(module_url).unwrap()
       )
       .await,
-      Some("abc".to_string())
+      Some("my tool".to_string())
     );
+
+    // the decoded name is still validated
+    let result = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        module_url: module_url.to_string(),
+        ..Default::default()
+      },
+    )
+    .await;
+    let Err(err) = result else {
+      panic!("expected an invalid name");
+    };
+    assert_eq!(err.to_string(), "Invalid executable name: my tool");
+  }
+
+  #[tokio::test]
+  async fn install_allow_import() {
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        permissions: PermissionFlags {
+          allow_import: Some(vec![
+            "deno.land".to_string(),
+            "jsr.io:443".to_string(),
+          ]),
+          ..Default::default()
+        },
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("https://example.com/abc/mod.ts").unwrap()
-      )
-      .await,
-      Some("abc".to_string())
+      shim_data.args,
+      vec![
+        "run",
+        "--allow-import=deno.land,jsr.io:443",
+        "--no-config",
+        "http://localhost:4545/echo_server.ts",
+      ]
     );
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn install_wrapper_dir_permissions() {
+    let temp_dir = TempDir::new();
+    fs::set_permissions(temp_dir.path(), fs::Permissions::from_mode(0o755))
+      .unwrap();
+    let root = temp_dir.path().join("root");
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(root.to_string()),
+        wrapper_dir_permissions: Some(0o700),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    for dir in [root.as_path().to_path_buf(), root.join("bin").to_path_buf()] {
+      let mode = fs::metadata(&dir).unwrap().permissions().mode() & 0o777;
+      assert_eq!(mode, 0o700, "{}", dir.display());
+    }
+    // the pre-existing parent is left alone
+    let mode =
+      fs::metadata(temp_dir.path()).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode, 0o755);
+  }
+
+  #[tokio::test]
+  async fn install_lock_no_copy() {
+    let temp_dir = TempDir::new();
+    let lock_path = temp_dir.path().join("deno.lock");
+    lock_path.write("{}");
+    let lock_path = canonicalize_path(lock_path.as_path()).unwrap();
+
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        lock: Some(lock_path.to_string_lossy().to_string()),
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        lock_no_copy: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("https://example.com/ab%20c/mod.ts").unwrap()
+      shim_data.args,
+      vec![
+        "run".to_string(),
+        "--no-config".to_string(),
+        "--lock".to_string(),
+        lock_path.to_string_lossy().to_string(),
+        "http://localhost:4545/echo_server.ts".to_string(),
+      ]
+    );
+    assert!(shim_data.extra_files.is_empty());
+    assert!(shim_data.metadata.companions.is_empty());
+  }
+
+  #[tokio::test]
+  async fn export_manifest_round_trip() {
+    let temp_dir = TempDir::new();
+    let old_root = temp_dir.path().join("old");
+    let working_dir = temp_dir.path().join("work");
+    working_dir.create_dir_all();
+    let working_dir = canonicalize_path(working_dir.as_path()).unwrap();
+    for (flags, install_flags_global) in [
+      (
+        Flags {
+          permissions: PermissionFlags {
+            allow_net: Some(vec![]),
+            ..Default::default()
+          },
+          ..Default::default()
+        },
+        InstallFlagsGlobal {
+          module_url: "http://localhost:4545/echo_server.ts".to_string(),
+          name: Some("echo_test".to_string()),
+          args: vec!["--foo".to_string()],
+          aliases: vec!["echo_alias".to_string()],
+          cwd: Some(working_dir.to_string_lossy().to_string()),
+          ..Default::default()
+        },
+      ),
+      (
+        Flags::default(),
+        InstallFlagsGlobal {
+          module_url: "http://localhost:4545/cat.ts".to_string(),
+          env: vec!["LEVEL=debug".to_string()],
+          ..Default::default()
+        },
+      ),
+    ] {
+      create_install_shim(
+        &HttpClientProvider::new(None, None),
+        &flags,
+        InstallFlagsGlobal {
+          root: Some(old_root.to_string()),
+          ..install_flags_global
+        },
       )
-      .await,
-      Some("ab c".to_string())
+      .await
+      .unwrap();
+    }
+
+    let manifest = export_manifest(old_root.join("bin").as_path()).unwrap();
+    assert_eq!(
+      manifest
+        .tools
+        .iter()
+        .map(|entry| &entry.name)
+        .collect::<Vec<_>>(),
+      vec![&Some("cat".to_string()), &Some("echo_test".to_string())]
     );
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("https://example.com/abc/index.ts").unwrap()
-      )
-      .await,
-      Some("abc".to_string())
+      manifest.tools[1].cwd,
+      Some(working_dir.to_string_lossy().to_string())
     );
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("https://example.com/abc/cli.ts").unwrap()
-      )
-      .await,
-      Some("abc".to_string())
+      manifest.tools[1].deno_flags,
+      vec!["--allow-net".to_string(), "--no-check".to_string()]
+    );
+    let manifest_path = temp_dir.path().join("tools.json");
+    manifest_path.write(serde_json::to_string_pretty(&manifest).unwrap());
+
+    let new_root = temp_dir.path().join("new");
+    let entries = read_install_manifest(
+      manifest_path.as_path(),
+      &InstallFlagsGlobal {
+        module_url: manifest_path.to_string(),
+        root: Some(new_root.to_string()),
+        manifest: true,
+        ..Default::default()
+      },
+    )
+    .unwrap();
+    for result in create_install_shims(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      entries,
+      None,
+    )
+    .await
+    {
+      result.unwrap();
+    }
+
+    assert_eq!(
+      export_manifest(new_root.join("bin").as_path()).unwrap(),
+      manifest
+    );
+    let new_file_path = new_root.as_path().join("bin").join("echo_test");
+    let metadata = read_metadata(&new_file_path).unwrap();
+    assert_eq!(
+      metadata.working_dir,
+      Some(working_dir.to_string_lossy().to_string())
+    );
+    let content = fs::read_to_string(if cfg!(windows) {
+      new_file_path.with_extension("cmd")
+    } else {
+      new_file_path
+    })
+    .unwrap();
+    assert!(content.contains("--allow-net"));
+    assert!(content.contains(&*working_dir.to_string_lossy()));
+  }
+
+  #[tokio::test]
+  async fn install_replace() {
+    let _http_server_guard = test_util::http_server();
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    replace_installation(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(),
+        root: Some(temp_dir.path().to_string()),
+        replace: Some("echo_test".to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let mut file_path = bin_dir.join("echo_test");
+    if cfg!(windows) {
+      file_path = file_path.with_extension("cmd");
+    }
+    let backup_path = with_file_name_suffix(file_path.as_path(), ".bak");
+    let content = fs::read_to_string(&file_path).unwrap();
+    assert!(content.contains("http://localhost:4545/cat.ts"));
+    let backup = fs::read_to_string(backup_path).unwrap();
+    assert!(backup.contains("http://localhost:4545/echo_server.ts"));
+    assert_eq!(
+      read_metadata(bin_dir.join("echo_test").as_path())
+        .unwrap()
+        .module_url,
+      "http://localhost:4545/cat.ts"
     );
+    assert!(!with_file_name_suffix(file_path.as_path(), ".new").exists());
+
+    // replacing a missing installation fails without leaving files behind
+    let err = replace_installation(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(),
+        root: Some(temp_dir.path().to_string()),
+        replace: Some("missing".to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.to_string(), "No installation found for missing");
+  }
+
+  #[test]
+  fn swap_in_staged_files_keeps_backups_and_rolls_back() {
+    let fs = InMemoryInstallerFs::default();
+    let tool = PathBuf::from("/bin/tool");
+    let tool_new = PathBuf::from("/bin/tool.new");
+    let meta = PathBuf::from("/bin/.tool.meta.json");
+    let meta_new = PathBuf::from("/bin/.tool.meta.json.new");
+    fs.write(&tool, "v2").unwrap();
+    fs.write(&with_file_name_suffix(&tool, ".bak"), "v1")
+      .unwrap();
+    fs.write(&tool_new, "v3").unwrap();
+
+    // the staged metadata is missing, so its swap fails
+    let swaps = [
+      (tool.as_path(), tool_new.as_path()),
+      (meta.as_path(), meta_new.as_path()),
+    ];
+    let err = swap_in_staged_files(&fs, &swaps).unwrap_err();
+    assert!(err.to_string().starts_with("Failed to replace"), "{err}");
+    assert_eq!(fs.read_to_string(&tool).unwrap(), "v2");
+    assert!(!fs.exists(&meta));
+    assert!(!fs.exists(&with_file_name_suffix(&tool, ".bak.1")));
+
+    fs.write(&tool_new, "v3").unwrap();
+    fs.write(&meta_new, "{}").unwrap();
+    swap_in_staged_files(&fs, &swaps).unwrap();
+    assert_eq!(fs.read_to_string(&tool).unwrap(), "v3");
+    assert_eq!(fs.read_to_string(&meta).unwrap(), "{}");
+    // the earlier backup is kept
+    let backup = with_file_name_suffix(&tool, ".bak");
+    assert_eq!(fs.read_to_string(&backup).unwrap(), "v1");
+    let backup = with_file_name_suffix(&tool, ".bak.1");
+    assert_eq!(fs.read_to_string(&backup).unwrap(), "v2");
+  }
+
+  #[tokio::test]
+  async fn install_rejects_module_in_installation_dir() {
+    let temp_dir = TempDir::new();
+    let root = canonicalize_path(temp_dir.path().as_path()).unwrap();
+    let bin_dir = root.join("bin");
+    let wrapper_path = bin_dir.join("echo_test");
+
+    let result = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        module_url: wrapper_path.to_string_lossy().to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(root.to_string_lossy().to_string()),
+        ..Default::default()
+      },
+    )
+    .await;
+    let Err(err) = result else {
+      panic!("expected the installation to be rejected");
+    };
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("https://example.com/main.ts").unwrap()
+      err.to_string(),
+      format!(
+        "{} is in the installation directory {}, so the executable would run an installed executable instead of a script. Install the original script instead.",
+        wrapper_path.display(),
+        bin_dir.display()
       )
-      .await,
-      Some("main".to_string())
     );
+  }
+
+  #[test]
+  fn verify_signature_accepts_and_rejects() {
+    use ring::rand::SystemRandom;
+    use ring::signature::Ed25519KeyPair;
+    use ring::signature::KeyPair;
+
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    let public_key = BASE64_STANDARD.encode(key_pair.public_key().as_ref());
+    let contents = b"console.log('hello');";
+    let signature = BASE64_STANDARD.encode(key_pair.sign(contents).as_ref());
+
+    assert!(verify_signature(&public_key, contents, &signature).is_ok());
+    // trailing newlines of the key or signature files are ignored
+    assert!(verify_signature(
+      &format!("{public_key}\n"),
+      contents,
+      &format!("{signature}\n"),
+    )
+    .is_ok());
+
+    let err =
+      verify_signature(&public_key, b"console.log('pwned');", &signature)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "The signature doesn't match");
+
+    let other_signature =
+      BASE64_STANDARD.encode(key_pair.sign(b"something else").as_ref());
+    assert!(verify_signature(&public_key, contents, &other_signature).is_err());
+    assert!(verify_signature(&public_key, contents, "not base64!").is_err());
+  }
+
+  #[tokio::test]
+  async fn install_grant_prompt_once() {
+    let temp_dir = TempDir::new();
+
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        args: vec!["--foo".to_string()],
+        grant_prompt_once: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let state_path = get_hidden_file_with_ext(&shim_data.file_path, "grants");
+    let recorder_path =
+      get_hidden_file_with_ext(&shim_data.file_path, "grant.js");
+    assert_eq!(shim_data.grant_state_file, Some(state_path.clone()));
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("https://example.com").unwrap()
-      )
-      .await,
-      None
+      shim_data.args,
+      vec![
+        "run".to_string(),
+        "--no-config".to_string(),
+        format!("--allow-write={}", state_path.to_string_lossy()),
+        recorder_path.to_string_lossy().to_string(),
+        "--foo".to_string(),
+      ]
     );
+
+    // the entrypoint imports the module and records the grants on exit
+    let (path, recorder) = &shim_data.extra_files[0];
+    assert_eq!(path, &recorder_path);
+    assert!(recorder.contains("Deno.writeTextFileSync("));
+    assert!(recorder.contains(
+      &serde_json::Value::from(state_path.to_string_lossy().to_string())
+        .to_string()
+    ));
+    assert!(recorder
+      .contains(r#"await import("http://localhost:4545/echo_server.ts");"#));
+
+    // the recorded flags are passed right before the entrypoint
+    let files = render_executable_files(&shim_data, &shim_data.file_path);
+    let sh_content = &files.last().unwrap().1;
+    assert!(sh_content.contains("done < "));
+    assert!(sh_content.contains("$grants "));
+    if cfg!(windows) {
+      assert!(files[0].1.contains("findstr /l /x /c:\"--allow-%%p\""));
+      assert!(files[0].1.contains("%grants% "));
+    }
+
+    // the state file is added to an existing --allow-write list
+    let resolve_args = |allow_write: Vec<String>| {
+      let flags = Flags {
+        permissions: PermissionFlags {
+          allow_write: Some(allow_write),
+          ..Default::default()
+        },
+        ..Flags::default()
+      };
+      let install_flags_global = InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        grant_prompt_once: true,
+        ..Default::default()
+      };
+      async move {
+        let shim_data = resolve_shim_data(
+          &HttpClientProvider::new(None, None),
+          &flags,
+          &install_flags_global,
+        )
+        .await
+        .unwrap();
+        shim_data
+          .args
+          .into_iter()
+          .filter(|arg| arg.starts_with("--allow-write"))
+          .collect::<Vec<_>>()
+      }
+    };
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("file:///abc/server.ts").unwrap()
-      )
-      .await,
-      Some("server".to_string())
+      resolve_args(vec!["out".to_string()]).await,
+      vec![format!(
+        "--allow-write=out,{}",
+        state_path.to_string_lossy()
+      )]
     );
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("file:///abc/main.ts").unwrap()
-      )
-      .await,
-      Some("abc".to_string())
+      resolve_args(vec![]).await,
+      vec!["--allow-write".to_string()]
     );
-    assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("file:///ab%20c/main.ts").unwrap()
-      )
-      .await,
-      Some("ab c".to_string())
+  }
+
+  #[tokio::test]
+  async fn install_grant_prompt_once_ignores_forged_grants() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        grant_prompt_once: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    let file_path = executable_path(bin_dir.as_path(), "echo_test");
+    get_hidden_file_with_ext(&file_path, "grants").write(
+      "--allow-all\n& echo pwned\n--allow-net --allow-run\n--allow-net\n",
     );
-    assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("file:///main.ts").unwrap()
+
+    // the fake deno prints the arguments it is run with
+    let fake_deno_dir = temp_dir.path().join("fake_deno");
+    fake_deno_dir.create_dir_all();
+    let output = if cfg!(windows) {
+      fake_deno_dir.join("deno.cmd").write("@echo %*\n");
+      Command::new("cmd")
+        .arg("/C")
+        .arg(&file_path)
+        .env("PATH", fake_deno_dir.as_path())
+        .output()
+        .unwrap()
+    } else {
+      let fake_deno = fake_deno_dir.join("deno");
+      fake_deno.write("#!/bin/sh\necho \"$@\"\n");
+      #[cfg(not(windows))]
+      fs::set_permissions(&fake_deno, fs::Permissions::from_mode(0o755))
+        .unwrap();
+      Command::new(&file_path)
+        .env_clear()
+        .env("PATH", fake_deno_dir.as_path())
+        .output()
+        .unwrap()
+    };
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(" --allow-net "), "{stdout}");
+    assert!(!stdout.contains("--allow-all"), "{stdout}");
+    assert!(!stdout.contains("--allow-run"), "{stdout}");
+    assert!(!stdout.contains("pwned"), "{stdout}");
+
+    // recorded permissions are never verified
+    let policy_path = temp_dir.path().join("policy.json");
+    policy_path.write(r#"{ "forbid": ["allow-run"] }"#);
+    for install_flags_global in [
+      InstallFlagsGlobal {
+        verify_permissions: true,
+        ..Default::default()
+      },
+      InstallFlagsGlobal {
+        permission_policy: Some(policy_path.to_string()),
+        ..Default::default()
+      },
+    ] {
+      let err = resolve_shim_data(
+        &HttpClientProvider::new(None, None),
+        &Flags::default(),
+        &InstallFlagsGlobal {
+          module_url: "http://localhost:4545/echo_server.ts".to_string(),
+          name: Some("echo_test".to_string()),
+          root: Some(temp_dir.path().to_string()),
+          grant_prompt_once: true,
+          force: true,
+          ..install_flags_global
+        },
       )
-      .await,
-      Some("main".to_string())
-    );
+      .await
+      .unwrap_err();
+      assert!(err
+        .to_string()
+        .starts_with("--grant-prompt-once can't be used"));
+    }
+
+    // importing an npm package doesn't run its bin
+    let err = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        module_url: "npm:cowsay@1.5.0".to_string(),
+        name: Some("cowsay".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        grant_prompt_once: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap_err();
     assert_eq!(
-      infer_name_from_url(&http_client, &Url::parse("file:///").unwrap()).await,
-      None
+      err.downcast_ref::<InstallError>().unwrap().kind,
+      InstallErrorKind::InvalidModuleUrl
     );
+  }
+
+  #[tokio::test]
+  async fn install_auto_import_map() {
+    let temp_dir = TempDir::new();
+    let tool_dir = temp_dir.path().join("tool");
+    tool_dir.create_dir_all();
+    tool_dir.join("main.ts").write("import \"@std/fmt\";");
+    tool_dir
+      .join("deno.json")
+      .write(r#"{ "imports": { "@std/fmt": "jsr:@std/fmt@1" }, "tasks": {} }"#);
+    let install_flags_global = InstallFlagsGlobal {
+      module_url: tool_dir.join("main.ts").to_string(),
+      name: Some("tool".to_string()),
+      root: Some(temp_dir.path().to_string()),
+      auto_import_map: true,
+      ..Default::default()
+    };
+
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &install_flags_global,
+    )
+    .await
+    .unwrap();
+    let copy_path =
+      get_hidden_file_with_ext(&shim_data.file_path, "import_map.json");
+    assert!(shim_data
+      .args
+      .windows(2)
+      .any(|args| args[0] == "--import-map"
+        && args[1] == copy_path.to_string_lossy()));
+    let (path, import_map) = &shim_data.extra_files[0];
+    assert_eq!(path, &copy_path);
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("https://example.com/abc@0.1.0").unwrap()
-      )
-      .await,
-      Some("abc".to_string())
+      serde_json::from_str::<serde_json::Value>(import_map).unwrap(),
+      serde_json::json!({ "imports": { "@std/fmt": "jsr:@std/fmt@1" } })
     );
+
+    // an import map referenced by the config is copied as a companion
+    tool_dir
+      .join("deno.json")
+      .write(r#"{ "importMap": "./import_map.json" }"#);
+    tool_dir
+      .join("import_map.json")
+      .write(r#"{ "imports": { "a": "./a.ts" } }"#);
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &install_flags_global,
+    )
+    .await
+    .unwrap();
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("https://example.com/abc@0.1.0/main.ts").unwrap()
-      )
-      .await,
-      Some("abc".to_string())
+      shim_data.extra_files,
+      vec![(
+        copy_path.clone(),
+        r#"{ "imports": { "a": "./a.ts" } }"#.to_string()
+      )]
     );
+    assert_eq!(shim_data.metadata.companions[0].path, copy_path);
+
+    // nothing is picked up without the flag
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        auto_import_map: false,
+        ..install_flags_global
+      },
+    )
+    .await
+    .unwrap();
+    assert!(!shim_data.args.contains(&"--import-map".to_string()));
+  }
+
+  #[tokio::test]
+  async fn install_which() {
+    let temp_dir = TempDir::new();
+
+    let installed = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let file_path =
+      which("echo_test", Some(temp_dir.path().to_path_buf())).unwrap();
+    assert!(file_path.is_absolute());
+    assert_eq!(file_path, installed.file_path);
+
+    let err =
+      which("not_installed", Some(temp_dir.path().to_path_buf())).unwrap_err();
+    assert_eq!(err.to_string(), "not_installed is not installed");
+  }
+
+  #[tokio::test]
+  async fn install_rejects_control_chars() {
+    let temp_dir = TempDir::new();
+    let install_flags_global = InstallFlagsGlobal {
+      module_url: "http://localhost:4545/echo_server.ts".to_string(),
+      name: Some("echo_test".to_string()),
+      root: Some(temp_dir.path().to_string()),
+      ..Default::default()
+    };
+
+    let err = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        args: vec!["--foo".to_string(), "a\0b".to_string()],
+        ..install_flags_global.clone()
+      },
+    )
+    .await
+    .unwrap_err();
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("https://example.com/abc@def@ghi").unwrap()
-      )
-      .await,
-      Some("abc".to_string())
+      err.to_string(),
+      "The argument \"a\\0b\" must not contain NUL bytes or control characters"
     );
+
+    let err = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo\u{1b}_server.ts".to_string(),
+        ..install_flags_global.clone()
+      },
+    )
+    .await
+    .unwrap_err();
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("https://example.com/@abc.ts").unwrap()
-      )
-      .await,
-      Some("@abc".to_string())
+      err.downcast_ref::<InstallError>().unwrap().kind,
+      InstallErrorKind::InvalidModuleUrl
     );
+
+    assert!(create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        env: vec!["A=b\u{7}".to_string()],
+        ..install_flags_global
+      },
+    )
+    .await
+    .is_err());
+    assert!(!temp_dir.path().join("bin").exists());
+
+    // tabs are allowed
+    assert!(!has_control_chars("a\tb"));
+  }
+
+  // the environment must not change while installing
+  #[allow(clippy::await_holding_lock)]
+  #[tokio::test]
+  async fn install_registry_default() {
+    let _guard = ENV_LOCK.lock().unwrap();
+    env::remove_var("DENO_INSTALL_REGISTRY");
+    let temp_dir = TempDir::new();
+    let install_flags_global = InstallFlagsGlobal {
+      module_url: "cowsay".to_string(),
+      root: Some(temp_dir.path().to_string()),
+      registry_default: Some("https://deno.land/x/".to_string()),
+      ..Default::default()
+    };
+
+    let installed = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      install_flags_global.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(installed.name, "cowsay");
+    let content = fs::read_to_string(&installed.file_path).unwrap();
+    assert!(content.contains("https://deno.land/x/cowsay"));
+
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("https://example.com/@abc/mod.ts").unwrap()
-      )
-      .await,
-      Some("@abc".to_string())
+      resolve_bare_name("cowsay@1.1.9", Some("npm:"), temp_dir.path()).unwrap(),
+      "npm:cowsay@1.1.9"
     );
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("file:///@abc.ts").unwrap()
-      )
-      .await,
-      Some("@abc".to_string())
+      resolve_bare_name("./cowsay", None, temp_dir.path()).unwrap(),
+      "./cowsay"
     );
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("file:///@abc/cli.ts").unwrap()
-      )
-      .await,
-      Some("@abc".to_string())
+      resolve_bare_name("main.ts", None, temp_dir.path()).unwrap(),
+      "main.ts"
     );
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("npm:cowsay@1.2/cowthink").unwrap()
-      )
-      .await,
-      Some("cowthink".to_string())
+      resolve_bare_name("gh:owner/repo/cli.ts", Some("npm:"), temp_dir.path())
+        .unwrap(),
+      "https://raw.githubusercontent.com/owner/repo/HEAD/cli.ts"
     );
+
+    // existing local paths aren't resolved against the registry
+    let local_dir = temp_dir.path().join("local");
+    local_dir.create_dir_all();
+    local_dir.join("cowsay").write("console.log('moo');");
+    local_dir.join("tool").create_dir_all();
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("npm:cowsay@1.2/cowthink/test").unwrap()
-      )
-      .await,
-      Some("cowsay".to_string())
+      resolve_bare_name("cowsay", Some("npm:"), local_dir.as_path()).unwrap(),
+      "cowsay"
     );
     assert_eq!(
-      infer_name_from_url(&http_client, &Url::parse("npm:cowsay@1.2").unwrap())
-        .await,
-      Some("cowsay".to_string())
+      resolve_bare_name("tool", Some("npm:"), local_dir.as_path()).unwrap(),
+      "tool"
     );
     assert_eq!(
-      infer_name_from_url(
-        &http_client,
-        &Url::parse("npm:@types/node@1.2").unwrap()
-      )
-      .await,
-      None
+      resolve_bare_name("cowsay", None, local_dir.as_path()).unwrap(),
+      "cowsay"
+    );
+    assert_eq!(
+      resolve_bare_name("missing", Some("npm:"), local_dir.as_path()).unwrap(),
+      "npm:missing"
+    );
+
+    let err = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        registry_default: None,
+        force: true,
+        ..install_flags_global
+      },
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(
+      err.downcast_ref::<InstallError>().unwrap().kind,
+      InstallErrorKind::InvalidModuleUrl
     );
   }
 
   #[tokio::test]
-  async fn install_unstable() {
+  async fn install_fail_if_exists() {
     let temp_dir = TempDir::new();
-    let bin_dir = temp_dir.path().join("bin");
-    std::fs::create_dir(&bin_dir).unwrap();
+    let install_flags_global = InstallFlagsGlobal {
+      module_url: "http://localhost:4545/echo_server.ts".to_string(),
+      name: Some("echo_test".to_string()),
+      root: Some(temp_dir.path().to_string()),
+      fail_if_exists: true,
+      ..Default::default()
+    };
 
     create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      install_flags_global.clone(),
+    )
+    .await
+    .unwrap();
+
+    let err = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      install_flags_global.clone(),
+    )
+    .await
+    .unwrap_err();
+    let err = err.downcast_ref::<InstallError>().unwrap();
+    assert_eq!(err.kind, InstallErrorKind::AlreadyInstalled);
+    assert_eq!(err.exit_code, ALREADY_INSTALLED_EXIT_CODE);
+
+    // without the flag the default error exits with 1
+    let err = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        fail_if_exists: false,
+        ..install_flags_global
+      },
+    )
+    .await
+    .unwrap_err();
+    let err = err.downcast_ref::<InstallError>().unwrap();
+    assert_eq!(err.kind, InstallErrorKind::AlreadyInstalled);
+    assert_eq!(err.exit_code, 1);
+  }
+
+  #[tokio::test]
+  async fn install_index() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    let index_path = bin_dir.join(INSTALL_INDEX_FILE_NAME);
+    let read_index = || {
+      serde_json::from_str::<InstallIndex>(&index_path.read_to_string())
+        .unwrap()
+        .installations
+        .into_iter()
+        .map(|metadata| metadata.name)
+        .collect::<Vec<_>>()
+    };
+
+    for name in ["echo_a", "echo_b"] {
+      create_install_shim(
+        &HttpClientProvider::new(None, None),
+        &Flags::default(),
+        InstallFlagsGlobal {
+          module_url: "http://localhost:4545/echo_server.ts".to_string(),
+          name: Some(name.to_string()),
+          root: Some(temp_dir.path().to_string()),
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap();
+    }
+    assert_eq!(read_index(), vec!["echo_a", "echo_b"]);
+
+    assert!(
+      remove_installation(&RealInstallerFs, bin_dir.as_path(), "echo_a")
+        .unwrap()
+    );
+    assert_eq!(read_index(), vec!["echo_b"]);
+
+    // listing reads the index rather than the metadata files
+    let mut index =
+      serde_json::from_str::<InstallIndex>(&index_path.read_to_string())
+        .unwrap();
+    index.installations[0].module_url =
+      "https://example.com/from_index.ts".to_string();
+    index_path.write(serde_json::to_string(&index).unwrap());
+    let installed = read_installed_metadata(bin_dir.as_path()).unwrap();
+    assert_eq!(installed[0].module_url, "https://example.com/from_index.ts");
+
+    // an edited metadata file is read again
+    let metadata_path =
+      get_metadata_path(&executable_path(bin_dir.as_path(), "echo_b"));
+    let mut metadata: InstallMetadata =
+      serde_json::from_str(&metadata_path.read_to_string()).unwrap();
+    metadata.module_url = "https://example.com/edited.ts".to_string();
+    metadata_path.write(serde_json::to_string_pretty(&metadata).unwrap());
+    let installed = read_installed_metadata(bin_dir.as_path()).unwrap();
+    assert_eq!(installed[0].module_url, "https://example.com/edited.ts");
+
+    // a stale index without stamps is rebuilt
+    index_path.write(
+      serde_json::to_string(&InstallIndex {
+        installations: vec![],
+        ..Default::default()
+      })
+      .unwrap(),
+    );
+    assert_eq!(read_installed_metadata(bin_dir.as_path()).unwrap().len(), 1);
+    assert_eq!(read_index(), vec!["echo_b"]);
+
+    // a missing index is rebuilt
+    index_path.remove_file();
+    let installed = read_installed_metadata(bin_dir.as_path()).unwrap();
+    assert_eq!(installed[0].module_url, "https://example.com/edited.ts");
+    assert_eq!(read_index(), vec!["echo_b"]);
+  }
+
+  #[tokio::test]
+  async fn install_normalizes_allow_net() {
+    let shim_data = resolve_shim_data(
       &HttpClientProvider::new(None, None),
       &Flags {
-        unstable_config: UnstableConfig {
-          legacy_flag_enabled: true,
+        permissions: PermissionFlags {
+          allow_net: Some(vec![
+            "example.com:443".to_string(),
+            "https://x".to_string(),
+          ]),
           ..Default::default()
         },
         ..Flags::default()
       },
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(env::temp_dir().to_string_lossy().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    assert!(shim_data
+      .args
+      .contains(&"--allow-net=example.com:443,x".to_string()));
+
+    let (host, warning) = normalize_net_host("https://x");
+    assert_eq!(host, "x");
+    assert_eq!(
+      warning.unwrap(),
+      "--allow-net expects host[:port] entries, using x for https://x"
+    );
+    assert_eq!(
+      normalize_net_host("wss://example.com:8443/socket").0,
+      "example.com:8443"
+    );
+    assert_eq!(
+      normalize_net_host("example.com:443"),
+      ("example.com:443".to_string(), None)
+    );
+    assert!(normalize_net_host("example.com/path").1.is_some());
+  }
+
+  // the environment must not change while installing
+  #[allow(clippy::await_holding_lock)]
+  #[tokio::test]
+  async fn install_verify_run_failure() {
+    let _guard = ENV_LOCK.lock().unwrap();
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+    let module_path = temp_dir.path().join("broken.ts");
+    module_path.write("console.error('broken tool');\nDeno.exit(3);\n");
+
+    // use the deno binary in the target directory
+    let original_path = env::var_os("PATH");
+    env::set_var("PATH", test_util::target_dir());
+    let result = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
       InstallFlagsGlobal {
+        module_url: module_path.to_string(),
+        name: Some("broken".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        verify_run: true,
+        ..Default::default()
+      },
+    )
+    .await;
+    match original_path {
+      Some(path) => env::set_var("PATH", path),
+      None => env::remove_var("PATH"),
+    }
+
+    let err = format!("{:#}", result.unwrap_err());
+    assert!(err.starts_with("Verifying broken failed"), "{err}");
+    assert!(err.contains("exited with"), "{err}");
+    assert!(err.contains("broken tool"), "{err}");
+    assert!(!bin_dir.join("broken").exists());
+    assert!(!bin_dir.join("broken.cmd").exists());
+  }
+
+  #[tokio::test]
+  async fn install_relocatable() {
+    let temp_dir = TempDir::new();
+    let config_path = temp_dir.path().join("deno.json");
+    config_path.write("{}");
+
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        config_flag: ConfigFlag::Path(config_path.to_string()),
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
         module_url: "http://localhost:4545/echo_server.ts".to_string(),
-        args: vec![],
         name: Some("echo_test".to_string()),
         root: Some(temp_dir.path().to_string()),
-        force: false,
+        relocatable: true,
+        ..Default::default()
       },
     )
     .await
     .unwrap();
-
-    let mut file_path = bin_dir.join("echo_test");
+    let config_copy =
+      get_hidden_file_with_ext(&shim_data.file_path, "deno.json");
+    assert!(shim_data
+      .args
+      .contains(&config_copy.to_string_lossy().to_string()));
+
+    let files = render_executable_files(&shim_data, &shim_data.file_path);
+    let installation_dir = shim_data.installation_dir.to_string_lossy();
     if cfg!(windows) {
-      file_path = file_path.with_extension("cmd");
+      let cmd_content = &files[0].1;
+      assert!(cmd_content.contains("\"%~dp0.echo_test.deno.json\""));
+      assert!(!cmd_content.contains(installation_dir.as_ref()));
     }
+    let sh_content = &files.last().unwrap().1;
+    assert!(sh_content.contains("$(dirname \"$0\")"));
+    assert!(sh_content.contains("/.echo_test.deno.json"));
+    assert!(!sh_content.contains(installation_dir.as_ref()));
+  }
 
-    assert!(file_path.exists());
+  #[cfg(windows)]
+  #[tokio::test]
+  async fn install_lowercase_windows_name() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
 
-    let content = fs::read_to_string(file_path).unwrap();
-    if cfg!(windows) {
-      assert!(content.contains(
-        r#""run" "--unstable" "--no-config" "http://localhost:4545/echo_server.ts""#
-      ));
-    } else {
-      assert!(content.contains(
-        r#"run --unstable --no-config 'http://localhost:4545/echo_server.ts'"#
-      ));
+    let installed = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("MyTool".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        lowercase_windows_name: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    // the file system is case-insensitive, so compare the listed names
+    let file_names = fs::read_dir(&bin_dir)
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+      .collect::<Vec<_>>();
+    assert!(file_names.contains(&"mytool.cmd".to_string()));
+    assert!(file_names.contains(&"mytool".to_string()));
+    assert!(!file_names.contains(&"MyTool.cmd".to_string()));
+    assert_eq!(installed.name, "MyTool");
+    assert_eq!(read_metadata(&installed.file_path).unwrap().name, "MyTool");
+  }
+
+  /// Validates `value` against the subset of JSON Schema used by the
+  /// installer's schemas, returning the first mismatch.
+  fn validate_json_schema(
+    schema: &serde_json::Value,
+    value: &serde_json::Value,
+    path: &str,
+  ) -> Result<(), String> {
+    if let Some(expected) = schema.get("type").and_then(|t| t.as_str()) {
+      let actual = match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+      };
+      if actual != expected {
+        return Err(format!("{path}: expected {expected}, got {actual}"));
+      }
+    }
+    if let Some(variants) = schema.get("enum").and_then(|e| e.as_array()) {
+      if !variants.contains(value) {
+        return Err(format!("{path}: {value} is not one of {variants:?}"));
+      }
+    }
+    if let Some(items) = schema.get("items") {
+      for (i, item) in value.as_array().into_iter().flatten().enumerate() {
+        validate_json_schema(items, item, &format!("{path}[{i}]"))?;
+      }
+    }
+    if let Some(object) = value.as_object() {
+      let properties = schema.get("properties").and_then(|p| p.as_object());
+      for required in schema
+        .get("required")
+        .and_then(|r| r.as_array())
+        .into_iter()
+        .flatten()
+      {
+        let required = required.as_str().unwrap();
+        if !object.contains_key(required) {
+          return Err(format!("{path}: missing {required}"));
+        }
+      }
+      for (key, value) in object {
+        match properties.and_then(|properties| properties.get(key)) {
+          Some(property) => {
+            validate_json_schema(property, value, &format!("{path}.{key}"))?
+          }
+          None
+            if schema.get("additionalProperties")
+              == Some(&serde_json::Value::Bool(false)) =>
+          {
+            return Err(format!("{path}: unexpected property {key}"));
+          }
+          None => {}
+        }
+      }
     }
+    Ok(())
+  }
+
+  #[test]
+  fn install_metadata_matches_json_schema() {
+    let schema: serde_json::Value =
+      serde_json::from_str(INSTALL_METADATA_SCHEMA).unwrap();
+    // every field is set so that a field missing from the schema is caught
+    let metadata = InstallMetadata {
+      name: "file_server".to_string(),
+      module_url: "jsr:@std/http@1.0.0/file-server".to_string(),
+      args: vec!["--port".to_string(), "8080".to_string()],
+      aliases: vec!["serve".to_string()],
+      min_deno_version: Some("1.40.0".to_string()),
+      pre_run: Some("echo starting".to_string()),
+      working_dir: Some("/srv".to_string()),
+      companions: vec![CompanionSource {
+        path: PathBuf::from("/root/.deno/bin/.file_server.import_map.json"),
+        source: "https://example.com/import_map.json".to_string(),
+        import_map_overlay: Some("{}".to_string()),
+      }],
+      env: vec!["PORT=8080".to_string()],
+      wrapper_comment: Some("installed by provisioning".to_string()),
+      registry: Some(RegistrySource {
+        kind: RegistryKind::Jsr,
+        package: "@std/http".to_string(),
+        version: "1.0.0".to_string(),
+      }),
+      etag: Some("33a64df551425fcc55e".to_string()),
+      last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string()),
+      signature: Some(VerifiedSignature {
+        public_key: "cHVibGljIGtleQ==".to_string(),
+        signature_url: "https://example.com/file_server.ts.sig".to_string(),
+      }),
+      last_verified: Some("2024-05-01T12:00:00.000Z".to_string()),
+      desktop_entry: Some(
+        "/root/.local/share/applications/file_server.desktop".to_string(),
+      ),
+      shorthand: Some("gh:denoland/std/http/file_server.ts@1.0.0".to_string()),
+      installed_at: Some("2024-04-01T12:00:00.000Z".to_string()),
+      wrappers: vec!["file_server.cmd".to_string(), "file_server".to_string()],
+      template: Some("/home/user/templates/wrapper.sh".to_string()),
+      deno_flags: vec![
+        "--allow-net".to_string(),
+        "--config=/home/user/deno.json".to_string(),
+        "--no-check".to_string(),
+      ],
+    };
+    let value = serde_json::to_value(&metadata).unwrap();
+    assert_eq!(validate_json_schema(&schema, &value, "$"), Ok(()));
+
+    let minimal = serde_json::to_value(InstallMetadata {
+      name: "fmt".to_string(),
+      module_url: "https://example.com/fmt.ts".to_string(),
+      ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(validate_json_schema(&schema, &minimal, "$"), Ok(()));
+
+    let invalid = serde_json::json!({ "name": "fmt", "args": "--check" });
+    assert!(validate_json_schema(&schema, &invalid, "$").is_err());
   }
 
   #[tokio::test]
-  async fn install_inferred_name() {
-    let shim_data = resolve_shim_data(
+  async fn install_unsupported_scheme() {
+    let temp_dir = TempDir::new();
+
+    let err = create_install_shim(
       &HttpClientProvider::new(None, None),
       &Flags::default(),
-      &InstallFlagsGlobal {
+      InstallFlagsGlobal {
+        module_url: "blob:https://example.com/a1b2c3".to_string(),
+        name: Some("blob_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Unsupported scheme \"blob\" of blob:https://example.com/a1b2c3. Supported schemes are: file, http, https, npm, jsr, data"
+    );
+    assert_eq!(
+      err.downcast_ref::<InstallError>().unwrap().kind,
+      InstallErrorKind::InvalidModuleUrl
+    );
+    assert!(!temp_dir.path().join("bin").exists());
+  }
+
+  #[tokio::test]
+  async fn reinstall_outdated_templates_regenerates_old_wrappers() {
+    let temp_dir = TempDir::new();
+    let bin_dir = temp_dir.path().join("bin");
+
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      InstallFlagsGlobal {
         module_url: "http://localhost:4545/echo_server.ts".to_string(),
-        args: vec![],
-        name: None,
-        root: Some(env::temp_dir().to_string_lossy().to_string()),
-        force: false,
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        args: vec!["--foo".to_string()],
+        env: vec!["FORMATTER=deno fmt".to_string()],
+        wrapper_comment: Some("installed by provisioning".to_string()),
+        ..Default::default()
       },
     )
     .await
     .unwrap();
 
-    assert_eq!(shim_data.name, "echo_server");
+    let (file_path, old_template) = if cfg!(windows) {
+      (bin_dir.join("echo_test.cmd"), ("@echo off\n", ""))
+    } else {
+      (bin_dir.join("echo_test"), ("exec deno ", "deno "))
+    };
+    let current = file_path.read_to_string();
+    assert!(current.contains(old_template.0));
+    file_path.write(current.replace(old_template.0, old_template.1));
+
+    let reinstalled =
+      reinstall_outdated_templates(&RealInstallerFs, bin_dir.as_path())
+        .unwrap();
+    assert_eq!(reinstalled, vec!["echo_test".to_string()]);
+    assert_eq!(file_path.read_to_string(), current);
+
+    let reinstalled =
+      reinstall_outdated_templates(&RealInstallerFs, bin_dir.as_path())
+        .unwrap();
+    assert!(reinstalled.is_empty());
+  }
+
+  #[test]
+  #[cfg(not(windows))]
+  fn repoint_wrapper_args_unquotes_paths() {
+    let installation_dir = Path::new("/new root/bin");
+    let file_names =
+      [OsStr::new(".tool.deno.json"), OsStr::new(".tool.grants")];
+    let args = "run '--config' '/old root/bin/.tool.deno.json' '--allow-write=/tmp,/old root/bin/.tool.grants' '/old root/bin/other.json'";
     assert_eq!(
-      shim_data.args,
-      vec!["run", "--no-config", "http://localhost:4545/echo_server.ts",]
+      repoint_wrapper_args(
+        args,
+        WrapperArgQuoting::Sh,
+        installation_dir,
+        &file_names
+      ),
+      "run '--config' '/new root/bin/.tool.deno.json' '--allow-write=/tmp,/new root/bin/.tool.grants' '/old root/bin/other.json'"
+    );
+    assert_eq!(
+      repoint_wrapper_args(
+        "\"run\" \"--config\" \"/old 100%% root/bin/.tool.deno.json\"",
+        WrapperArgQuoting::Cmd,
+        installation_dir,
+        &file_names
+      ),
+      "\"run\" \"--config\" \"/new root/bin/.tool.deno.json\""
     );
   }
 
+  #[test]
+  fn split_wrapper_invocation_skips_quoted_deno() {
+    let contents = "#!/bin/sh\necho 'deno is here' \"$@\"\nTOOL='deno fmt' CMD='it'\\''s deno ' exec deno run '--foo' \"$@\"\n";
+    let (before, args, after) = split_wrapper_invocation(contents).unwrap();
+    assert!(before.ends_with("exec deno "));
+    assert_eq!(args, "run '--foo'");
+    assert_eq!(after, " \"$@\"\n");
+
+    let contents = "@echo off\n@set \"TOOL=deno fmt\"\n@deno \"run\" %*\n";
+    let (before, args, _) = split_wrapper_invocation(contents).unwrap();
+    assert!(before.ends_with("\n@deno "));
+    assert_eq!(args, "\"run\"");
+  }
+
+  #[test]
+  fn resolve_missing_name_fails_when_not_interactive() {
+    let mut output = Vec::new();
+    let err = resolve_missing_name(
+      false,
+      &mut io::Cursor::new("echo_test\n"),
+      &mut output,
+    )
+    .unwrap_err();
+    assert!(err
+      .to_string()
+      .starts_with("An executable name was not provided"));
+    assert!(output.is_empty());
+  }
+
+  #[test]
+  fn resolve_missing_name_reads_name_interactively() {
+    let mut output = Vec::new();
+    let name = resolve_missing_name(
+      true,
+      &mut io::Cursor::new("../escape\n\n  echo_test  \n"),
+      &mut output,
+    )
+    .unwrap();
+    assert_eq!(name, "echo_test");
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(output.matches("Executable name: ").count(), 3);
+    assert!(output.contains("Invalid executable name: ../escape"));
+
+    let err =
+      resolve_missing_name(true, &mut io::Cursor::new(""), &mut Vec::new())
+        .unwrap_err();
+    assert!(err
+      .to_string()
+      .starts_with("An executable name was not provided"));
+  }
+
   #[tokio::test]
-  async fn install_unstable_legacy() {
+  async fn install_keep_extension() {
+    let temp_dir = TempDir::new();
+    temp_dir.write("server.ts", "console.log('hello');");
+
     let shim_data = resolve_shim_data(
       &HttpClientProvider::new(None, None),
-      &Flags {
-        unstable_config: UnstableConfig {
-          legacy_flag_enabled: true,
-          ..Default::default()
-        },
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        module_url: temp_dir.path().join("server.ts").to_string(),
+        root: Some(temp_dir.path().to_string()),
+        keep_extension: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let bin_dir = temp_dir.path().join("bin");
+    if cfg!(windows) {
+      assert_eq!(shim_data.name, "server");
+    } else {
+      assert_eq!(shim_data.name, "server.ts");
+      assert_eq!(shim_data.file_path, bin_dir.join("server.ts").to_path_buf());
+      assert_eq!(
+        get_metadata_path(&shim_data.file_path),
+        bin_dir.join(".server.ts.install.json").to_path_buf()
+      );
+    }
+
+    assert!(validate_name_with_extension("server.ts").is_ok());
+    assert!(validate_name_with_extension("server").is_ok());
+    assert!(validate_name_with_extension("server.").is_err());
+    assert!(validate_name_with_extension(".ts").is_err());
+  }
+
+  #[tokio::test]
+  async fn export_archive_contains_manifest_scripts() {
+    let temp_dir = TempDir::new();
+    let entries = vec![
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
         ..Default::default()
       },
-      &InstallFlagsGlobal {
-        module_url: "http://localhost:4545/echo_server.ts".to_string(),
-        args: vec![],
-        name: None,
-        root: Some(env::temp_dir().to_string_lossy().to_string()),
-        force: false,
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(),
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
       },
+    ];
+
+    let mut archive = Vec::new();
+    export_archive(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      entries,
+      &mut archive,
+      ArchiveFormat::Tar,
     )
     .await
     .unwrap();
 
-    assert_eq!(shim_data.name, "echo_server");
+    let mut paths = tar::Archive::new(archive.as_slice())
+      .entries()
+      .unwrap()
+      .map(|entry| entry.unwrap().path().unwrap().to_string_lossy().to_string())
+      .collect::<Vec<_>>();
+    paths.sort();
+    let mut expected = vec![
+      "bin/.cat.install.json".to_string(),
+      "bin/.echo_test.install.json".to_string(),
+      "bin/cat".to_string(),
+      "bin/echo_test".to_string(),
+    ];
+    if cfg!(windows) {
+      expected.push("bin/cat.cmd".to_string());
+      expected.push("bin/echo_test.cmd".to_string());
+      expected.sort();
+    }
+    assert_eq!(paths, expected);
+    assert!(!temp_dir.path().join("bin").exists());
+
     assert_eq!(
-      shim_data.args,
-      vec![
-        "run",
-        "--unstable",
-        "--no-config",
-        "http://localhost:4545/echo_server.ts",
-      ]
+      ArchiveFormat::from_path(Path::new("tools.tgz")).unwrap(),
+      ArchiveFormat::TarGz
     );
+    assert!(ArchiveFormat::from_path(Path::new("tools.zip")).is_err());
   }
 
   #[tokio::test]
-  async fn install_unstable_features() {
-    let shim_data = resolve_shim_data(
+  async fn install_force_dry_run_diff() {
+    let temp_dir = TempDir::new();
+    create_install_shim(
       &HttpClientProvider::new(None, None),
-      &Flags {
-        unstable_config: UnstableConfig {
-          features: vec!["kv".to_string(), "cron".to_string()],
-          ..Default::default()
-        },
-        ..Default::default()
-      },
-      &InstallFlagsGlobal {
+      &Flags::default(),
+      InstallFlagsGlobal {
         module_url: "http://localhost:4545/echo_server.ts".to_string(),
-        args: vec![],
-        name: None,
-        root: Some(env::temp_dir().to_string_lossy().to_string()),
-        force: false,
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
       },
     )
     .await
     .unwrap();
 
-    assert_eq!(shim_data.name, "echo_server");
-    assert_eq!(
-      shim_data.args,
-      vec![
-        "run",
-        "--unstable-kv",
-        "--unstable-cron",
-        "--no-config",
-        "http://localhost:4545/echo_server.ts",
-      ]
-    );
-  }
-
-  #[tokio::test]
-  async fn install_inferred_name_from_parent() {
     let shim_data = resolve_shim_data(
       &HttpClientProvider::new(None, None),
       &Flags::default(),
       &InstallFlagsGlobal {
-        module_url: "http://localhost:4545/subdir/main.ts".to_string(),
-        args: vec![],
-        name: None,
-        root: Some(env::temp_dir().to_string_lossy().to_string()),
-        force: false,
+        module_url: "http://localhost:4545/cat.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        force: true,
+        dry_run: true,
+        ..Default::default()
       },
     )
     .await
     .unwrap();
 
-    assert_eq!(shim_data.name, "subdir");
-    assert_eq!(
-      shim_data.args,
-      vec!["run", "--no-config", "http://localhost:4545/subdir/main.ts",]
-    );
-  }
-
-  #[tokio::test]
-  async fn install_inferred_name_after_redirect_for_no_path_url() {
-    let _http_server_guard = test_util::http_server();
-    let shim_data = resolve_shim_data(
+    let output =
+      test_util::strip_ansi_codes(&render_dry_run_diff(&shim_data).unwrap())
+        .to_string();
+    let removed = output
+      .lines()
+      .find(|line| line.contains("| -"))
+      .expect("a removed line");
+    assert!(removed.contains("http://localhost:4545/echo_server.ts"));
+    let added = output
+      .lines()
+      .find(|line| line.contains("| +"))
+      .expect("an added line");
+    assert!(added.contains("http://localhost:4545/cat.ts"));
+
+    let unchanged = resolve_shim_data(
       &HttpClientProvider::new(None, None),
       &Flags::default(),
       &InstallFlagsGlobal {
-        module_url: "http://localhost:4550/?redirect_to=/subdir/redirects/a.ts"
-          .to_string(),
-        args: vec![],
-        name: None,
-        root: Some(env::temp_dir().to_string_lossy().to_string()),
-        force: false,
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        force: true,
+        dry_run: true,
+        ..Default::default()
       },
     )
     .await
     .unwrap();
+    let output = render_dry_run_diff(&unchanged).unwrap();
+    assert!(!output.contains("| -"));
+    assert!(output.contains("is unchanged"));
+  }
 
-    assert_eq!(shim_data.name, "a");
+  #[test]
+  fn installation_dir_bin_override() {
+    let temp_dir = TempDir::new();
+    let root = temp_dir.path().as_path();
     assert_eq!(
-      shim_data.args,
-      vec![
-        "run",
-        "--no-config",
-        "http://localhost:4550/?redirect_to=/subdir/redirects/a.ts",
-      ]
+      installation_dir_with_bin(root, None).unwrap(),
+      root.join("bin")
+    );
+    assert_eq!(
+      installation_dir_with_bin(root, Some("")).unwrap(),
+      root.join("bin")
+    );
+    assert_eq!(
+      installation_dir_with_bin(root, Some("scripts")).unwrap(),
+      root.join("scripts")
     );
+    for bin in ["scripts/bin", "..", "/usr/bin", "."] {
+      assert!(installation_dir_with_bin(root, Some(bin)).is_err(), "{bin}");
+    }
   }
 
   #[tokio::test]
-  async fn install_custom_dir_option() {
-    let shim_data = resolve_shim_data(
+  async fn touch_updates_only_the_timestamp() {
+    let temp_dir = TempDir::new();
+    let installed = create_install_shim(
       &HttpClientProvider::new(None, None),
       &Flags::default(),
-      &InstallFlagsGlobal {
+      InstallFlagsGlobal {
         module_url: "http://localhost:4545/echo_server.ts".to_string(),
-        args: vec![],
         name: Some("echo_test".to_string()),
-        root: Some(env::temp_dir().to_string_lossy().to_string()),
-        force: false,
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
       },
     )
     .await
     .unwrap();
+    let wrapper = fs::read_to_string(&installed.file_path).unwrap();
+    let mut metadata = read_metadata(&installed.file_path).unwrap();
+    assert_eq!(metadata.last_verified, None);
+
+    let old = "2000-01-01T00:00:00.000Z";
+    metadata.last_verified = Some(old.to_string());
+    fs::write(
+      get_metadata_path(&installed.file_path),
+      serde_json::to_string_pretty(&metadata).unwrap(),
+    )
+    .unwrap();
 
-    assert_eq!(shim_data.name, "echo_test");
+    touch("echo_test", Some(temp_dir.path().to_path_buf())).unwrap();
+    let touched = read_metadata(&installed.file_path).unwrap();
+    let parse =
+      |time: &str| chrono::DateTime::parse_from_rfc3339(time).unwrap();
+    assert!(parse(touched.last_verified.as_ref().unwrap()) > parse(old));
     assert_eq!(
-      shim_data.args,
-      vec!["run", "--no-config", "http://localhost:4545/echo_server.ts",]
+      InstallMetadata {
+        last_verified: None,
+        ..touched
+      },
+      InstallMetadata {
+        last_verified: None,
+        ..metadata
+      }
     );
+    assert_eq!(fs::read_to_string(&installed.file_path).unwrap(), wrapper);
+
+    fs::remove_file(&installed.file_path).unwrap();
+    assert!(touch("echo_test", Some(temp_dir.path().to_path_buf())).is_err());
+    assert!(touch("missing", Some(temp_dir.path().to_path_buf())).is_err());
   }
 
   #[tokio::test]
-  async fn install_with_flags() {
+  async fn install_npm_forwards_allow_scripts() {
+    let temp_dir = TempDir::new();
     let shim_data = resolve_shim_data(
       &HttpClientProvider::new(None, None),
       &Flags {
-        permissions: PermissionFlags {
-          allow_net: Some(vec![]),
-          allow_read: Some(vec![]),
-          ..Default::default()
-        },
-        type_check_mode: TypeCheckMode::None,
-        log_level: Some(Level::Error),
+        node_modules_dir: Some(true),
+        allow_scripts: PackagesAllowedScripts::Some(vec![
+          "npm:cowsay".to_string(),
+          "npm:esbuild".to_string(),
+        ]),
         ..Flags::default()
       },
       &InstallFlagsGlobal {
-        module_url: "http://localhost:4545/echo_server.ts".to_string(),
-        args: vec!["--foobar".to_string()],
-        name: Some("echo_test".to_string()),
-        root: Some(env::temp_dir().to_string_lossy().to_string()),
-        force: false,
+        module_url: "npm:cowsay@1.5.0".to_string(),
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
       },
     )
     .await
     .unwrap();
+    assert!(shim_data
+      .args
+      .contains(&"--allow-scripts=npm:cowsay,npm:esbuild".to_string()));
+    assert!(shim_data
+      .args
+      .contains(&"--node-modules-dir=true".to_string()));
 
-    assert_eq!(shim_data.name, "echo_test");
-    assert_eq!(
-      shim_data.args,
-      vec![
-        "run",
-        "--allow-read",
-        "--allow-net",
-        "--quiet",
-        "--no-config",
-        "http://localhost:4545/echo_server.ts",
-        "--foobar",
-      ]
-    );
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags {
+        allow_scripts: PackagesAllowedScripts::All,
+        ..Flags::default()
+      },
+      &InstallFlagsGlobal {
+        module_url: "npm:cowsay@1.5.0".to_string(),
+        root: Some(temp_dir.path().to_string()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    assert!(shim_data.args.contains(&"--allow-scripts".to_string()));
+    assert!(!shim_data
+      .args
+      .iter()
+      .any(|arg| arg.starts_with("--node-modules-dir")));
   }
 
   #[tokio::test]
-  async fn install_prompt() {
-    let shim_data = resolve_shim_data(
+  async fn repair_moved_root() {
+    let temp_dir = TempDir::new();
+    let config_file_path = temp_dir.path().join("deno.json");
+    config_file_path.write("{}");
+    let old_root = temp_dir.path().join("old root");
+
+    let installed = create_install_shim(
       &HttpClientProvider::new(None, None),
       &Flags {
-        permissions: PermissionFlags {
-          no_prompt: true,
-          ..Default::default()
-        },
+        config_flag: ConfigFlag::Path(config_file_path.to_string()),
         ..Flags::default()
       },
-      &InstallFlagsGlobal {
-        module_url: "http://localhost:4545/echo_server.ts".to_string(),
-        args: vec![],
+      InstallFlagsGlobal {
+        module_url: "http://localhost:4545/cat.ts".to_string(),
         name: Some("echo_test".to_string()),
-        root: Some(env::temp_dir().to_string_lossy().to_string()),
-        force: false,
+        root: Some(old_root.to_string()),
+        grant_prompt_once: true,
+        ..Default::default()
       },
     )
     .await
     .unwrap();
+    let old_config_path =
+      get_hidden_file_with_ext(&installed.file_path, "deno.json");
+    assert!(fs::read_to_string(&installed.file_path)
+      .unwrap()
+      .contains(&old_config_path.to_string_lossy().to_string()));
+
+    // the new location needs quoting, so the paths can't be swapped in place
+    let new_root = temp_dir.path().join("new 'root");
+    fs::rename(&old_root, &new_root).unwrap();
+    let bin_dir = new_root.join("bin");
+    let file_path = executable_path(bin_dir.as_path(), "echo_test");
+    let new_config_path = get_hidden_file_with_ext(&file_path, "deno.json");
+    let state_path = get_hidden_file_with_ext(&file_path, "grants");
 
     assert_eq!(
-      shim_data.args,
-      vec![
-        "run",
-        "--no-prompt",
-        "--no-config",
-        "http://localhost:4545/echo_server.ts",
-      ]
+      repair(&RealInstallerFs, bin_dir.as_path()).unwrap(),
+      vec!["echo_test"]
+    );
+    let executable = fs::read_to_string(&file_path).unwrap();
+    assert!(!executable.contains(&old_root.to_string_lossy().to_string()));
+    let is_cmd = cfg!(windows);
+    assert!(executable.contains(&render_script_arg(
+      &new_config_path.to_string_lossy(),
+      is_cmd
+    )));
+    assert!(executable.contains(&render_script_arg(
+      &format!("--allow-write={}", state_path.to_string_lossy()),
+      is_cmd
+    )));
+    let recorder =
+      fs::read_to_string(get_hidden_file_with_ext(&file_path, "grant.js"))
+        .unwrap();
+    assert!(!recorder.contains(&old_root.to_string_lossy().to_string()));
+    assert_eq!(
+      read_metadata(&file_path).unwrap().companions[0].path,
+      new_config_path
     );
+
+    assert!(repair(&RealInstallerFs, bin_dir.as_path())
+      .unwrap()
+      .is_empty());
   }
 
   #[tokio::test]
-  async fn install_allow_all() {
+  async fn install_name_from_arg() {
+    let temp_dir = TempDir::new();
     let shim_data = resolve_shim_data(
       &HttpClientProvider::new(None, None),
-      &Flags {
-        permissions: PermissionFlags {
-          allow_all: true,
-          ..Default::default()
-        },
-        ..Flags::default()
+      &Flags::default(),
+      &InstallFlagsGlobal {
+        module_url: "http://localhost:4545/tools/file_server/v1/mod.ts"
+          .to_string(),
+        root: Some(temp_dir.path().to_string()),
+        name_from_arg: Some(1),
+        ..Default::default()
       },
+    )
+    .await
+    .unwrap();
+    assert_eq!(shim_data.name, "file_server");
+
+    let url =
+      Url::parse("http://localhost:4545/tools/file_server/v1/mod.ts").unwrap();
+    assert_eq!(name_from_url_segment(&url, 0).unwrap(), "tools");
+    assert_eq!(name_from_url_segment(&url, 2).unwrap(), "v1");
+    assert!(name_from_url_segment(&url, 4).is_err());
+
+    // the segment must be a valid name
+    let err = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
       &InstallFlagsGlobal {
-        module_url: "http://localhost:4545/echo_server.ts".to_string(),
-        args: vec![],
-        name: Some("echo_test".to_string()),
-        root: Some(env::temp_dir().to_string_lossy().to_string()),
-        force: false,
+        module_url: "http://localhost:4545/tools/file_server/v1/mod.ts"
+          .to_string(),
+        root: Some(temp_dir.path().to_string()),
+        name_from_arg: Some(3),
+        ..Default::default()
       },
     )
     .await
+    .err()
     .unwrap();
+    assert_eq!(err.to_string(), "Invalid executable name: mod.ts");
+  }
 
-    assert_eq!(
-      shim_data.args,
-      vec![
-        "run",
-        "--allow-all",
-        "--no-config",
-        "http://localhost:4545/echo_server.ts",
-      ]
-    );
+  /// [`InstallerFs`] keeping the files in memory.
+  #[derive(Default)]
+  struct InMemoryInstallerFs {
+    files: std::sync::Mutex<std::collections::HashMap<PathBuf, String>>,
+  }
+
+  impl InstallerFs for InMemoryInstallerFs {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+      self
+        .files
+        .lock()
+        .unwrap()
+        .get(path)
+        .cloned()
+        .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()> {
+      let mut files = self.files.lock().unwrap();
+      files.insert(path.to_path_buf(), contents.to_string());
+      Ok(())
+    }
+
+    fn set_mode(&self, path: &Path, _mode: u32) -> io::Result<()> {
+      self.read_to_string(path).map(|_| ())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+      let mut files = self.files.lock().unwrap();
+      files
+        .remove(path)
+        .map(|_| ())
+        .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+      let mut files = self.files.lock().unwrap();
+      let contents = files
+        .remove(from)
+        .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+      files.insert(to.to_path_buf(), contents);
+      Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+      self.files.lock().unwrap().contains_key(path)
+    }
   }
 
   #[tokio::test]
-  async fn install_npm_lockfile_default() {
-    let temp_dir = canonicalize_path(&env::temp_dir()).unwrap();
+  async fn install_and_uninstall_in_memory() {
+    let temp_dir = TempDir::new();
+    let root = temp_dir.path().join("virtual");
     let shim_data = resolve_shim_data(
       &HttpClientProvider::new(None, None),
-      &Flags {
-        permissions: PermissionFlags {
-          allow_all: true,
-          ..Default::default()
-        },
-        ..Flags::default()
-      },
+      &Flags::default(),
       &InstallFlagsGlobal {
-        module_url: "npm:cowsay".to_string(),
-        args: vec![],
-        name: None,
-        root: Some(temp_dir.to_string_lossy().to_string()),
-        force: false,
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some("echo_test".to_string()),
+        root: Some(root.to_string()),
+        aliases: vec!["echo_alias".to_string()],
+        ..Default::default()
       },
     )
     .await
     .unwrap();
 
-    let lock_path = temp_dir.join("bin").join(".cowsay.lock.json");
-    assert_eq!(
-      shim_data.args,
-      vec![
-        "run",
-        "--allow-all",
-        "--no-config",
-        "--lock",
-        &lock_path.to_string_lossy(),
-        "npm:cowsay"
-      ]
+    let fs = InMemoryInstallerFs::default();
+    let created_files = write_installation_files(&fs, &shim_data).unwrap();
+    for (path, contents) in
+      render_executable_files(&shim_data, &shim_data.file_path)
+    {
+      assert!(created_files.contains(&path));
+      assert_eq!(fs.read_to_string(&path).unwrap(), contents);
+    }
+    assert!(fs
+      .read_to_string(&shim_data.file_path)
+      .unwrap()
+      .contains("http://localhost:4545/echo_server.ts"));
+    assert!(fs.exists(&get_metadata_path(&shim_data.file_path)));
+    assert!(!root.exists());
+
+    assert!(
+      remove_installation(&fs, &shim_data.installation_dir, "echo_test")
+        .unwrap()
     );
-    assert_eq!(shim_data.extra_files, vec![(lock_path, "{}".to_string())]);
+    assert!(fs.files.lock().unwrap().is_empty());
+    assert!(!root.exists());
+  }
+
+  #[test]
+  fn deno_on_path() {
+    let temp_dir = TempDir::new();
+    let problem =
+      deno_on_path_problem(Some(temp_dir.path().as_path().as_os_str()));
+    assert!(problem
+      .unwrap()
+      .starts_with("deno was not found on the PATH"));
+    assert!(deno_on_path_problem(None).is_some());
+
+    let path = env::join_paths([
+      temp_dir.path().to_path_buf(),
+      test_util::target_dir().to_path_buf(),
+    ])
+    .unwrap();
+    assert_eq!(deno_on_path_problem(Some(&path)), None);
   }
 
   #[tokio::test]
-  async fn install_npm_no_lock() {
-    let shim_data = resolve_shim_data(
-      &HttpClientProvider::new(None, None),
-      &Flags {
-        permissions: PermissionFlags {
-          allow_all: true,
+  async fn install_force_merge_args() {
+    let temp_dir = TempDir::new();
+    let file_path = get_installation_dir(temp_dir.path().as_path())
+      .unwrap()
+      .join(executable_path(Path::new(""), "echo_test"));
+    let installs: [(&[&str], bool, &[&str]); 3] = [
+      (&["--port", "8080"], false, &["--port", "8080"]),
+      (
+        &["--verbose", "8080"],
+        true,
+        &["--port", "8080", "--verbose"],
+      ),
+      (&["--quiet"], false, &["--quiet"]),
+    ];
+    for (args, merge_args, expected) in installs {
+      create_install_shim(
+        &HttpClientProvider::new(None, None),
+        &Flags::default(),
+        InstallFlagsGlobal {
+          module_url: "http://localhost:4545/echo_server.ts".to_string(),
+          args: args.iter().map(|arg| arg.to_string()).collect(),
+          name: Some("echo_test".to_string()),
+          root: Some(temp_dir.path().to_string()),
+          force: true,
+          merge_args,
           ..Default::default()
         },
-        no_lock: true,
-        ..Flags::default()
-      },
-      &InstallFlagsGlobal {
-        module_url: "npm:cowsay".to_string(),
-        args: vec![],
-        name: None,
-        root: Some(env::temp_dir().to_string_lossy().to_string()),
-        force: false,
-      },
-    )
-    .await
-    .unwrap();
+      )
+      .await
+      .unwrap();
+
+      assert_eq!(read_metadata(&file_path).unwrap().args, expected);
+      let content = fs::read_to_string(&file_path).unwrap();
+      for arg in expected {
+        assert_eq!(content.matches(arg).count(), 1);
+      }
+    }
+  }
+
+  #[test]
+  fn generated_markers() {
+    assert!(has_generated_marker("#!/bin/sh\n# deno-install v1\n"));
+    assert!(has_generated_marker("@echo off\n% deno-install v1 %\n"));
+    assert!(has_generated_marker(
+      "#!/bin/sh\n# generated by deno install\n"
+    ));
+    assert!(has_generated_marker(
+      "@echo off\n% generated by deno install %\n"
+    ));
+    assert!(!has_generated_marker("#!/bin/sh\nexec deno run main.ts\n"));
+
+    let temp_dir = TempDir::new();
+    let file_path = temp_dir.path().join("legacy");
+    file_path.write("#!/bin/sh\n# generated by deno install\n");
+    assert!(is_generated_executable(file_path.as_path()));
+  }
+
+  #[test]
+  fn print_env_root_source() {
+    let _guard = ENV_LOCK.lock().unwrap();
+    let temp_dir = TempDir::new();
+    let original = env::var_os("DENO_INSTALL_ROOT");
+    env::set_var("DENO_INSTALL_ROOT", temp_dir.path().as_path());
+    let env_output = render_install_env(None);
+    let flag_output = render_install_env(Some(&temp_dir.path().to_string()));
+    match original {
+      Some(value) => env::set_var("DENO_INSTALL_ROOT", value),
+      None => env::remove_var("DENO_INSTALL_ROOT"),
+    }
 
+    let env_output = env_output.unwrap();
+    assert!(env_output.contains("root source: DENO_INSTALL_ROOT\n"));
+    let root =
+      canonicalize_path_maybe_not_exists(temp_dir.path().as_path()).unwrap();
+    assert!(env_output.contains(&format!("root: {}\n", root.display())));
+    assert!(flag_output.unwrap().contains("root source: --root\n"));
+  }
+
+  #[tokio::test]
+  async fn install_pins_jsr_module() {
+    let _http_server_guard = test_util::http_server();
+    let temp_dir = TempDir::new();
+    let registry_urls = RegistryUrls {
+      jsr: Url::parse("http://localhost:4545/install/pin_jsr/").unwrap(),
+      ..Default::default()
+    };
+    let resolve = |module_url: &str, no_pin: bool| {
+      let install_flags_global = InstallFlagsGlobal {
+        module_url: module_url.to_string(),
+        name: Some("serve".to_string()),
+        root: Some(temp_dir.path().to_string()),
+        no_pin,
+        ..Default::default()
+      };
+      let registry_urls = &registry_urls;
+      async move {
+        resolve_shim_data_with_registries(
+          &HttpClientProvider::new(None, None),
+          &Flags::default(),
+          &install_flags_global,
+          registry_urls,
+        )
+        .await
+        .unwrap()
+      }
+    };
+
+    let shim_data = resolve("jsr:@std/http@^0.224.0/file-server", false).await;
     assert_eq!(
-      shim_data.args,
-      vec![
-        "run",
-        "--allow-all",
-        "--no-config",
-        "--no-lock",
-        "npm:cowsay"
-      ]
+      shim_data.metadata.module_url,
+      "jsr:@std/http@0.224.5/file-server"
     );
-    assert_eq!(shim_data.extra_files, vec![]);
+    assert_eq!(
+      shim_data.metadata.registry.as_ref().unwrap().version,
+      "0.224.5"
+    );
+    let (_, content) =
+      render_executable_files(&shim_data, &shim_data.file_path)
+        .pop()
+        .unwrap();
+    assert!(content.contains("jsr:@std/http@0.224.5/file-server"));
+
+    let shim_data = resolve("jsr:@std/http", false).await;
+    assert_eq!(shim_data.metadata.module_url, "jsr:@std/http@1.0.0");
+
+    let shim_data = resolve("jsr:@std/http@^0.224.0/file-server", true).await;
+    assert_eq!(
+      shim_data.metadata.module_url,
+      "jsr:@std/http@^0.224.0/file-server"
+    );
+    assert_eq!(shim_data.metadata.registry, None);
   }
 
   #[tokio::test]
-  async fn install_local_module() {
+  async fn install_dry_run_json_plan() {
     let temp_dir = TempDir::new();
-    let bin_dir = temp_dir.path().join("bin");
-    std::fs::create_dir(&bin_dir).unwrap();
-    let local_module = env::current_dir().unwrap().join("echo_server.ts");
-    let local_module_url = Url::from_file_path(&local_module).unwrap();
-    let local_module_str = local_module.to_string_lossy();
-
-    create_install_shim(
+    let installed = create_install_shim(
       &HttpClientProvider::new(None, None),
       &Flags::default(),
       InstallFlagsGlobal {
-        module_url: local_module_str.to_string(),
-        args: vec![],
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
         name: Some("echo_test".to_string()),
         root: Some(temp_dir.path().to_string()),
-        force: false,
+        ..Default::default()
       },
     )
     .await
     .unwrap();
+    let before = fs::read_to_string(&installed.file_path).unwrap();
+
+    let config_path = temp_dir.path().join("deno.json");
+    config_path.write("{}");
+    let flags = Flags {
+      config_flag: ConfigFlag::Path(config_path.to_string()),
+      ..Flags::default()
+    };
+    let install_flags_global = InstallFlagsGlobal {
+      module_url: "http://localhost:4545/cat.ts".to_string(),
+      args: vec!["--verbose".to_string()],
+      name: Some("echo_test".to_string()),
+      root: Some(temp_dir.path().to_string()),
+      force: true,
+      dry_run: true,
+      json: true,
+      ..Default::default()
+    };
+    let shim_data = resolve_shim_data(
+      &HttpClientProvider::new(None, None),
+      &flags,
+      &install_flags_global,
+    )
+    .await
+    .unwrap();
 
-    let mut file_path = bin_dir.join("echo_test");
-    if cfg!(windows) {
-      file_path = file_path.with_extension("cmd");
-    }
+    let plan = render_dry_run_plan(&shim_data);
+    assert_eq!(plan["name"], "echo_test");
+    assert_eq!(
+      plan["path"],
+      installed.file_path.to_string_lossy().to_string()
+    );
+    assert_eq!(plan["would_overwrite"], true);
+    let args = plan["args"].as_array().unwrap();
+    assert_eq!(args[args.len() - 2], "http://localhost:4545/cat.ts");
+    assert_eq!(args[args.len() - 1], "--verbose");
+    let config_copy_path =
+      get_hidden_file_with_ext(&installed.file_path, "deno.json");
+    assert_eq!(
+      plan["companions"],
+      serde_json::json!([config_copy_path.to_string_lossy()])
+    );
 
-    assert!(file_path.exists());
-    let content = fs::read_to_string(file_path).unwrap();
-    assert!(content.contains(&local_module_url.to_string()));
+    create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &flags,
+      install_flags_global,
+    )
+    .await
+    .unwrap();
+    assert_eq!(fs::read_to_string(&installed.file_path).unwrap(), before);
+    assert!(!config_copy_path.exists());
   }
 
   #[tokio::test]
-  async fn install_force() {
+  async fn install_companions_modes() {
     let temp_dir = TempDir::new();
-    let bin_dir = temp_dir.path().join("bin");
-    std::fs::create_dir(&bin_dir).unwrap();
-
-    create_install_shim(
+    let config_path = temp_dir.path().join("deno.json");
+    config_path.write("{}");
+    let flags = Flags {
+      config_flag: ConfigFlag::Path(config_path.to_string()),
+      ..Flags::default()
+    };
+    let install_flags_global = |companions_mode| InstallFlagsGlobal {
+      module_url: "http://localhost:4545/echo_server.ts".to_string(),
+      name: Some("echo_test".to_string()),
+      root: Some(temp_dir.path().to_string()),
+      force: true,
+      companions_mode,
+      ..Default::default()
+    };
+    let installed = create_install_shim(
       &HttpClientProvider::new(None, None),
-      &Flags::default(),
-      InstallFlagsGlobal {
-        module_url: "http://localhost:4545/echo_server.ts".to_string(),
-        args: vec![],
-        name: Some("echo_test".to_string()),
-        root: Some(temp_dir.path().to_string()),
-        force: false,
-      },
+      &flags,
+      install_flags_global(InstallCompanionsMode::Overwrite),
     )
     .await
     .unwrap();
+    let config_copy_path =
+      get_hidden_file_with_ext(&installed.file_path, "deno.json");
+    let edited = r#"{ "lock": false }"#;
 
-    let mut file_path = bin_dir.join("echo_test");
-    if cfg!(windows) {
-      file_path = file_path.with_extension("cmd");
-    }
-    assert!(file_path.exists());
+    fs::write(&config_copy_path, edited).unwrap();
+    fs::remove_file(&installed.file_path).unwrap();
+    let err = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &flags,
+      install_flags_global(InstallCompanionsMode::Error),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(
+      err.downcast_ref::<InstallError>().unwrap().kind,
+      InstallErrorKind::AlreadyInstalled
+    );
+    assert_eq!(fs::read_to_string(&config_copy_path).unwrap(), edited);
+    assert!(!installed.file_path.exists());
 
-    // No force. Install failed.
-    let no_force_result = create_install_shim(
+    let installed = create_install_shim(
       &HttpClientProvider::new(None, None),
-      &Flags::default(),
-      InstallFlagsGlobal {
-        module_url: "http://localhost:4545/cat.ts".to_string(), // using a different URL
-        args: vec![],
-        name: Some("echo_test".to_string()),
-        root: Some(temp_dir.path().to_string()),
-        force: false,
-      },
+      &flags,
+      install_flags_global(InstallCompanionsMode::Preserve),
     )
-    .await;
-    assert!(no_force_result.is_err());
-    assert!(no_force_result
-      .unwrap_err()
-      .to_string()
-      .contains("Existing installation found"));
-    // Assert not modified
-    let file_content = fs::read_to_string(&file_path).unwrap();
-    assert!(file_content.contains("echo_server.ts"));
+    .await
+    .unwrap();
+    assert_eq!(fs::read_to_string(&config_copy_path).unwrap(), edited);
+    assert!(installed.file_path.exists());
+    assert!(!installed.created_files.contains(&config_copy_path));
 
-    // Force. Install success.
-    let force_result = create_install_shim(
+    create_install_shim(
       &HttpClientProvider::new(None, None),
-      &Flags::default(),
-      InstallFlagsGlobal {
-        module_url: "http://localhost:4545/cat.ts".to_string(), // using a different URL
-        args: vec![],
-        name: Some("echo_test".to_string()),
-        root: Some(temp_dir.path().to_string()),
-        force: true,
-      },
+      &flags,
+      install_flags_global(InstallCompanionsMode::Overwrite),
     )
-    .await;
-    assert!(force_result.is_ok());
-    // Assert modified
-    let file_content_2 = fs::read_to_string(&file_path).unwrap();
-    assert!(file_content_2.contains("cat.ts"));
+    .await
+    .unwrap();
+    assert_eq!(fs::read_to_string(&config_copy_path).unwrap(), "{}");
   }
 
-  #[tokio::test]
-  async fn install_with_config() {
+  #[test]
+  fn desktop_entry() {
+    let entry = render_desktop_entry(
+      "my tool",
+      Path::new("/home/me/.deno/bin/my$tool"),
+      Some("/home/me/icons/tool.png"),
+    );
+    assert_eq!(
+      entry,
+      concat!(
+        "# deno-install v1\n",
+        "[Desktop Entry]\n",
+        "Type=Application\n",
+        "Name=my tool\n",
+        "Exec=\"/home/me/.deno/bin/my\\\\$tool\"\n",
+        "Terminal=false\n",
+        "Icon=/home/me/icons/tool.png\n",
+      )
+    );
+    assert!(has_generated_marker(&entry));
+    assert_eq!(escape_desktop_entry_value("a\nb\\c"), "a\\nb\\\\c");
+    assert_eq!(quote_desktop_exec_arg("100%"), "\"100%%\"");
+
     let temp_dir = TempDir::new();
-    let bin_dir = temp_dir.path().join("bin");
-    let config_file_path = temp_dir.path().join("test_tsconfig.json");
-    let config = "{}";
-    let mut config_file = File::create(&config_file_path).unwrap();
-    let result = config_file.write_all(config.as_bytes());
-    assert!(result.is_ok());
+    let home = temp_dir.path().join("home");
+    assert_eq!(
+      applications_dir(
+        Some("relative".as_ref()),
+        Some(home.as_path().as_os_str())
+      )
+      .unwrap(),
+      home.join(".local/share/applications").to_path_buf()
+    );
+    let data_home = temp_dir.path().join("data");
+    assert_eq!(
+      applications_dir(Some(data_home.as_path().as_os_str()), None).unwrap(),
+      data_home.join("applications").to_path_buf()
+    );
+    assert!(applications_dir(None, None).is_err());
+    data_home.create_dir_all();
+    data_home.join("applications").write("");
+    assert!(
+      applications_dir(Some(data_home.as_path().as_os_str()), None).is_err()
+    );
+  }
 
-    let result = create_install_shim(
+  #[cfg(windows)]
+  #[tokio::test]
+  async fn wrapper_variants_consistency() {
+    let temp_dir = TempDir::new();
+    let installed = create_install_shim(
       &HttpClientProvider::new(None, None),
-      &Flags {
-        config_flag: ConfigFlag::Path(config_file_path.to_string()),
-        ..Flags::default()
-      },
+      &Flags::default(),
       InstallFlagsGlobal {
-        module_url: "http://localhost:4545/cat.ts".to_string(),
-        args: vec![],
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        args: vec!["--port".to_string(), "100%".to_string()],
         name: Some("echo_test".to_string()),
         root: Some(temp_dir.path().to_string()),
-        force: true,
+        bat: true,
+        ..Default::default()
       },
     )
-    .await;
-    assert!(result.is_ok());
-
-    let config_file_name = ".echo_test.deno.json";
+    .await
+    .unwrap();
+    let installation_dir = installed.file_path.parent().unwrap();
+    assert_eq!(
+      wrapper_variant_problems(installation_dir, "echo_test").unwrap(),
+      Vec::<String>::new()
+    );
 
-    let file_path = bin_dir.join(config_file_name);
-    assert!(file_path.exists());
-    let content = fs::read_to_string(file_path).unwrap();
-    assert!(content == "{}");
+    let bat_path = installed.file_path.with_extension("bat");
+    fs::write(&bat_path, "@echo off\n@deno run main.ts %*\n").unwrap();
+    let sh_path = installed.file_path.with_extension("");
+    fs::remove_file(&sh_path).unwrap();
+    assert_eq!(
+      wrapper_variant_problems(installation_dir, "echo_test").unwrap(),
+      vec![
+        format!(
+          "{} doesn't forward the installed arguments",
+          bat_path.display()
+        ),
+        format!("{} is missing", sh_path.display()),
+        "The .cmd and .bat files of echo_test differ".to_string(),
+      ]
+    );
   }
-
-  // TODO: enable on Windows after fixing batch escaping
-  #[cfg(not(windows))]
+
   #[tokio::test]
-  async fn install_shell_escaping() {
+  async fn install_overwrite_if_older() {
     let temp_dir = TempDir::new();
-    let bin_dir = temp_dir.path().join("bin");
-    std::fs::create_dir(&bin_dir).unwrap();
+    let install = |module_url: &str| InstallFlagsGlobal {
+      module_url: module_url.to_string(),
+      name: Some("echo_test".to_string()),
+      root: Some(temp_dir.path().to_string()),
+      overwrite_if_older: Some(7 * 24 * 60 * 60),
+      ..Default::default()
+    };
+    let installed = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      install("http://localhost:4545/echo_server.ts"),
+    )
+    .await
+    .unwrap();
+    assert!(!installed.created_files.is_empty());
 
-    create_install_shim(
+    // a recent installation is kept
+    let skipped = create_install_shim(
       &HttpClientProvider::new(None, None),
       &Flags::default(),
-      InstallFlagsGlobal {
-        module_url: "http://localhost:4545/echo_server.ts".to_string(),
-        args: vec!["\"".to_string()],
-        name: Some("echo_test".to_string()),
-        root: Some(temp_dir.path().to_string()),
-        force: false,
-      },
+      install("http://localhost:4545/cat.ts"),
+    )
+    .await
+    .unwrap();
+    assert!(skipped.created_files.is_empty());
+    let content = fs::read_to_string(&installed.file_path).unwrap();
+    assert!(content.contains("echo_server.ts"));
+
+    let mut metadata = read_metadata(&installed.file_path).unwrap();
+    let old = chrono::Utc::now() - chrono::Duration::days(30);
+    metadata.installed_at =
+      Some(old.to_rfc3339_opts(chrono::SecondsFormat::Millis, true));
+    fs::write(
+      get_metadata_path(&installed.file_path),
+      serde_json::to_string_pretty(&metadata).unwrap(),
+    )
+    .unwrap();
+    let reinstalled = create_install_shim(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      install("http://localhost:4545/cat.ts"),
     )
     .await
     .unwrap();
+    assert!(!reinstalled.created_files.is_empty());
+    let content = fs::read_to_string(&installed.file_path).unwrap();
+    assert!(content.contains("cat.ts"));
+  }
 
-    let mut file_path = bin_dir.join("echo_test");
-    if cfg!(windows) {
-      file_path = file_path.with_extension("cmd");
-    }
+  #[tokio::test]
+  async fn install_strict_name() {
+    let temp_dir = TempDir::new();
+    let resolve = |name: &str, strict_name: bool| {
+      let install_flags_global = InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some(name.to_string()),
+        root: Some(temp_dir.path().to_string()),
+        strict_name,
+        ..Default::default()
+      };
+      async move {
+        resolve_shim_data(
+          &HttpClientProvider::new(None, None),
+          &Flags::default(),
+          &install_flags_global,
+        )
+        .await
+      }
+    };
 
-    assert!(file_path.exists());
-    let content = fs::read_to_string(file_path).unwrap();
-    if cfg!(windows) {
-      // TODO: see comment above this test
-    } else {
-      assert!(content.contains(
-        r#"run --no-config 'http://localhost:4545/echo_server.ts' '"'"#
-      ));
+    assert_eq!(resolve("my_tool", false).await.unwrap().name, "my_tool");
+    let err = resolve("my_tool", true).await.err().unwrap();
+    assert_eq!(
+      err.downcast_ref::<InstallError>().unwrap().kind,
+      InstallErrorKind::InvalidName
+    );
+    for name in ["MyTool", "1tool", "tool.ts"] {
+      assert!(resolve(name, true).await.is_err(), "{name}");
     }
+    assert_eq!(resolve("my-tool2", true).await.unwrap().name, "my-tool2");
   }
 
   #[tokio::test]
-  async fn install_unicode() {
-    let temp_dir = TempDir::new();
-    let bin_dir = temp_dir.path().join("bin");
-    std::fs::create_dir(&bin_dir).unwrap();
-    let unicode_dir = temp_dir.path().join("Magnús");
-    std::fs::create_dir(&unicode_dir).unwrap();
-    let local_module = unicode_dir.join("echo_server.ts");
-    let local_module_str = local_module.to_string_lossy();
-    std::fs::write(&local_module, "// Some JavaScript I guess").unwrap();
+  async fn install_github_shorthand() {
+    assert_eq!(
+      expand_github_shorthand("gh:denoland/deno_std/http/file_server.ts@0.224.0")
+        .unwrap(),
+      Some(
+        "https://raw.githubusercontent.com/denoland/deno_std/0.224.0/http/file_server.ts"
+          .to_string()
+      )
+    );
+    assert_eq!(
+      expand_github_shorthand("gh:owner/repo/src/cli.ts").unwrap(),
+      Some(
+        "https://raw.githubusercontent.com/owner/repo/HEAD/src/cli.ts"
+          .to_string()
+      )
+    );
+    assert_eq!(
+      expand_github_shorthand("https://example.com/cli.ts").unwrap(),
+      None
+    );
+    for specifier in ["gh:owner/repo", "gh:owner/cli.ts", "gh:owner/repo/@main"]
+    {
+      let err = expand_github_shorthand(specifier).unwrap_err();
+      assert_eq!(
+        err.downcast_ref::<InstallError>().unwrap().kind,
+        InstallErrorKind::InvalidModuleUrl,
+        "{specifier}"
+      );
+    }
 
-    create_install_shim(
+    let temp_dir = TempDir::new();
+    let shim_data = resolve_shim_data(
       &HttpClientProvider::new(None, None),
       &Flags::default(),
-      InstallFlagsGlobal {
-        module_url: local_module_str.to_string(),
-        args: vec![],
-        name: Some("echo_test".to_string()),
+      &InstallFlagsGlobal {
+        module_url: "gh:owner/repo/tools/fmt.ts@v1.2.0".to_string(),
         root: Some(temp_dir.path().to_string()),
-        force: false,
+        ..Default::default()
       },
     )
     .await
     .unwrap();
+    assert_eq!(shim_data.name, "fmt");
+    assert_eq!(
+      shim_data.metadata.module_url,
+      "https://raw.githubusercontent.com/owner/repo/v1.2.0/tools/fmt.ts"
+    );
+    assert_eq!(
+      shim_data.metadata.shorthand.as_deref(),
+      Some("gh:owner/repo/tools/fmt.ts@v1.2.0")
+    );
+  }
 
-    let mut file_path = bin_dir.join("echo_test");
-    if cfg!(windows) {
-      file_path = file_path.with_extension("cmd");
+  #[tokio::test]
+  async fn install_report_file() {
+    let temp_dir = TempDir::new();
+    let report_path = temp_dir.path().join("report.jsonl");
+    for name in ["tool_a", "tool_b"] {
+      create_install_shim(
+        &HttpClientProvider::new(None, None),
+        &Flags::default(),
+        InstallFlagsGlobal {
+          module_url: "http://localhost:4545/echo_server.ts".to_string(),
+          args: vec!["--port".to_string(), "8080".to_string()],
+          name: Some(name.to_string()),
+          root: Some(temp_dir.path().to_string()),
+          report_file: Some(report_path.to_string()),
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap();
     }
 
-    // We need to actually run it to make sure the URL is interpreted correctly
-    let status = Command::new(file_path)
-      .env_clear()
-      // use the deno binary in the target directory
-      .env("PATH", test_util::target_dir())
-      .spawn()
-      .unwrap()
-      .wait()
-      .unwrap();
-    assert!(status.success());
+    let report = fs::read_to_string(&report_path).unwrap();
+    let lines = report.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    for (line, name) in lines.into_iter().zip(["tool_a", "tool_b"]) {
+      let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+      assert_eq!(entry["action"], "install");
+      assert_eq!(entry["name"], name);
+      assert_eq!(entry["url"], "http://localhost:4545/echo_server.ts");
+      assert_eq!(entry["args"], serde_json::json!(["--port", "8080"]));
+      assert_eq!(entry["outcome"], "success");
+      assert!(entry["timestamp"].as_str().is_some());
+      assert!(entry.get("error").is_none());
+    }
   }
 
+  // the environment must not change while installing
+  #[allow(clippy::await_holding_lock)]
   #[tokio::test]
-  async fn install_with_import_map() {
+  async fn install_no_shadow() {
+    let _guard = ENV_LOCK.lock().unwrap();
     let temp_dir = TempDir::new();
-    let bin_dir = temp_dir.path().join("bin");
-    let import_map_path = temp_dir.path().join("import_map.json");
-    let import_map_url = Url::from_file_path(&import_map_path).unwrap();
-    let import_map = "{ \"imports\": {} }";
-    let mut import_map_file = File::create(&import_map_path).unwrap();
-    let result = import_map_file.write_all(import_map.as_bytes());
-    assert!(result.is_ok());
+    let system_dir = temp_dir.path().join("system");
+    system_dir.create_dir_all();
+    let fake_binary = if cfg!(windows) {
+      system_dir.join("faketool.exe")
+    } else {
+      system_dir.join("faketool")
+    };
+    fake_binary.write("");
+
+    let original_path = env::var_os("PATH");
+    env::set_var("PATH", system_dir.as_path());
+    let install = |name: &str, no_shadow: bool| {
+      let install_flags_global = InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some(name.to_string()),
+        root: Some(temp_dir.path().to_string()),
+        no_shadow,
+        ..Default::default()
+      };
+      async move {
+        create_install_shim(
+          &HttpClientProvider::new(None, None),
+          &Flags::default(),
+          install_flags_global,
+        )
+        .await
+      }
+    };
+    let rejected = install("faketool", true).await;
+    let other_name = install("othertool", true).await;
+    let allowed = install("faketool", false).await;
+    match original_path {
+      Some(path) => env::set_var("PATH", path),
+      None => env::remove_var("PATH"),
+    }
 
-    let result = create_install_shim(
-      &HttpClientProvider::new(None, None),
-      &Flags {
-        import_map_path: Some(import_map_path.to_string()),
-        ..Flags::default()
-      },
-      InstallFlagsGlobal {
-        module_url: "http://localhost:4545/cat.ts".to_string(),
-        args: vec![],
+    let err = rejected.unwrap_err();
+    assert_eq!(
+      err.downcast_ref::<InstallError>().unwrap().kind,
+      InstallErrorKind::InvalidName
+    );
+    assert!(err.to_string().contains("--allow-shadow"), "{err}");
+    assert!(other_name.is_ok());
+    assert!(allowed.is_ok());
+
+    // --allow-shadow takes precedence over the install.json default
+    let defaults_path = temp_dir.path().join("install.json");
+    defaults_path.write(r#"{ "noShadow": true }"#);
+    let apply = |allow_shadow: bool| {
+      apply_install_defaults(
+        InstallFlagsGlobal {
+          module_url: "http://localhost:4545/echo_server.ts".to_string(),
+          allow_shadow,
+          ..Default::default()
+        },
+        defaults_path.as_path(),
+      )
+      .unwrap()
+    };
+    assert!(apply(false).no_shadow);
+    assert!(!apply(true).no_shadow);
+  }
+
+  #[tokio::test]
+  async fn install_template_vars() {
+    let temp_dir = TempDir::new();
+    let template_path = temp_dir.path().join("wrapper.sh");
+    template_path.write(
+      "#!/bin/sh\n# {{MARKER}}\n# {{ORG}} tool {{NAME}}\nLOG_PATH={{LOG_PATH}} exec deno {{DENO_ARGS}} \"$@\"\n",
+    );
+    let resolve = |template_vars: Vec<String>| {
+      let install_flags_global = InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
         name: Some("echo_test".to_string()),
         root: Some(temp_dir.path().to_string()),
-        force: true,
-      },
-    )
-    .await;
-    assert!(result.is_ok());
-
-    let mut file_path = bin_dir.join("echo_test");
-    if cfg!(windows) {
-      file_path = file_path.with_extension("cmd");
-    }
-    assert!(file_path.exists());
+        template: Some(template_path.to_string()),
+        template_vars,
+        output_format: InstallOutputFormat::Sh,
+        ..Default::default()
+      };
+      async move {
+        resolve_shim_data(
+          &HttpClientProvider::new(None, None),
+          &Flags::default(),
+          &install_flags_global,
+        )
+        .await
+      }
+    };
 
-    let mut expected_string = format!(
-      "--import-map '{import_map_url}' --no-config 'http://localhost:4545/cat.ts'"
+    let shim_data = resolve(vec![
+      "ORG=acme".to_string(),
+      "LOG_PATH=/var/log/echo.log".to_string(),
+    ])
+    .await
+    .unwrap();
+    let sh_path = shim_data.file_path.with_extension("");
+    let files = render_executable_files(&shim_data, &shim_data.file_path);
+    let (_, script) = files.iter().find(|(path, _)| *path == sh_path).unwrap();
+    assert!(
+      script.starts_with("#!/bin/sh\n# deno-install v1\n"),
+      "{script}"
     );
-    if cfg!(windows) {
-      expected_string = format!(
-        "\"--import-map\" \"{import_map_url}\" \"--no-config\" \"http://localhost:4545/cat.ts\""
-      );
-    }
+    assert!(script.contains("# acme tool echo_test\n"), "{script}");
+    assert!(
+      script.contains("LOG_PATH=/var/log/echo.log exec deno "),
+      "{script}"
+    );
+    assert!(
+      script.contains("http://localhost:4545/echo_server.ts"),
+      "{script}"
+    );
+    assert!(!script.contains("{{"), "{script}");
+    assert_eq!(shim_data.metadata.template, Some(template_path.to_string()));
 
-    let content = fs::read_to_string(file_path).unwrap();
-    assert!(content.contains(&expected_string));
+    let err = resolve(vec!["ORG=acme".to_string()]).await.unwrap_err();
+    assert!(err.to_string().contains("{{LOG_PATH}}"), "{err}");
   }
 
-  // Regression test for https://github.com/denoland/deno/issues/10556.
   #[tokio::test]
-  async fn install_file_url() {
+  async fn install_template_preamble_and_env() {
     let temp_dir = TempDir::new();
-    let bin_dir = temp_dir.path().join("bin");
-    let module_path = fs::canonicalize(testdata_path().join("cat.ts")).unwrap();
-    let file_module_string =
-      Url::from_file_path(module_path).unwrap().to_string();
-    assert!(file_module_string.starts_with("file:///"));
-
-    let result = create_install_shim(
-      &HttpClientProvider::new(None, None),
-      &Flags::default(),
-      InstallFlagsGlobal {
-        module_url: file_module_string.to_string(),
-        args: vec![],
+    let bare_template = temp_dir.path().join("bare.sh");
+    bare_template
+      .write("#!/bin/sh\n# {{MARKER}}\nexec deno {{DENO_ARGS}} \"$@\"\n");
+    let full_template = temp_dir.path().join("full.sh");
+    full_template.write(
+      "#!/bin/sh\n# {{MARKER}}\n{{PREAMBLE}}{{ENV}}exec deno {{DENO_ARGS}} \"$@\"\n",
+    );
+    let resolve = |template: &Path, flags: InstallFlagsGlobal| {
+      let install_flags_global = InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
         name: Some("echo_test".to_string()),
         root: Some(temp_dir.path().to_string()),
-        force: true,
+        template: Some(template.to_string_lossy().to_string()),
+        output_format: InstallOutputFormat::Sh,
+        ..flags
+      };
+      async move {
+        resolve_shim_data(
+          &HttpClientProvider::new(None, None),
+          &Flags::default(),
+          &install_flags_global,
+        )
+        .await
+      }
+    };
+    let render = |shim_data: &ShimData| {
+      let sh_path = shim_data.file_path.with_extension("");
+      render_executable_files(shim_data, &shim_data.file_path)
+        .into_iter()
+        .find(|(path, _)| *path == sh_path)
+        .unwrap()
+        .1
+    };
+    let pre_run = || InstallFlagsGlobal {
+      pre_run: Some("echo starting".to_string()),
+      ..Default::default()
+    };
+    let min_deno_version = || InstallFlagsGlobal {
+      min_deno_version: Some("1.0.0".to_string()),
+      ..Default::default()
+    };
+    let working_dir = || InstallFlagsGlobal {
+      cwd: Some(temp_dir.path().to_string()),
+      ..Default::default()
+    };
+    let grants = || InstallFlagsGlobal {
+      grant_prompt_once: true,
+      ..Default::default()
+    };
+    let env = || InstallFlagsGlobal {
+      env: vec!["LOG_LEVEL=debug".to_string()],
+      ..Default::default()
+    };
+
+    // flags that add to the preamble need {{PREAMBLE}}
+    for flags in [pre_run(), min_deno_version(), working_dir(), grants()] {
+      let err = resolve(bare_template.as_path(), flags).await.unwrap_err();
+      assert!(err.to_string().contains("{{PREAMBLE}}"), "{err}");
+    }
+    let err = resolve(bare_template.as_path(), env()).await.unwrap_err();
+    assert!(err.to_string().contains("{{ENV}}"), "{err}");
+
+    let script =
+      render(&resolve(full_template.as_path(), pre_run()).await.unwrap());
+    assert!(script.contains("\necho starting\nexec deno "), "{script}");
+    let script = render(
+      &resolve(full_template.as_path(), min_deno_version())
+        .await
+        .unwrap(),
+    );
+    assert!(script.contains("1.0.0"), "{script}");
+    let script = render(
+      &resolve(full_template.as_path(), working_dir())
+        .await
+        .unwrap(),
+    );
+    assert!(script.contains("cd "), "{script}");
+    let script =
+      render(&resolve(full_template.as_path(), grants()).await.unwrap());
+    assert!(script.contains("done < "), "{script}");
+    assert!(script.contains("$grants"), "{script}");
+    let script =
+      render(&resolve(full_template.as_path(), env()).await.unwrap());
+    assert!(script.contains("LOG_LEVEL='debug' exec deno "), "{script}");
+    assert!(!script.contains("{{"), "{script}");
+
+    // without preamble or env, the placeholders render empty
+    let script = render(
+      &resolve(full_template.as_path(), Default::default())
+        .await
+        .unwrap(),
+    );
+    assert!(script.contains("# deno-install v1\nexec deno "), "{script}");
+
+    // the .cmd file can't be rendered from the template
+    let result = resolve(
+      bare_template.as_path(),
+      InstallFlagsGlobal {
+        output_format: InstallOutputFormat::All,
+        ..Default::default()
       },
     )
     .await;
-    assert!(result.is_ok());
-
-    let mut file_path = bin_dir.join("echo_test");
-    if cfg!(windows) {
-      file_path = file_path.with_extension("cmd");
-    }
-    assert!(file_path.exists());
-
-    let mut expected_string =
-      format!("run --no-config '{}'", &file_module_string);
     if cfg!(windows) {
-      expected_string =
-        format!("\"run\" \"--no-config\" \"{}\"", &file_module_string);
+      assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("--output-format sh"));
+    } else {
+      assert!(result.is_ok());
     }
-
-    let content = fs::read_to_string(file_path).unwrap();
-    assert!(content.contains(&expected_string));
   }
 
-  #[test]
-  fn uninstall_basic() {
+  #[tokio::test]
+  async fn batch_install_prints_path_hint_once() {
     let temp_dir = TempDir::new();
     let bin_dir = temp_dir.path().join("bin");
-    std::fs::create_dir(&bin_dir).unwrap();
-
-    let mut file_path = bin_dir.join("echo_test");
-    File::create(&file_path).unwrap();
-    if cfg!(windows) {
-      file_path = file_path.with_extension("cmd");
-      File::create(&file_path).unwrap();
-    }
-
-    // create extra files
-    {
-      let file_path = file_path.with_extension("deno.json");
-      File::create(file_path).unwrap();
-    }
-    {
-      // legacy tsconfig.json, make sure it's cleaned up for now
-      let file_path = file_path.with_extension("tsconfig.json");
-      File::create(file_path).unwrap();
-    }
-    {
-      let file_path = file_path.with_extension("lock.json");
-      File::create(file_path).unwrap();
-    }
-
-    uninstall(UninstallFlags {
-      kind: UninstallKind::Global(UninstallFlagsGlobal {
-        name: "echo_test".to_string(),
+    let entries = ["tool_a", "tool_b", "tool_c"]
+      .into_iter()
+      .map(|name| InstallFlagsGlobal {
+        module_url: "http://localhost:4545/echo_server.ts".to_string(),
+        name: Some(name.to_string()),
         root: Some(temp_dir.path().to_string()),
-      }),
-      global: false,
-    })
-    .unwrap();
-
-    assert!(!file_path.exists());
-    assert!(!file_path.with_extension("tsconfig.json").exists());
-    assert!(!file_path.with_extension("deno.json").exists());
-    assert!(!file_path.with_extension("lock.json").exists());
+        ..Default::default()
+      })
+      .collect();
+    let installed = create_install_shims(
+      &HttpClientProvider::new(None, None),
+      &Flags::default(),
+      entries,
+      None,
+    )
+    .await
+    .into_iter()
+    .map(|result| result.unwrap())
+    .collect::<Vec<_>>();
 
-    if cfg!(windows) {
-      file_path = file_path.with_extension("cmd");
-      assert!(!file_path.exists());
+    // the temporary directory is not on the PATH
+    for script in &installed {
+      assert_eq!(script.path_hints.len(), 1);
     }
+    let hints = batch_path_hints(&installed);
+    assert_eq!(hints.len(), 1);
+    let bin_dir = canonicalize_path(bin_dir.as_path()).unwrap();
+    assert!(
+      hints[0].contains(&format!("Add {} to PATH", bin_dir.display())),
+      "{}",
+      hints[0]
+    );
   }
 }
@@ -25,6 +25,7 @@ fn install_basic() {
       ("HOME", temp_dir_str.as_str()),
       ("USERPROFILE", temp_dir_str.as_str()),
       ("DENO_INSTALL_ROOT", ""),
+      ("XDG_DATA_HOME", ""),
     ])
     .run();
 
@@ -70,6 +71,7 @@ fn install_basic() {
       ("HOME", temp_dir_str.as_str()),
       ("USERPROFILE", temp_dir_str.as_str()),
       ("DENO_INSTALL_ROOT", ""),
+      ("XDG_DATA_HOME", ""),
     ])
     .run()
     .skip_output_check()
@@ -102,6 +104,7 @@ fn install_basic_global() {
       ("HOME", temp_dir_str.as_str()),
       ("USERPROFILE", temp_dir_str.as_str()),
       ("DENO_INSTALL_ROOT", ""),
+      ("XDG_DATA_HOME", ""),
     ])
     .run();
 
@@ -147,6 +150,7 @@ fn install_basic_global() {
       ("HOME", temp_dir_str.as_str()),
       ("USERPROFILE", temp_dir_str.as_str()),
       ("DENO_INSTALL_ROOT", ""),
+      ("XDG_DATA_HOME", ""),
     ])
     .run()
     .skip_output_check()
@@ -221,6 +225,7 @@ fn installer_test_local_module_run() {
       ("HOME", temp_dir_str.as_str()),
       ("USERPROFILE", temp_dir_str.as_str()),
       ("DENO_INSTALL_ROOT", ""),
+      ("XDG_DATA_HOME", ""),
     ])
     .run()
     .skip_output_check()
@@ -301,6 +306,7 @@ fn check_local_by_default() {
       ("HOME", temp_dir_str.as_str()),
       ("USERPROFILE", temp_dir_str.as_str()),
       ("DENO_INSTALL_ROOT", ""),
+      ("XDG_DATA_HOME", ""),
     ])
     .run()
     .skip_output_check()
@@ -326,8 +332,41 @@ fn check_local_by_default2() {
       ("NO_COLOR", "1"),
       ("USERPROFILE", temp_dir_str.as_str()),
       ("DENO_INSTALL_ROOT", ""),
+      ("XDG_DATA_HOME", ""),
     ])
     .run()
     .skip_output_check()
     .assert_exit_code(0);
 }
+
+#[cfg(not(windows))]
+#[test]
+fn install_shebang_env_runs_main_module_guard() {
+  let context = TestContextBuilder::new().use_temp_cwd().build();
+  let temp_dir = context.temp_dir();
+  temp_dir.write(
+    "guarded.ts",
+    "if (import.meta.main) console.log(`main ran: ${Deno.args.join(' ')}`);",
+  );
+  let output = context
+    .new_command()
+    .args("install -g --shebang-env --root ./root --name guarded guarded.ts")
+    .env("NO_COLOR", "1")
+    .run();
+  output.assert_exit_code(0);
+  assert_contains!(
+    output.combined_output(),
+    "Writing a shell script for guarded instead of a --shebang-env script"
+  );
+
+  let bin_file_path = temp_dir.path().join("root").join("bin").join("guarded");
+  assert!(bin_file_path.read_to_string().starts_with("#!/bin/sh\n"));
+  let output = context
+    .new_command()
+    .name(&bin_file_path)
+    .args("foo")
+    .env("PATH", util::target_dir())
+    .run();
+  output.assert_matches_text("main ran: foo\n");
+  output.assert_exit_code(0);
+}